@@ -0,0 +1,63 @@
+// ===================================
+// TPE Simulator - test payments without a terminal on the desk
+// ===================================
+//
+// Built with the `simulator` feature: cargo run --features simulator --bin tpe-simulator -- concert
+//
+// Usage:
+//   tpe-simulator concert [OUTCOME...]             Concert V2/V3 on a pseudo-terminal (Linux/macOS)
+//   tpe-simulator caisse-ap [ADDRESS] [OUTCOME...] Caisse-AP IP on ADDRESS (default 127.0.0.1:8888)
+//
+// OUTCOME: approved | refused:CODE | timeout | nak | garbage | slow-ack:MS
+// Outcomes are played in order, one per transaction, then every payment is approved.
+// Configure the printed port or address as the TPE port in the settings.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tauri_app_lib::tpe_simulator::{SimOutcome, TpeSimulator};
+
+const USAGE: &str = "Usage: tpe-simulator concert|caisse-ap [ADDRESS] [approved|refused:CODE|timeout|nak|garbage|slow-ack:MS ...]";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{}\n{}", e, USAGE);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (mode, rest) = args.split_first().ok_or("Missing terminal type")?;
+    
+    let (address, outcomes) = match rest.first() {
+        Some(first) if first.parse::<SocketAddr>().is_ok() => (first.as_str(), &rest[1..]),
+        _ => ("127.0.0.1:8888", rest),
+    };
+    let outcomes = outcomes
+        .iter()
+        .map(|o| o.parse::<SimOutcome>())
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let simulator = match mode.as_str() {
+        "concert" => concert()?,
+        "caisse-ap" => TpeSimulator::caisse_ap(address)?,
+        other => return Err(format!("Unknown terminal type '{}'", other)),
+    };
+    simulator.script(outcomes);
+    
+    println!("TPE port: {}  (Ctrl+C to stop)", simulator.address());
+    loop {
+        std::thread::sleep(Duration::from_secs(3600));
+    }
+}
+
+#[cfg(unix)]
+fn concert() -> Result<TpeSimulator, String> {
+    TpeSimulator::concert()
+}
+
+#[cfg(not(unix))]
+fn concert() -> Result<TpeSimulator, String> {
+    Err("Concert simulation needs a pseudo-terminal (Linux/macOS); use caisse-ap".to_string())
+}
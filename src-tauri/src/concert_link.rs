@@ -0,0 +1,332 @@
+// ===================================
+// Concert Link Layer - ENQ/ACK, retransmission and LRC checks
+// ===================================
+//
+// Emission (POS -> TPE):   ENQ >  < ACK   STX..ETX LRC >  < ACK (NAK: resend, max 3)   EOT >
+// Reception (TPE -> POS):  < ENQ  ACK >   < STX..ETX LRC  ACK > (bad LRC: NAK, max 3)   < EOT
+//
+// The LRC is the XOR of every byte after STX, ETX included.
+
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tokio::time::Instant;
+
+use crate::protocols::{bytes_to_hex, calculate_lrc};
+use crate::tpe_error::TpeError;
+use crate::tpe_timeouts::TpeTimeouts;
+use crate::tpe::{log_to_file, send_bytes, send_cancel_sequence, CancelToken, ProgressReporter, ResponseRead};
+use crate::tpe::{ACK, ENQ, EOT, ETX, NAK, STX};
+
+/// Retransmissions allowed after a NAK (or a bad LRC on our side)
+const MAX_RETRANSMISSIONS: usize = 3;
+
+/// Attempts at establishing the link with ENQ
+const MAX_ENQ_ATTEMPTS: usize = 3;
+
+/// Answer of the terminal to our request frame
+#[derive(Debug, PartialEq)]
+pub(crate) enum SendOutcome {
+    Acked,
+    Rejected(u8), // NAK after every retransmission, EOT or ENQ
+    NoAnswer,
+    NotSent(TpeError), // The frame could not be written: the terminal never took it
+}
+
+/// Read whatever arrives before the deadline (None once it has passed)
+async fn read_until<S: AsyncRead + Unpin + ?Sized>(
+    stream: &mut S,
+    buf: &mut [u8],
+    deadline: Instant,
+) -> Result<Option<usize>, TpeError> {
+    match tokio::time::timeout_at(deadline, stream.read(buf)).await {
+        Err(_) => Ok(None),
+        Ok(Ok(0)) => {
+            log_to_file("Connection closed by TPE");
+            Err(TpeError::ConnectionLost { reason: "connexion fermée par le TPE".to_string() })
+        }
+        Ok(Ok(n)) => Ok(Some(n)),
+        Ok(Err(e)) => {
+            log_to_file(&format!("Read error: {}", e));
+            Err(TpeError::ConnectionLost { reason: e.to_string() })
+        }
+    }
+}
+
+/// Wait for a single control byte, ignoring line noise
+async fn wait_control<S: AsyncRead + Unpin + ?Sized>(stream: &mut S, timeout: Duration) -> Result<Option<u8>, TpeError> {
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 1];
+    
+    while read_until(stream, &mut buf, deadline).await?.is_some() {
+        if matches!(buf[0], ACK | NAK | ENQ | EOT) {
+            return Ok(Some(buf[0]));
+        }
+    }
+    
+    Ok(None)
+}
+
+async fn write_control<S: AsyncWrite + Unpin + ?Sized>(stream: &mut S, byte: u8, timeouts: &TpeTimeouts) {
+    let _ = send_bytes(stream, &[byte], timeouts.write()).await;
+}
+
+/// Establish the link: ENQ until the terminal answers ACK, the ACK limit after each ENQ
+pub(crate) async fn establish<S: AsyncRead + AsyncWrite + Unpin + ?Sized>(stream: &mut S, timeouts: &TpeTimeouts) -> Result<(), TpeError> {
+    for attempt in 1..=MAX_ENQ_ATTEMPTS {
+        send_bytes(stream, &[ENQ], timeouts.write()).await?;
+        
+        match wait_control(stream, timeouts.ack()).await? {
+            Some(ACK) => {
+                log_to_file("Handshake OK (ACK received)");
+                return Ok(());
+            }
+            // Some terminals answer ENQ with ENQ: acknowledge and carry on
+            Some(ENQ) => {
+                log_to_file("TPE sent ENQ, replying with ACK");
+                write_control(stream, ACK, timeouts).await;
+                return Ok(());
+            }
+            reply => {
+                log_to_file(&format!("Handshake attempt {}/{}: no ACK ({:?})", attempt, MAX_ENQ_ATTEMPTS, reply));
+            }
+        }
+    }
+    
+    // No ACK after any ENQ
+    Err(TpeError::Timeout { seconds: (timeouts.ack() * MAX_ENQ_ATTEMPTS as u32).as_secs_f64() })
+}
+
+/// Send a framed request, retransmitting on NAK, then release the line with EOT.
+/// A missing answer is not retransmitted: the terminal may already be processing it.
+pub(crate) async fn send_frame<S: AsyncRead + AsyncWrite + Unpin + ?Sized>(
+    stream: &mut S,
+    frame: &[u8],
+    timeouts: &TpeTimeouts,
+) -> Result<SendOutcome, TpeError> {
+    for attempt in 0..=MAX_RETRANSMISSIONS {
+        if attempt > 0 {
+            log_to_file(&format!("NAK received, retransmitting ({}/{})", attempt, MAX_RETRANSMISSIONS));
+        }
+        // Earlier copies were NAKed: a failed write leaves no request on the terminal
+        if let Err(e) = send_bytes(stream, frame, timeouts.write()).await {
+            return Ok(SendOutcome::NotSent(e));
+        }
+        
+        match wait_control(stream, timeouts.ack()).await? {
+            Some(ACK) => {
+                write_control(stream, EOT, timeouts).await;
+                return Ok(SendOutcome::Acked);
+            }
+            Some(NAK) => continue,
+            Some(other) => return Ok(SendOutcome::Rejected(other)),
+            None => return Ok(SendOutcome::NoAnswer),
+        }
+    }
+    
+    Ok(SendOutcome::Rejected(NAK))
+}
+
+/// Receive the terminal's answer frame: ACK its ENQ, check the LRC (NAK and wait for
+/// a retransmission on mismatch), ACK the frame and wait for the closing EOT.
+/// An EOT before any frame means the terminal aborted; it is returned as data.
+pub(crate) async fn receive_frame<S: AsyncRead + AsyncWrite + Unpin + ?Sized>(
+    stream: &mut S,
+    timeout: Duration,
+    timeouts: &TpeTimeouts,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<ResponseRead, TpeError> {
+    let deadline = Instant::now() + timeout;
+    let mut buf = [0u8; 256];
+    let mut frame: Vec<u8> = Vec::new();
+    let mut bad_lrc_count = 0;
+    
+    loop {
+        let read = tokio::select! {
+            res = read_until(stream, &mut buf, deadline) => Some(res?),
+            _ = cancel.cancelled() => None,
+        };
+        let Some(read) = read else {
+            send_cancel_sequence(stream, timeouts.write()).await;
+            return Ok(ResponseRead::Cancelled);
+        };
+        let Some(n) = read else {
+            return Ok(ResponseRead::TimedOut);
+        };
+        let chunk = &buf[..n];
+        progress.frame(chunk);
+        
+        for (i, &byte) in chunk.iter().enumerate() {
+            // Waiting for STX: only control characters matter
+            if frame.is_empty() {
+                match byte {
+                    ENQ => {
+                        log_to_file("TPE sent ENQ, replying with ACK");
+                        write_control(stream, ACK, timeouts).await;
+                    }
+                    STX => frame.push(STX),
+                    EOT => {
+                        log_to_file("Terminal sent EOT (Abort/End) without data");
+                        return Ok(ResponseRead::Data(vec![EOT]));
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            
+            // LRC byte right after ETX
+            if frame.last() == Some(&ETX) {
+                let expected = calculate_lrc(&frame[1..]);
+                frame.push(byte);
+                
+                if byte == expected {
+                    write_control(stream, ACK, timeouts).await;
+                    log_to_file("End of response message detected (ETX, LRC OK)");
+                    if !chunk[i + 1..].contains(&EOT) {
+                        wait_eot(stream, timeouts.eot()).await;
+                    }
+                    return Ok(ResponseRead::Data(frame));
+                }
+                
+                bad_lrc_count += 1;
+                log_to_file(&format!(
+                    "Bad LRC (got {:02X}, expected {:02X}) on {}",
+                    byte, expected, bytes_to_hex(&frame)
+                ));
+                if bad_lrc_count > MAX_RETRANSMISSIONS {
+                    write_control(stream, EOT, timeouts).await;
+                    return Err(TpeError::Protocol {
+                        reason: format!("trame corrompue (LRC invalide après {} retransmissions)", MAX_RETRANSMISSIONS),
+                    });
+                }
+                write_control(stream, NAK, timeouts).await;
+                frame.clear();
+                continue;
+            }
+            
+            frame.push(byte);
+        }
+    }
+}
+
+/// Consume the terminal's EOT closing its emission (missing EOT is harmless)
+async fn wait_eot<S: AsyncRead + Unpin + ?Sized>(stream: &mut S, timeout: Duration) {
+    match wait_control(stream, timeout).await {
+        Ok(Some(EOT)) => {}
+        other => log_to_file(&format!("No EOT after response frame ({:?})", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::frame_message;
+    use tokio::io::{duplex, AsyncWriteExt, DuplexStream};
+    
+    /// Short ACK limit so the give-up tests stay fast
+    fn timeouts() -> TpeTimeouts {
+        TpeTimeouts { ack_ms: 100, ..TpeTimeouts::default() }
+    }
+    
+    /// Link with the terminal's bytes already waiting on the line
+    async fn link(from_terminal: &[u8]) -> (DuplexStream, DuplexStream) {
+        let (pos, mut terminal) = duplex(1024);
+        terminal.write_all(from_terminal).await.unwrap();
+        (pos, terminal)
+    }
+    
+    /// Everything the POS sent, once it hung up
+    async fn sent_by_pos(pos: DuplexStream, mut terminal: DuplexStream) -> Vec<u8> {
+        drop(pos);
+        let mut sent = Vec::new();
+        terminal.read_to_end(&mut sent).await.unwrap();
+        sent
+    }
+    
+    fn corrupted(data: &str) -> Vec<u8> {
+        let mut frame = frame_message(data);
+        *frame.last_mut().unwrap() ^= 0x01;
+        frame
+    }
+    
+    async fn receive(pos: &mut DuplexStream) -> Result<ResponseRead, TpeError> {
+        receive_frame(pos, Duration::from_secs(2), &timeouts(), &CancelToken::new(), &ProgressReporter::silent()).await
+    }
+    
+    #[tokio::test]
+    async fn establish_acknowledges_an_enq_answer() {
+        let (mut pos, terminal) = link(&[ENQ]).await;
+        
+        establish(&mut pos, &timeouts()).await.unwrap();
+        
+        assert_eq!(sent_by_pos(pos, terminal).await, vec![ENQ, ACK]);
+    }
+    
+    #[tokio::test]
+    async fn establish_gives_up_without_ack() {
+        let (mut pos, terminal) = link(&[]).await;
+        
+        let err = establish(&mut pos, &timeouts()).await.unwrap_err();
+        
+        assert_eq!(err, TpeError::Timeout { seconds: 0.3 });
+        assert_eq!(sent_by_pos(pos, terminal).await, vec![ENQ; MAX_ENQ_ATTEMPTS]);
+    }
+    
+    #[tokio::test]
+    async fn send_frame_retransmits_on_nak() {
+        let frame = frame_message("0100000500978");
+        let (mut pos, terminal) = link(&[NAK, ACK]).await;
+        
+        let outcome = send_frame(&mut pos, &frame, &timeouts()).await.unwrap();
+        
+        assert_eq!(outcome, SendOutcome::Acked);
+        assert_eq!(sent_by_pos(pos, terminal).await, [frame.clone(), frame, vec![EOT]].concat());
+    }
+    
+    #[tokio::test]
+    async fn send_frame_gives_up_after_max_retransmissions() {
+        let frame = frame_message("0100000500978");
+        let (mut pos, terminal) = link(&[NAK; MAX_RETRANSMISSIONS + 1]).await;
+        
+        let outcome = send_frame(&mut pos, &frame, &timeouts()).await.unwrap();
+        
+        assert_eq!(outcome, SendOutcome::Rejected(NAK));
+        assert_eq!(sent_by_pos(pos, terminal).await, frame.repeat(MAX_RETRANSMISSIONS + 1));
+    }
+    
+    #[tokio::test]
+    async fn receive_frame_naks_a_bad_lrc() {
+        let answer = frame_message("0100000500978");
+        let (mut pos, terminal) = link(&[vec![ENQ], corrupted("0100000500978"), answer.clone(), vec![EOT]].concat()).await;
+        
+        let Ok(ResponseRead::Data(data)) = receive(&mut pos).await else {
+            panic!("no answer frame");
+        };
+        
+        assert_eq!(data, answer);
+        assert_eq!(sent_by_pos(pos, terminal).await, vec![ACK, NAK, ACK]);
+    }
+    
+    #[tokio::test]
+    async fn receive_frame_gives_up_after_max_bad_lrcs() {
+        let (mut pos, terminal) = link(&corrupted("0100000500978").repeat(MAX_RETRANSMISSIONS + 1)).await;
+        
+        let err = receive(&mut pos).await.err().unwrap();
+        
+        assert_eq!(err.code(), "Protocol");
+        assert_eq!(sent_by_pos(pos, terminal).await, [vec![NAK; MAX_RETRANSMISSIONS], vec![EOT]].concat());
+    }
+    
+    #[tokio::test]
+    async fn receive_frame_tolerates_a_missing_eot() {
+        let answer = frame_message("0100000500978");
+        let (mut pos, terminal) = link(&answer).await;
+        
+        let Ok(ResponseRead::Data(data)) = receive(&mut pos).await else {
+            panic!("no answer frame");
+        };
+        
+        assert_eq!(data, answer);
+        assert_eq!(sent_by_pos(pos, terminal).await, vec![ACK]);
+    }
+}
@@ -0,0 +1,188 @@
+// ===================================
+// Currency - ISO 4217 settings for terminals and printers
+// ===================================
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Currency {
+    pub numeric: String, // ISO 4217 numeric code, sent to the terminal (CE tag)
+    pub alpha: String,   // ISO 4217 alpha code (Yavin payloads)
+    pub symbol: String,  // Shown on receipts
+    pub minor_units: u8, // Digits after the decimal point (2 for EUR, 0 for XPF)
+    #[serde(default)]
+    pub ascii_symbol: Option<String>, // For printers without the symbol in their code page
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::known("EUR").expect("EUR is a known currency")
+    }
+}
+
+/// Most decimals ISO 4217 uses (3 for TND, KWD...)
+const MAX_MINOR_UNITS: u8 = 3;
+
+/// numeric, alpha, symbol, minor units, ASCII symbol
+const KNOWN_CURRENCIES: &[(&str, &str, &str, u8, &str)] = &[
+    ("978", "EUR", "€", 2, "E"),
+    ("756", "CHF", "CHF", 2, "CHF"),
+    ("826", "GBP", "£", 2, "GBP"),
+    ("840", "USD", "$", 2, "$"),
+    ("124", "CAD", "$", 2, "CAD"),
+    ("953", "XPF", "F", 0, "F"),
+    ("952", "XOF", "F", 0, "FCFA"),
+];
+
+impl Currency {
+    /// Look a currency up by alpha or numeric code
+    pub fn known(code: &str) -> Option<Currency> {
+        let code = code.trim().to_uppercase();
+        KNOWN_CURRENCIES
+            .iter()
+            .find(|(numeric, alpha, _, _, _)| *numeric == code || *alpha == code)
+            .map(|(numeric, alpha, symbol, minor_units, ascii)| Currency {
+                numeric: numeric.to_string(),
+                alpha: alpha.to_string(),
+                symbol: symbol.to_string(),
+                minor_units: *minor_units,
+                ascii_symbol: Some(ascii.to_string()),
+            })
+    }
+    
+    pub fn validate(&self) -> Result<(), String> {
+        if self.numeric.len() != 3 || !self.numeric.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("Code devise numérique invalide: '{}'", self.numeric));
+        }
+        if self.alpha.len() != 3 || !self.alpha.chars().all(|c| c.is_ascii_uppercase()) {
+            return Err(format!("Code devise alphabétique invalide: '{}'", self.alpha));
+        }
+        if self.minor_units > MAX_MINOR_UNITS {
+            return Err(format!("{}: nombre de décimales invalide ({})", self.alpha, self.minor_units));
+        }
+        Ok(())
+    }
+    
+    /// Does a currency reported by the terminal (numeric or alpha) designate this one?
+    pub fn matches(&self, code: &str) -> bool {
+        let code = code.trim();
+        code == self.numeric || code.eq_ignore_ascii_case(&self.alpha)
+    }
+    
+    /// Amount in major units ("12.50") from an amount in minor units, in integer
+    /// arithmetic so large amounts never pick up float rounding
+    pub fn format_minor(&self, amount_minor: impl Into<i64>) -> String {
+        let amount_minor: i64 = amount_minor.into();
+        let sign = if amount_minor < 0 { "-" } else { "" };
+        let magnitude = amount_minor.unsigned_abs();
+        let units = self.minor_units.min(MAX_MINOR_UNITS) as u32;
+        if units == 0 {
+            return format!("{}{}", sign, magnitude);
+        }
+        let factor = 10u64.pow(units);
+        format!("{}{}.{:0width$}", sign, magnitude / factor, magnitude % factor, width = units as usize)
+    }
+    
+    /// Amount in minor units, rounded half up like the till's `toMinorUnits`
+    /// so a receipt always shows the amount sent to the terminal
+    pub fn to_minor(&self, amount: f64) -> i64 {
+        let factor = 10f64.powi(self.minor_units.min(MAX_MINOR_UNITS) as i32);
+        (amount * factor + 0.5).floor() as i64
+    }
+    
+    pub fn format_major(&self, amount: f64) -> String {
+        self.format_minor(self.to_minor(amount))
+    }
+    
+    /// Symbol safe for any printer code page
+    pub fn printable_symbol(&self) -> &str {
+        match &self.ascii_symbol {
+            Some(s) if !s.is_empty() => s,
+            _ if self.symbol.is_ascii() => &self.symbol,
+            _ => &self.alpha,
+        }
+    }
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+/// Currencies offered in the terminal and printer settings
+#[tauri::command]
+pub fn list_currencies() -> Vec<Currency> {
+    KNOWN_CURRENCIES
+        .iter()
+        .filter_map(|(_, alpha, _, _, _)| Currency::known(alpha))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn with_decimals(minor_units: u8) -> Currency {
+        Currency {
+            numeric: "788".to_string(),
+            alpha: "TND".to_string(),
+            symbol: "DT".to_string(),
+            minor_units,
+            ascii_symbol: None,
+        }
+    }
+    
+    #[test]
+    fn formats_two_decimal_currencies() {
+        let eur = Currency::default();
+        
+        assert_eq!(eur.format_minor(0u32), "0.00");
+        assert_eq!(eur.format_minor(5u32), "0.05");
+        assert_eq!(eur.format_minor(1250u32), "12.50");
+        assert_eq!(eur.format_minor(u32::MAX), "42949672.95");
+        assert_eq!(eur.format_minor(-250i64), "-2.50");
+    }
+    
+    #[test]
+    fn formats_currencies_without_decimals() {
+        let xpf = Currency::known("XPF").unwrap();
+        
+        assert_eq!(xpf.format_minor(1500u32), "1500");
+        assert_eq!(xpf.format_minor(-3i64), "-3");
+    }
+    
+    #[test]
+    fn formats_three_decimal_currencies() {
+        let tnd = with_decimals(3);
+        
+        assert_eq!(tnd.format_minor(1250u32), "1.250");
+        assert_eq!(tnd.format_minor(7u32), "0.007");
+        assert_eq!(tnd.format_minor(-1001i64), "-1.001");
+    }
+    
+    #[test]
+    fn receipt_amounts_match_the_terminal_amount() {
+        let eur = Currency::default();
+        let xpf = Currency::known("XPF").unwrap();
+        
+        // 0.125 EUR is sent as 13 cents: "{:.2}" would have printed 0.12
+        assert_eq!(eur.to_minor(0.125), 13);
+        assert_eq!(eur.format_major(0.125), "0.13");
+        assert_eq!(eur.format_major(2.675), eur.format_minor(eur.to_minor(2.675)));
+        assert_eq!(eur.format_major(12.5), "12.50");
+        assert_eq!(xpf.format_major(1499.6), "1500");
+    }
+    
+    #[test]
+    fn rejects_more_than_three_decimals() {
+        assert!(with_decimals(3).validate().is_ok());
+        assert!(with_decimals(4).validate().is_err());
+    }
+    
+    #[test]
+    fn finds_currencies_by_either_code() {
+        assert_eq!(Currency::known("xpf"), Currency::known("953"));
+        assert!(Currency::default().matches("978"));
+        assert!(Currency::default().matches("eur"));
+        assert!(Currency::known("ZZZ").is_none());
+    }
+}
@@ -8,6 +8,8 @@ use std::io::Write;
 use std::time::Duration;
 use printers::common::base::job::PrinterJobOptions;
 
+use crate::currency::Currency;
+
 // ===================================
 // Types
 // ===================================
@@ -25,6 +27,8 @@ pub struct PrinterConfig {
     pub port: String,
     pub baud_rate: u32,
     pub paper_width: u8, // 58mm or 80mm
+    #[serde(default)]
+    pub currency: Currency,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,6 +54,23 @@ pub struct ReceiptItem {
     pub subtotal: f64,
 }
 
+/// Copy of the card ticket returned by the payment terminal
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TicketCopy {
+    Customer,
+    Merchant,
+}
+
+impl TicketCopy {
+    fn title(&self) -> &'static str {
+        match self {
+            TicketCopy::Customer => "COPIE CLIENT",
+            TicketCopy::Merchant => "COPIE COMMERCANT",
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HardwareStatus {
     pub printer_connected: bool,
@@ -172,17 +193,18 @@ pub fn print_receipt(config: PrinterConfig, receipt: ReceiptData) -> Result<Stri
     data.push(escpos::LF);
 
     // Items
+    let currency = &config.currency;
     for item in &receipt.items {
         let item_line = format!(
-            "{} x{} @ {:.2}€",
-            item.name, item.quantity, item.unit_price
+            "{} x{} @ {}{}",
+            item.name, item.quantity, currency.format_major(item.unit_price), currency.symbol
         );
         data.extend_from_slice(item_line.as_bytes());
         data.push(escpos::LF);
 
         // Subtotal aligned right
         data.extend_from_slice(&escpos::ALIGN_RIGHT);
-        let subtotal_line = format!("{:.2}€", item.subtotal);
+        let subtotal_line = format!("{}{}", currency.format_major(item.subtotal), currency.symbol);
         data.extend_from_slice(subtotal_line.as_bytes());
         data.push(escpos::LF);
         data.extend_from_slice(&escpos::ALIGN_LEFT);
@@ -196,7 +218,7 @@ pub fn print_receipt(config: PrinterConfig, receipt: ReceiptData) -> Result<Stri
     data.extend_from_slice(&escpos::BOLD_ON);
     data.extend_from_slice(&escpos::DOUBLE_HEIGHT_ON);
     data.extend_from_slice(&escpos::ALIGN_RIGHT);
-    let total_line = format!("TOTAL: {:.2}€", receipt.total);
+    let total_line = format!("TOTAL: {}{}", currency.format_major(receipt.total), currency.symbol);
     data.extend_from_slice(total_line.as_bytes());
     data.push(escpos::LF);
     data.extend_from_slice(&escpos::NORMAL_SIZE);
@@ -233,6 +255,62 @@ pub fn print_receipt(config: PrinterConfig, receipt: ReceiptData) -> Result<Stri
     }
 }
 
+/// ESC/POS data for a card ticket returned by the terminal.
+/// Terminal tickets are laid out for its narrow roll; longer lines are wrapped.
+fn build_card_ticket(lines: &[String], copy: TicketCopy, paper_width: u8) -> Vec<u8> {
+    let width = if paper_width == 58 { 32 } else { 48 };
+    let mut data: Vec<u8> = Vec::new();
+
+    data.extend_from_slice(&escpos::INIT);
+    data.extend_from_slice(&escpos::ALIGN_CENTER);
+    data.extend_from_slice(&escpos::BOLD_ON);
+    data.extend_from_slice(copy.title().as_bytes());
+    data.extend_from_slice(&escpos::BOLD_OFF);
+    data.push(escpos::LF);
+    data.push(escpos::LF);
+
+    for line in lines {
+        let chars: Vec<char> = line.chars().collect();
+        if chars.is_empty() {
+            data.push(escpos::LF);
+        }
+        for chunk in chars.chunks(width) {
+            data.extend_from_slice(chunk.iter().collect::<String>().as_bytes());
+            data.push(escpos::LF);
+        }
+    }
+
+    data.extend_from_slice(&escpos::ALIGN_LEFT);
+    data.push(escpos::LF);
+    data.push(escpos::LF);
+    data.push(escpos::LF);
+    data.extend_from_slice(&escpos::CUT_PARTIAL);
+    data
+}
+
+/// Print the card ticket returned by the terminal on the serial receipt printer
+#[tauri::command]
+pub fn print_card_ticket(config: PrinterConfig, lines: Vec<String>, copy: TicketCopy) -> Result<String, String> {
+    if lines.is_empty() {
+        return Err("No card ticket to print".to_string());
+    }
+
+    let port = serialport::new(&config.port, config.baud_rate)
+        .timeout(Duration::from_secs(5))
+        .open();
+
+    let mut port = match port {
+        Ok(p) => p,
+        Err(e) => return Err(format!("Failed to open port: {}", e)),
+    };
+
+    let data = build_card_ticket(&lines, copy, config.paper_width);
+    match port.write_all(&data) {
+        Ok(_) => Ok("Card ticket printed successfully".to_string()),
+        Err(e) => Err(format!("Failed to print: {}", e)),
+    }
+}
+
 #[tauri::command]
 pub fn test_printer(port_name: String, baud_rate: u32) -> Result<String, String> {
     let port = serialport::new(&port_name, baud_rate)
@@ -363,7 +441,14 @@ pub fn list_system_printers() -> Result<Vec<SystemPrinterInfo>, String> {
 
 /// Print a receipt via Windows driver (RAW ESC/POS data sent to driver)
 #[tauri::command]
-pub fn print_via_driver(printer_name: String, receipt: ReceiptData, paper_width: u8) -> Result<String, String> {
+pub fn print_via_driver(
+    printer_name: String,
+    receipt: ReceiptData,
+    paper_width: u8,
+    currency: Option<Currency>,
+) -> Result<String, String> {
+    let currency = currency.unwrap_or_default();
+
     // Find the printer
     let printer = printers::get_printer_by_name(&printer_name);
     let printer = match printer {
@@ -423,7 +508,8 @@ pub fn print_via_driver(printer_name: String, receipt: ReceiptData, paper_width:
         data.extend_from_slice(&escpos::BOLD_ON);
         data.extend_from_slice(&escpos::DOUBLE_HEIGHT_ON);
         data.extend_from_slice(&escpos::ALIGN_RIGHT);
-        let total_line = format!("TOTAL: {:.2}E", receipt.total);
+        // Driver code pages rarely have the symbol (e.g. no €): print its ASCII form
+        let total_line = format!("TOTAL: {}{}", currency.format_major(receipt.total), currency.printable_symbol());
         data.extend_from_slice(total_line.as_bytes());
         data.push(escpos::LF);
         data.extend_from_slice(&escpos::NORMAL_SIZE);
@@ -458,6 +544,31 @@ pub fn print_via_driver(printer_name: String, receipt: ReceiptData, paper_width:
     }
 }
 
+/// Print the card ticket returned by the terminal via Windows driver
+#[tauri::command]
+pub fn print_card_ticket_via_driver(
+    printer_name: String,
+    lines: Vec<String>,
+    copy: TicketCopy,
+    paper_width: u8,
+) -> Result<String, String> {
+    if lines.is_empty() {
+        return Err("No card ticket to print".to_string());
+    }
+
+    let printer = printers::get_printer_by_name(&printer_name);
+    let printer = match printer {
+        Some(p) => p,
+        None => return Err(format!("Printer '{}' not found", printer_name)),
+    };
+
+    let data = build_card_ticket(&lines, copy, paper_width);
+    match printer.print(&data, PrinterJobOptions::none()) {
+        Ok(_) => Ok("Card ticket printed successfully via driver".to_string()),
+        Err(e) => Err(format!("Failed to print via driver: {:?}", e)),
+    }
+}
+
 /// Open cash drawer via Windows driver (sends ESC/POS command to the printer)
 #[tauri::command]
 pub fn open_drawer_via_driver(printer_name: String, pin: u8) -> Result<String, String> {
@@ -511,3 +622,46 @@ pub fn test_printer_driver(printer_name: String) -> Result<String, String> {
         Err(e) => Err(format!("Failed to test print via driver: {:?}", e)),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(data: &[u8]) -> String {
+        String::from_utf8_lossy(data).to_string()
+    }
+
+    #[test]
+    fn card_ticket_has_title_lines_and_cut() {
+        let lines = vec!["CARTE BANCAIRE".to_string(), String::new(), "MONTANT 12,50 EUR".to_string()];
+
+        let data = build_card_ticket(&lines, TicketCopy::Merchant, 80);
+
+        assert!(data.starts_with(&escpos::INIT));
+        assert!(data.ends_with(&escpos::CUT_PARTIAL));
+        assert!(text(&data).contains("COPIE COMMERCANT"));
+        assert!(text(&data).contains("\n\nCARTE BANCAIRE\n\nMONTANT 12,50 EUR\n"));
+    }
+
+    #[test]
+    fn card_ticket_wraps_to_the_paper_width() {
+        let line = "X".repeat(40);
+        let lines = vec![line.clone()];
+
+        let narrow = text(&build_card_ticket(&lines, TicketCopy::Customer, 58));
+        let wide = text(&build_card_ticket(&lines, TicketCopy::Customer, 80));
+
+        assert!(narrow.contains(&format!("{}\n{}\n", "X".repeat(32), "X".repeat(8))));
+        assert!(wide.contains(&format!("{}\n", line)));
+        assert!(wide.contains("COPIE CLIENT"));
+    }
+
+    #[test]
+    fn card_ticket_wraps_on_characters_not_bytes() {
+        let line = "é".repeat(33);
+
+        let data = text(&build_card_ticket(&[line], TicketCopy::Customer, 58));
+
+        assert!(data.contains(&format!("{}\né\n", "é".repeat(32))));
+    }
+}
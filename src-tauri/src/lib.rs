@@ -4,15 +4,33 @@ mod hardware;
 mod tpe;
 mod http_proxy;
 mod protocols;
+mod tlv;
+mod currency;
+mod concert_link;
+mod yavin;
+mod nepting;
+mod pending;
+mod serial_line;
+mod tpe_ids;
+mod tpe_detect;
+mod terminals;
+mod tpe_reconciliation;
+mod tpe_sessions;
+mod tpe_error;
+mod tpe_timeouts;
+#[cfg(any(test, feature = "simulator"))]
+pub mod tpe_simulator;
 
 use hardware::{
     list_serial_ports,
     print_receipt,
+    print_card_ticket,
     test_printer,
     open_cash_drawer,
     check_hardware_status,
     list_system_printers,
     print_via_driver,
+    print_card_ticket_via_driver,
     open_drawer_via_driver,
     test_printer_driver,
 };
@@ -20,11 +38,40 @@ use hardware::{
 use tpe::{
     test_tpe_connection,
     send_tpe_payment,
+    send_tpe_refund,
+    send_tpe_preauthorization,
+    complete_tpe_preauthorization,
+    cancel_tpe_preauthorization,
     cancel_tpe_transaction,
     get_tpe_logs,
     clear_tpe_logs,
 };
 
+use tpe_detect::detect_tpe_protocol;
+
+use tpe_reconciliation::run_tpe_reconciliation;
+
+use tpe_sessions::{start_tpe_monitor, get_tpe_sessions};
+
+use terminals::{
+    list_tpe_terminals,
+    add_tpe_terminal,
+    update_tpe_terminal,
+    remove_tpe_terminal,
+    set_default_tpe_terminal,
+    send_tpe_payment_by_name,
+};
+
+use yavin::find_yavin_transaction;
+
+use currency::list_currencies;
+
+use pending::{
+    list_pending_tpe_payments,
+    dismiss_pending_tpe_payment,
+    check_pending_tpe_payment,
+};
+
 use http_proxy::http_request;
 
 #[tauri::command]
@@ -58,20 +105,41 @@ pub fn run() {
             greet,
             list_serial_ports,
             print_receipt,
+            print_card_ticket,
             test_printer,
             open_cash_drawer,
             check_hardware_status,
             list_system_printers,
             print_via_driver,
+            print_card_ticket_via_driver,
             open_drawer_via_driver,
             test_printer_driver,
             shutdown_system,
             // TPE commands
             test_tpe_connection,
             send_tpe_payment,
+            send_tpe_refund,
+            send_tpe_preauthorization,
+            complete_tpe_preauthorization,
+            cancel_tpe_preauthorization,
             cancel_tpe_transaction,
             get_tpe_logs,
             clear_tpe_logs,
+            detect_tpe_protocol,
+            list_tpe_terminals,
+            add_tpe_terminal,
+            update_tpe_terminal,
+            remove_tpe_terminal,
+            set_default_tpe_terminal,
+            send_tpe_payment_by_name,
+            run_tpe_reconciliation,
+            start_tpe_monitor,
+            get_tpe_sessions,
+            find_yavin_transaction,
+            list_currencies,
+            list_pending_tpe_payments,
+            dismiss_pending_tpe_payment,
+            check_pending_tpe_payment,
             quit_app,
             // HTTP Proxy for Windows compatibility
             http_request,
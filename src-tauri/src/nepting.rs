@@ -0,0 +1,119 @@
+// ===================================
+// Nepting RC - Caisse-AP terminals answering with an RC code
+// ===================================
+//
+// These terminals take the Caisse-AP IP request but report the outcome in a
+// single RC tag ("000" = approved), often as a bare TLV body without STX/ETX
+// framing. Some firmwares only answer "OK".
+
+use crate::tlv;
+use crate::tpe::{log_to_file, TpePaymentResponse};
+
+/// Has the whole answer arrived? Unframed answers have no ETX to wait for:
+/// the answer is complete once it decodes with an RC tag.
+pub(crate) fn answer_complete(data: &[u8]) -> bool {
+    tlv::decode_frame(data).is_ok_and(|message| message.get("RC").is_some())
+}
+
+/// "000", "00" or "0"
+fn is_approval_code(code: &str) -> bool {
+    let code = code.trim();
+    !code.is_empty() && code.chars().all(|c| c == '0')
+}
+
+pub(crate) fn parse_response(data: &[u8], amount_minor: u32) -> TpePaymentResponse {
+    let response_str = String::from_utf8_lossy(data).to_string();
+    log_to_file(&format!("Nepting response: {}", response_str));
+    
+    // RC003000 = approved, RC003007 = refused with code 007
+    if let Ok(message) = tlv::decode_frame(data) {
+        if let Some(code) = message.get("RC").map(|c| c.trim().to_string()) {
+            let approved = is_approval_code(&code);
+            return TpePaymentResponse {
+                success: approved,
+                transaction_result: if approved { "0".to_string() } else { code.clone() },
+                amount_minor,
+                error_message: (!approved).then(|| format!("Paiement refusé (code Nepting {})", code)),
+                raw_response: Some(response_str),
+                ..Default::default()
+            }
+            .with_tags(message.fields());
+        }
+    }
+    
+    // No RC: only a bare "OK" counts as an approval
+    let text = response_str.trim_matches(|c: char| c.is_control() || c.is_whitespace());
+    if text == "OK" {
+        return TpePaymentResponse {
+            success: true,
+            transaction_result: "OK".to_string(),
+            amount_minor,
+            raw_response: Some(response_str),
+            ..Default::default()
+        };
+    }
+    
+    TpePaymentResponse {
+        success: false,
+        transaction_result: "?".to_string(),
+        amount_minor,
+        error_message: Some(format!("Réponse Nepting sans code RC: {}", text)),
+        raw_response: Some(response_str),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::frame_message;
+    
+    #[test]
+    fn unframed_rc_000_is_approved() {
+        let res = parse_response(b"CA00201RC003000", 1500);
+        
+        assert!(res.success);
+        assert_eq!(res.transaction_result, "0");
+        assert_eq!(res.amount_minor, 1500);
+        assert!(res.error_message.is_none());
+    }
+    
+    #[test]
+    fn short_zero_codes_are_approved() {
+        assert!(parse_response(b"RC00200", 100).success);
+        assert!(parse_response(b"RC0010", 100).success);
+    }
+    
+    #[test]
+    fn framed_answer_fills_card_details() {
+        let res = parse_response(&frame_message("RC003000AC006123456PA016497010******0119"), 990);
+        
+        assert!(res.success);
+        assert_eq!(res.authorization_number.as_deref(), Some("123456"));
+        assert_eq!(res.masked_pan.as_deref(), Some("497010******0119"));
+    }
+    
+    #[test]
+    fn non_zero_rc_is_refused_with_its_code() {
+        let res = parse_response(b"RC003007\x06\x04", 500);
+        
+        assert!(!res.success);
+        assert_eq!(res.transaction_result, "007");
+        assert!(res.error_message.unwrap().contains("007"));
+    }
+    
+    #[test]
+    fn bare_ok_is_approved_but_not_a_lookalike() {
+        assert!(parse_response(b"OK\r\n", 100).success);
+        assert!(!parse_response(b"BOOKING KO", 100).success);
+        assert!(!parse_response(b"", 100).success);
+    }
+    
+    #[test]
+    fn answer_is_complete_once_rc_is_decodable() {
+        assert!(!answer_complete(b"CA00201RC00"));
+        assert!(!answer_complete(b"CA00201"));
+        assert!(answer_complete(b"CA00201RC003000"));
+        assert!(answer_complete(&frame_message("RC003007")));
+    }
+}
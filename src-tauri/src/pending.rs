@@ -0,0 +1,273 @@
+// ===================================
+// Pending Payment Journal - Crash-safe record of in-flight TPE transactions
+// ===================================
+//
+// A record is written (and synced to disk) right before the request frame
+// leaves for the terminal, and removed once the terminal gave a definitive
+// answer. Anything left in the journal at startup is a payment whose outcome
+// we never saw: the customer may have been debited without a sale recorded.
+// Records name the terminal but hold no credentials: the Yavin API key needed
+// to query the outcome is read back from the terminal registry.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::currency::Currency;
+use crate::protocols::{TpeProtocol, TpeTransaction};
+use crate::terminals;
+use crate::tpe::{log_to_file, TpeConfig, TpePaymentResponse};
+use crate::tpe_error::TpeError;
+use crate::yavin;
+
+const JOURNAL_FILE_NAME: &str = "ma-caisse-tpe-pending.json";
+
+// Serializes read-modify-write cycles on the journal file
+static JOURNAL_LOCK: Mutex<()> = Mutex::new(());
+
+/// Terminal of a pending payment, without its credentials
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingTerminal {
+    pub name: String, // Registry name (empty for payments sent with explicit settings)
+    pub port: String,
+    pub protocol_version: u8,
+}
+
+impl From<&TpeConfig> for PendingTerminal {
+    fn from(config: &TpeConfig) -> Self {
+        PendingTerminal {
+            name: config.name.clone(),
+            port: config.port.clone(),
+            protocol_version: config.protocol_version,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PendingPayment {
+    pub payment_id: String,
+    pub started_at: String,
+    pub terminal: PendingTerminal,
+    pub transaction_type: String, // TransactionType name ("Debit", "Credit", "PreAuthorization"...)
+    #[serde(alias = "amount_cents")]
+    pub amount_minor: u32,
+    #[serde(default)]
+    pub currency: Currency, // Currency of amount_minor
+    #[serde(default)]
+    pub pos_transaction_id: Option<String>,
+    #[serde(default)]
+    pub tpe_transaction_id: Option<String>, // TI sent to the terminal
+    #[serde(default)]
+    pub terminal_reference: Option<String>, // Transaction id given back by the terminal (Yavin)
+    #[serde(default)]
+    pub last_error: Option<String>, // Set when the exchange failed after sending
+}
+
+impl PendingPayment {
+    pub fn new(payment_id: &str, terminal: &TpeConfig, tx: &TpeTransaction) -> Self {
+        PendingPayment {
+            payment_id: payment_id.to_string(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            terminal: PendingTerminal::from(terminal),
+            transaction_type: format!("{:?}", tx.tx_type),
+            amount_minor: tx.amount_minor,
+            currency: tx.currency.clone(),
+            pos_transaction_id: tx.pos_transaction_id.clone(),
+            tpe_transaction_id: tx.tpe_transaction_id.clone(),
+            terminal_reference: None,
+            last_error: None,
+        }
+    }
+}
+
+/// State file next to the app's local data (keeps Yavin keys out of Documents)
+#[cfg(not(test))]
+pub(crate) fn data_path(file_name: &str) -> PathBuf {
+    if let Some(data) = dirs::data_local_dir() {
+        let dir = data.join("ma-caisse");
+        if fs::create_dir_all(&dir).is_ok() {
+            return dir.join(file_name);
+        }
+    }
+    if let Some(home) = dirs::home_dir() {
+        return home.join(file_name);
+    }
+    PathBuf::from(file_name)
+}
+
+/// Tests run real transactions (simulator): keep their files out of the user's data
+#[cfg(test)]
+pub(crate) fn data_path(file_name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ma-caisse-tests-{}", std::process::id()));
+    let _ = fs::create_dir_all(&dir);
+    dir.join(file_name)
+}
+
+fn journal_path() -> PathBuf {
+    data_path(JOURNAL_FILE_NAME)
+}
+
+fn load(path: &PathBuf) -> Vec<PendingPayment> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+            log_to_file(&format!("Pending journal unreadable, ignoring: {}", e));
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Write through a temp file + rename so a power cut never leaves a half-written file
+pub(crate) fn write_durably(path: &PathBuf, contents: &str) -> Result<(), String> {
+    let tmp_path = path.with_extension("json.tmp");
+    
+    let mut file = File::create(&tmp_path).map_err(|e| e.to_string())?;
+    file.write_all(contents.as_bytes()).map_err(|e| e.to_string())?;
+    file.sync_all().map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
+}
+
+fn store(path: &PathBuf, entries: &[PendingPayment]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(entries).map_err(|e| e.to_string())?;
+    write_durably(path, &json)
+}
+
+fn update<F: FnOnce(&mut Vec<PendingPayment>)>(f: F) -> Result<(), String> {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = journal_path();
+    let mut entries = load(&path);
+    f(&mut entries);
+    store(&path, &entries)
+}
+
+/// Durably record a payment about to be sent to the terminal
+pub fn record(entry: PendingPayment) -> Result<(), TpeError> {
+    log_to_file(&format!("Journal: recording in-flight payment {}", entry.payment_id));
+    update(|entries| {
+        entries.retain(|e| e.payment_id != entry.payment_id);
+        entries.push(entry);
+    }).map_err(|e| TpeError::Storage { reason: format!("paiement en cours non enregistré ({})", e) })
+}
+
+/// The terminal answered: the payment no longer needs recovery
+pub fn resolve(payment_id: &str) {
+    log_to_file(&format!("Journal: payment {} resolved", payment_id));
+    if let Err(e) = update(|entries| entries.retain(|e| e.payment_id != payment_id)) {
+        log_to_file(&format!("Journal: failed to resolve {}: {}", payment_id, e));
+    }
+}
+
+/// Keep the record but remember why the exchange failed (no-op if never recorded)
+pub fn mark_failed(payment_id: &str, error: &str) {
+    let result = update(|entries| {
+        if let Some(entry) = entries.iter_mut().find(|e| e.payment_id == payment_id) {
+            entry.last_error = Some(error.to_string());
+        }
+    });
+    if let Err(e) = result {
+        log_to_file(&format!("Journal: failed to update {}: {}", payment_id, e));
+    }
+}
+
+/// Remember the terminal's own id of the transaction, to query it after a crash
+/// (no-op if never recorded)
+pub fn note_terminal_reference(payment_id: &str, reference: &str) {
+    let result = update(|entries| {
+        if let Some(entry) = entries.iter_mut().find(|e| e.payment_id == payment_id) {
+            entry.terminal_reference = Some(reference.to_string());
+        }
+    });
+    if let Err(e) = result {
+        log_to_file(&format!("Journal: failed to update {}: {}", payment_id, e));
+    }
+}
+
+fn find(payment_id: &str) -> Option<PendingPayment> {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    load(&journal_path()).into_iter().find(|e| e.payment_id == payment_id)
+}
+
+/// Registry entry of the terminal of a pending payment (holds its credentials)
+fn registered_terminal(entry: &PendingPayment) -> Result<TpeConfig, TpeError> {
+    let name = entry.terminal.name.trim();
+    if name.is_empty() {
+        return Err(TpeError::InvalidRequest {
+            reason: "paiement envoyé à un TPE non enregistré, vérifiez le journal du TPE".to_string(),
+        });
+    }
+    terminals::get(Some(name))
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+/// Payments sent to a terminal whose outcome was never received (shown at startup)
+#[tauri::command]
+pub fn list_pending_tpe_payments() -> Vec<PendingPayment> {
+    let _guard = JOURNAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    load(&journal_path())
+}
+
+/// Drop a pending payment once the cashier checked it by hand
+#[tauri::command]
+pub fn dismiss_pending_tpe_payment(payment_id: String) -> Result<(), TpeError> {
+    if find(&payment_id).is_none() {
+        return Err(TpeError::UnknownPayment { payment_id });
+    }
+    resolve(&payment_id);
+    Ok(())
+}
+
+/// Ask the terminal for the outcome of a pending payment, where the protocol allows it.
+/// Returns None when the terminal has no trace of the transaction.
+#[tauri::command]
+pub async fn check_pending_tpe_payment(payment_id: String) -> Result<Option<TpePaymentResponse>, TpeError> {
+    let entry = find(&payment_id).ok_or_else(|| TpeError::UnknownPayment { payment_id: payment_id.clone() })?;
+    let address = entry.terminal.port.trim_end_matches("+ASCII").trim().to_string();
+    let protocol = TpeProtocol::from_version(entry.terminal.protocol_version).for_transport(address.contains(':'));
+    
+    log_to_file(&format!("Journal: checking {} on {} ({})", payment_id, address, protocol.name()));
+    
+    let result = match protocol {
+        TpeProtocol::YavinCloud => {
+            let merchant_ref = entry.pos_transaction_id.clone().unwrap_or_default();
+            if merchant_ref.is_empty() {
+                return Err(TpeError::InvalidRequest { reason: "aucune référence de vente enregistrée pour ce paiement".to_string() });
+            }
+            let api_key = registered_terminal(&entry)?.api_key.unwrap_or_default();
+            yavin::find_cloud_transaction(&address, &api_key, &merchant_ref).await?
+        }
+        // The local API is queried by the id the terminal gave when the payment started
+        TpeProtocol::YavinLocal => {
+            let Some(transaction_id) = entry.terminal_reference.as_deref() else {
+                return Err(TpeError::InvalidRequest {
+                    reason: "le TPE n'a pas communiqué d'identifiant pour ce paiement, vérifiez le journal du TPE".to_string(),
+                });
+            };
+            yavin::find_local_transaction(&address, transaction_id).await?
+        }
+        // Concert and Caisse-AP have no "last transaction" query: the terminal's
+        // journal (or ticket) is the only source of truth.
+        _ => {
+            return Err(TpeError::Unsupported {
+                protocol: protocol.name().to_string(),
+                operation: "interrogation de la dernière transaction, vérifiez le journal du TPE".to_string(),
+            });
+        }
+    };
+    
+    // A definitive answer closes the journal entry (an unknown status is not one)
+    if let Some(response) = &result {
+        if response.error.is_none() && !matches!(response.transaction_result.as_str(), "PENDING" | "IN_PROGRESS") {
+            resolve(&payment_id);
+        }
+    }
+    
+    Ok(result.map(|response| TpePaymentResponse {
+        payment_id: Some(payment_id),
+        ..response
+    }))
+}
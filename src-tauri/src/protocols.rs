@@ -1,7 +1,10 @@
 // TPE Protocol Builders
 // Factorized protocol implementations for different TPE types
 
-use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+
+use crate::currency::Currency;
+use crate::tlv::TlvMessage;
 
 // Protocol constants
 pub const STX: u8 = 0x02;
@@ -18,11 +21,203 @@ pub fn bytes_to_hex(data: &[u8]) -> String {
 }
 
 // ===================================
-// TLV Helper (for Concert V3 / Caisse-AP)
+// Transaction Parameters
 // ===================================
 
-fn tlv(tag: &str, value: &str) -> String {
-    format!("{}{:03}{}", tag, value.len(), value)
+/// Transaction type requested from the terminal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionType {
+    Debit,               // Paiement
+    Credit,              // Remboursement
+    PreAuthorization,    // Pré-autorisation (empreinte bancaire)
+    Completion,          // Encaissement de tout ou partie d'une pré-autorisation
+    PreAuthCancellation, // Libération d'une pré-autorisation
+    Reconciliation,      // Totaux du jour / télécollecte (aucun montant débité)
+}
+
+impl TransactionType {
+    /// Code used in the CD tag (TLV) and the binary type field
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionType::Debit => "0",
+            TransactionType::Credit => "1",
+            TransactionType::PreAuthCancellation => "2",
+            TransactionType::PreAuthorization => "4",
+            TransactionType::Completion => "5",
+            TransactionType::Reconciliation => "9",
+        }
+    }
+    
+    /// Label shown to the cashier
+    pub fn label(&self) -> &'static str {
+        match self {
+            TransactionType::Debit => "Paiement",
+            TransactionType::Credit => "Remboursement",
+            TransactionType::PreAuthorization => "Pré-autorisation",
+            TransactionType::Completion => "Encaissement de pré-autorisation",
+            TransactionType::PreAuthCancellation => "Annulation de pré-autorisation",
+            TransactionType::Reconciliation => "Télécollecte",
+        }
+    }
+    
+    /// Pre-authorization flows need TLV messages (the hold reference has no
+    /// room in the fixed-length binary formats)
+    pub fn is_pre_authorization_flow(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::PreAuthorization | TransactionType::Completion | TransactionType::PreAuthCancellation
+        )
+    }
+    
+    /// Does the transaction move money? (a reconciliation only reports totals)
+    pub fn moves_money(&self) -> bool {
+        *self != TransactionType::Reconciliation
+    }
+}
+
+/// Card application the terminal should use (meal vouchers and ANCV holiday
+/// vouchers run as separate applications on the terminal)
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardApplication {
+    #[default]
+    Any,            // Terminal picks the application from the card
+    BankCard,       // CB, Visa, Mastercard...
+    MealVoucher,    // Titres-restaurant (Conecs, Swile, Edenred...)
+    HolidayVoucher, // Chèques-Vacances (ANCV Connect)
+}
+
+impl CardApplication {
+    /// Code used in the CC tag, None lets the terminal choose
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            CardApplication::Any => None,
+            CardApplication::BankCard => Some("001"),
+            CardApplication::MealVoucher => Some("002"),
+            CardApplication::HolidayVoucher => Some("003"),
+        }
+    }
+    
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code.trim() {
+            "001" => Some(CardApplication::BankCard),
+            "002" => Some(CardApplication::MealVoucher),
+            "003" => Some(CardApplication::HolidayVoucher),
+            _ => None,
+        }
+    }
+    
+    /// Label shown to the cashier
+    pub fn label(&self) -> &'static str {
+        match self {
+            CardApplication::Any => "Automatique",
+            CardApplication::BankCard => "Carte bancaire",
+            CardApplication::MealVoucher => "Titre-restaurant",
+            CardApplication::HolidayVoucher => "Chèque-Vacances (ANCV)",
+        }
+    }
+}
+
+/// Parameters of a TPE transaction, shared by all protocol builders
+#[derive(Debug, Clone)]
+pub struct TpeTransaction {
+    pub tx_type: TransactionType,
+    pub amount_minor: u32,
+    pub pos_number: String,
+    /// Reference of the original transaction (credits) or of the hold
+    /// (completions and pre-authorization cancellations)
+    pub original_ref: Option<String>,
+    /// Our POS transaction id (merchant reference)
+    pub pos_transaction_id: Option<String>,
+    /// Currency of the amount (amount_minor is in its minor units)
+    pub currency: Currency,
+    /// TI tag, unique per till (see tpe_ids)
+    pub tpe_transaction_id: Option<String>,
+    /// Card application requested from the terminal (CC tag)
+    pub application: CardApplication,
+}
+
+impl TpeTransaction {
+    pub fn debit(amount_minor: u32, pos_number: &str) -> Self {
+        TpeTransaction {
+            tx_type: TransactionType::Debit,
+            amount_minor,
+            pos_number: pos_number.to_string(),
+            original_ref: None,
+            pos_transaction_id: None,
+            currency: Currency::default(),
+            tpe_transaction_id: None,
+            application: CardApplication::Any,
+        }
+    }
+    
+    /// Link the transaction to our POS transaction id
+    pub fn with_pos_transaction_id(mut self, pos_transaction_id: Option<String>) -> Self {
+        self.pos_transaction_id = pos_transaction_id.filter(|id| !id.is_empty());
+        self
+    }
+    
+    pub fn credit(amount_minor: u32, pos_number: &str, original_ref: Option<String>) -> Self {
+        TpeTransaction {
+            tx_type: TransactionType::Credit,
+            amount_minor,
+            pos_number: pos_number.to_string(),
+            original_ref: original_ref.filter(|r| !r.is_empty()),
+            pos_transaction_id: None,
+            currency: Currency::default(),
+            tpe_transaction_id: None,
+            application: CardApplication::Any,
+        }
+    }
+    
+    /// Put a hold on the card
+    pub fn pre_authorization(amount_minor: u32, pos_number: &str) -> Self {
+        TpeTransaction {
+            tx_type: TransactionType::PreAuthorization,
+            ..TpeTransaction::debit(amount_minor, pos_number)
+        }
+    }
+    
+    /// Capture `amount_minor` (at most the held amount) from a pre-authorization
+    pub fn completion(amount_minor: u32, pos_number: &str, hold_ref: &str) -> Self {
+        TpeTransaction {
+            tx_type: TransactionType::Completion,
+            original_ref: Some(hold_ref.to_string()),
+            ..TpeTransaction::debit(amount_minor, pos_number)
+        }
+    }
+    
+    /// Ask the terminal for its day totals and start the télécollecte
+    pub fn reconciliation(pos_number: &str) -> Self {
+        TpeTransaction {
+            tx_type: TransactionType::Reconciliation,
+            ..TpeTransaction::debit(0, pos_number)
+        }
+    }
+    
+    /// Release a pre-authorization without capturing it
+    pub fn pre_auth_cancellation(amount_minor: u32, pos_number: &str, hold_ref: &str) -> Self {
+        TpeTransaction {
+            tx_type: TransactionType::PreAuthCancellation,
+            original_ref: Some(hold_ref.to_string()),
+            ..TpeTransaction::debit(amount_minor, pos_number)
+        }
+    }
+    
+    pub fn with_currency(mut self, currency: Currency) -> Self {
+        self.currency = currency;
+        self
+    }
+    
+    pub fn with_tpe_transaction_id(mut self, tpe_transaction_id: String) -> Self {
+        self.tpe_transaction_id = Some(tpe_transaction_id);
+        self
+    }
+    
+    pub fn with_application(mut self, application: CardApplication) -> Self {
+        self.application = application;
+        self
+    }
 }
 
 // ===================================
@@ -31,71 +226,80 @@ fn tlv(tag: &str, value: &str) -> String {
 
 /// Concert V2 binary protocol (Ingenico older terminals)
 /// Format: TYPE(1) + POS(2) + AMOUNT(8) + CURRENCY(3) = 14 chars
-pub fn build_concert_v2(amount_cents: u32, pos_number: &str) -> Vec<u8> {
-    let pos_num = format_pos_number(pos_number);
-    let tx_type = "0"; // Debit
-    let amount = format!("{:08}", amount_cents);
-    let data = format!("{}{}{}{}", tx_type, pos_num, amount, "978");
+/// The fixed-length format has no room for an original reference (refused by `build_request`).
+pub fn build_concert_v2(tx: &TpeTransaction) -> Vec<u8> {
+    let pos_num = format_pos_number(&tx.pos_number);
+    let tx_type = tx.tx_type.code();
+    let amount = format!("{:08}", tx.amount_minor);
+    let data = format!("{}{}{}{}", tx_type, pos_num, amount, tx.currency.numeric);
     
     frame_message(&data)
 }
 
 /// Concert V3 TLV protocol (Modern terminals, SmilePay)
 /// Uses Tag-Length-Value format same as Caisse-AP IP
-pub fn build_concert_v3_tlv(amount_cents: u32, pos_number: &str) -> Vec<u8> {
-    let pos_num = format_pos_number(pos_number);
-    
-    let mut msg = String::new();
-    msg.push_str(&tlv("CZ", "0320")); // Protocol version 3.2
-    msg.push_str(&tlv("CA", &pos_num)); // POS number
-    msg.push_str(&tlv("CE", "978")); // Currency EUR
-    msg.push_str(&tlv("BA", "0")); // Answer at end
-    msg.push_str(&tlv("CD", "0")); // Debit transaction
-    msg.push_str(&tlv("CB", &format!("{:012}", amount_cents))); // Amount 12 digits
-    
-    frame_message(&msg)
+pub fn build_concert_v3_tlv(tx: &TpeTransaction) -> Result<Vec<u8>, String> {
+    let pos_num = format_pos_number(&tx.pos_number);
+    
+    let mut msg = TlvMessage::new();
+    msg.push("CZ", "0320")? // Protocol version 3.2
+        .push("CA", &pos_num)? // POS number
+        .push("CE", &tx.currency.numeric)? // Currency (ISO 4217 numeric)
+        .push("BA", "0")? // Answer at end
+        .push("CD", tx.tx_type.code())? // Transaction type (0 = debit, 1 = credit, 4/5/2 = pre-authorization)
+        .push("CB", &format!("{:012}", tx.amount_minor))?; // Amount 12 digits
+    if let Some(original_ref) = &tx.original_ref {
+        msg.push("RF", original_ref)?; // Original transaction or hold reference
+    }
+    if let Some(application) = tx.application.code() {
+        msg.push("CC", application)?; // Card application
+    }
+    
+    Ok(frame_message(&msg.encode()))
 }
 
 /// Concert V3 binary protocol (alternative format)
 /// Format: TYPE(2) + POS(2) + AMOUNT(12) + CURRENCY(3) = 19 chars
-pub fn build_concert_v3_binary(amount_cents: u32, pos_number: &str) -> Vec<u8> {
-    let pos_num = format_pos_number(pos_number);
-    let tx_type = "00"; // Debit
-    let amount = format!("{:012}", amount_cents);
-    let data = format!("{}{}{}{}", tx_type, pos_num, amount, "978");
+/// The fixed-length format has no room for an original reference (refused by `build_request`).
+pub fn build_concert_v3_binary(tx: &TpeTransaction) -> Vec<u8> {
+    let pos_num = format_pos_number(&tx.pos_number);
+    let tx_type = format!("{:0>2}", tx.tx_type.code());
+    let amount = format!("{:012}", tx.amount_minor);
+    let data = format!("{}{}{}{}", tx_type, pos_num, amount, tx.currency.numeric);
     
     frame_message(&data)
 }
 
 /// Caisse-AP IP protocol (TCP/IP terminals, Nepting)
 /// Full TLV with transaction ID and label
-pub fn build_caisse_ap_ip(amount_cents: u32, pos_number: &str) -> Vec<u8> {
-    let pos_num = format_pos_number(pos_number);
-    
-    // Generate transaction ID
-    let tx_id = format!("{:06}", SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() % 1000000);
-    
-    let mut msg = String::new();
-    msg.push_str(&tlv("CZ", "0320")); // Protocol version
-    msg.push_str(&tlv("CA", &pos_num)); // POS number
-    msg.push_str(&tlv("CE", "978")); // Currency
-    msg.push_str(&tlv("BA", "0")); // Answer mode
-    msg.push_str(&tlv("CD", "0")); // Transaction type
-    msg.push_str(&tlv("CB", &format!("{:012}", amount_cents))); // Amount
-    msg.push_str(&tlv("TI", &tx_id)); // Transaction ID
-    msg.push_str(&tlv("LB", "CAISSE")); // Label
-    
-    frame_message(&msg)
+pub fn build_caisse_ap_ip(tx: &TpeTransaction) -> Result<Vec<u8>, String> {
+    let pos_num = format_pos_number(&tx.pos_number);
+    let tx_id = tx.tpe_transaction_id.as_deref().ok_or("Caisse-AP: identifiant de transaction (TI) manquant")?;
+    
+    let mut msg = TlvMessage::new();
+    msg.push("CZ", "0320")? // Protocol version
+        .push("CA", &pos_num)? // POS number
+        .push("CE", &tx.currency.numeric)? // Currency
+        .push("BA", "0")? // Answer mode
+        .push("CD", tx.tx_type.code())? // Transaction type
+        .push("CB", &format!("{:012}", tx.amount_minor))? // Amount
+        .push("TI", tx_id)?; // Transaction ID
+    if let Some(original_ref) = &tx.original_ref {
+        msg.push("RF", original_ref)?; // Original transaction or hold reference
+    }
+    if let Some(application) = tx.application.code() {
+        msg.push("CC", application)?; // Card application
+    }
+    msg.push("LB", "CAISSE")?; // Label
+    
+    Ok(frame_message(&msg.encode()))
 }
 
 /// SmilePay protocol (uses Concert V3 TLV)
 /// SmilePay Smart/Super Smile terminals use standard Concert V3
-pub fn build_smilepay(amount_cents: u32, pos_number: &str) -> Vec<u8> {
+pub fn build_smilepay(tx: &TpeTransaction) -> Result<Vec<u8>, String> {
     // SmilePay uses Concert V3 TLV format
-    build_concert_v3_tlv(amount_cents, pos_number)
+    build_concert_v3_tlv(tx)
 }
 
 // ===================================
@@ -103,92 +307,32 @@ pub fn build_smilepay(amount_cents: u32, pos_number: &str) -> Vec<u8> {
 // ===================================
 
 /// Build Yavin Local API JSON payload
-pub fn build_yavin_local_payload(amount_cents: u32, terminal_id: &str) -> String {
+pub fn build_yavin_local_payload(tx: &TpeTransaction, terminal_id: &str) -> String {
     serde_json::json!({
         "serial_number": terminal_id,
-        "amount": amount_cents,
-        "currency": "EUR",
+        "amount": tx.amount_minor,
+        "currency": tx.currency.alpha,
         "transaction_type": "PAYMENT"
     }).to_string()
 }
 
 /// Build Yavin Cloud API JSON payload
-pub fn build_yavin_cloud_payload(amount_cents: u32, terminal_id: &str, merchant_ref: &str) -> String {
+pub fn build_yavin_cloud_payload(tx: &TpeTransaction, terminal_id: &str) -> String {
     serde_json::json!({
         "serial_number": terminal_id,
-        "amount": amount_cents,
-        "currency": "EUR",
+        "amount": tx.amount_minor,
+        "currency": tx.currency.alpha,
         "transaction_type": "PAYMENT",
-        "merchant_reference": merchant_ref
+        "merchant_reference": tx.pos_transaction_id.clone().unwrap_or_default()
     }).to_string()
 }
 
-// ===================================
-// Response Parsing
-// ===================================
-
-/// Concert response codes
-pub struct ConcertResponse {
-    pub success: bool,
-    pub code: String,
-    pub message: String,
-}
-
-/// Parse Concert protocol response
-pub fn parse_concert_response(data: &[u8]) -> ConcertResponse {
-    let stx = data.iter().position(|&b| b == STX);
-    let etx = data.iter().position(|&b| b == ETX);
-    
-    if let (Some(s), Some(e)) = (stx, etx) {
-        if e > s {
-            let body = &data[s+1..e];
-            let body_str = String::from_utf8_lossy(body);
-            
-            // Try to extract result code at position 1 (V2) or 2 (V3)
-            let code = if body_str.len() >= 3 {
-                let v2_code = &body_str[1..3.min(body_str.len())];
-                let v3_code = if body_str.len() >= 4 { &body_str[2..4] } else { "" };
-                
-                if v2_code == "00" || v3_code == "00" {
-                    "00".to_string()
-                } else if v2_code == "10" || v3_code == "10" {
-                    "10".to_string()
-                } else if v2_code == "01" || v3_code == "01" {
-                    "01".to_string()
-                } else {
-                    v2_code.to_string()
-                }
-            } else {
-                "??".to_string()
-            };
-            
-            let (success, message) = match code.as_str() {
-                "00" => (true, "Transaction acceptée".to_string()),
-                "01" => (false, "Transaction annulée".to_string()),
-                "02" => (false, "Carte refusée".to_string()),
-                "03" => (false, "Erreur communication".to_string()),
-                "10" => (false, "Fonction impossible".to_string()),
-                "11" => (false, "Timeout".to_string()),
-                _ => (false, format!("Erreur inconnue ({})", code)),
-            };
-            
-            return ConcertResponse { success, code, message };
-        }
-    }
-    
-    ConcertResponse {
-        success: false,
-        code: "??".to_string(),
-        message: "Réponse invalide".to_string(),
-    }
-}
-
 // ===================================
 // Helper Functions
 // ===================================
 
 /// Format POS number to exactly 2 digits
-fn format_pos_number(pos_number: &str) -> String {
+pub(crate) fn format_pos_number(pos_number: &str) -> String {
     if pos_number.len() >= 2 { 
         pos_number[..2].to_string() 
     } else if pos_number.len() == 1 { 
@@ -199,7 +343,7 @@ fn format_pos_number(pos_number: &str) -> String {
 }
 
 /// Frame message with STX, ETX, and LRC
-fn frame_message(data: &str) -> Vec<u8> {
+pub(crate) fn frame_message(data: &str) -> Vec<u8> {
     let mut lrc_input: Vec<u8> = data.as_bytes().to_vec();
     lrc_input.push(ETX);
     let lrc = calculate_lrc(&lrc_input);
@@ -226,6 +370,7 @@ pub enum TpeProtocol {
     SmilePay,        // Uses Concert V3 TLV
     YavinLocal,      // HTTP Local API
     YavinCloud,      // HTTP Cloud API
+    NeptingRc,       // Caisse-AP request, answer carries an RC response code
 }
 
 impl TpeProtocol {
@@ -237,10 +382,35 @@ impl TpeProtocol {
             5 => TpeProtocol::SmilePay,
             6 => TpeProtocol::YavinLocal,
             7 => TpeProtocol::YavinCloud,
+            8 => TpeProtocol::CaisseApIp,
+            9 => TpeProtocol::NeptingRc,
             _ => TpeProtocol::ConcertV3Tlv, // Default
         }
     }
     
+    /// Inverse of from_version: the protocol_version to save in the settings
+    pub fn version(&self) -> u8 {
+        match self {
+            TpeProtocol::ConcertV2 => 2,
+            TpeProtocol::ConcertV3Tlv => 3,
+            TpeProtocol::ConcertV3Binary => 4,
+            TpeProtocol::SmilePay => 5,
+            TpeProtocol::YavinLocal => 6,
+            TpeProtocol::YavinCloud => 7,
+            TpeProtocol::CaisseApIp => 8,
+            TpeProtocol::NeptingRc => 9,
+        }
+    }
+    
+    /// Adjust the configured protocol to the transport in use.
+    /// Concert V3 TLV carried over TCP/IP is Caisse-AP IP.
+    pub fn for_transport(self, is_tcp: bool) -> Self {
+        match self {
+            TpeProtocol::ConcertV3Tlv if is_tcp => TpeProtocol::CaisseApIp,
+            other => other,
+        }
+    }
+    
     pub fn name(&self) -> &'static str {
         match self {
             TpeProtocol::ConcertV2 => "Concert V2 (Binaire)",
@@ -250,10 +420,91 @@ impl TpeProtocol {
             TpeProtocol::SmilePay => "SmilePay",
             TpeProtocol::YavinLocal => "Yavin (Local API)",
             TpeProtocol::YavinCloud => "Yavin (Cloud API)",
+            TpeProtocol::NeptingRc => "Nepting (code RC)",
         }
     }
     
     pub fn is_http(&self) -> bool {
         matches!(self, TpeProtocol::YavinLocal | TpeProtocol::YavinCloud)
     }
+    
+    /// Concert link protocols open the exchange with ENQ and expect ACK
+    /// (Caisse-AP IP and Nepting RC send the TLV frame directly)
+    pub fn uses_enq_handshake(&self) -> bool {
+        matches!(
+            self,
+            TpeProtocol::ConcertV2
+                | TpeProtocol::ConcertV3Tlv
+                | TpeProtocol::ConcertV3Binary
+                | TpeProtocol::SmilePay
+        )
+    }
+    
+    /// Does the terminal keep the TCP connection open between transactions?
+    /// (Nepting RC terminals hang up after each answer, HTTP protocols use their own client)
+    pub fn keeps_connection(&self) -> bool {
+        !self.is_http() && *self != TpeProtocol::NeptingRc
+    }
+    
+    /// Can this protocol carry the given transaction type?
+    pub fn supports(&self, tx_type: TransactionType) -> bool {
+        match self {
+            TpeProtocol::YavinLocal | TpeProtocol::YavinCloud | TpeProtocol::NeptingRc => {
+                tx_type == TransactionType::Debit
+            }
+            TpeProtocol::ConcertV2 | TpeProtocol::ConcertV3Binary => {
+                !tx_type.is_pre_authorization_flow() && tx_type.moves_money()
+            }
+            TpeProtocol::ConcertV3Tlv | TpeProtocol::CaisseApIp | TpeProtocol::SmilePay => true,
+        }
+    }
+    
+    /// Can this protocol ask the terminal for a given card application?
+    /// Only the TLV messages have a field for it.
+    pub fn supports_application(&self, application: CardApplication) -> bool {
+        match self {
+            TpeProtocol::ConcertV3Tlv | TpeProtocol::CaisseApIp | TpeProtocol::SmilePay | TpeProtocol::NeptingRc => true,
+            _ => application == CardApplication::Any,
+        }
+    }
+    
+    /// Can this protocol send the original reference of a credit?
+    /// The fixed-length Concert formats have no field for it.
+    pub fn supports_original_ref(&self) -> bool {
+        !matches!(self, TpeProtocol::ConcertV2 | TpeProtocol::ConcertV3Binary)
+    }
+    
+    /// Part of the transaction this protocol cannot carry (its type, the card
+    /// application or the original reference), None when it can be sent
+    pub fn unsupported_operation(&self, tx: &TpeTransaction) -> Option<String> {
+        if !self.supports(tx.tx_type) {
+            return Some(tx.tx_type.label().to_string());
+        }
+        if !self.supports_application(tx.application) {
+            return Some(format!("choix de l'application {}", tx.application.label()));
+        }
+        if tx.original_ref.is_some() && !self.supports_original_ref() {
+            return Some(format!("{} avec référence d'origine", tx.tx_type.label()));
+        }
+        None
+    }
+    
+    /// Build the framed transaction request for this protocol.
+    /// Fails for HTTP protocols, which send JSON payloads instead,
+    /// and for transactions the protocol cannot carry.
+    pub fn build_request(&self, tx: &TpeTransaction) -> Result<Vec<u8>, String> {
+        if let Some(operation) = self.unsupported_operation(tx) {
+            return Err(format!("{}: opération non prise en charge ({})", self.name(), operation));
+        }
+        match self {
+            TpeProtocol::ConcertV2 => Ok(build_concert_v2(tx)),
+            TpeProtocol::ConcertV3Tlv => build_concert_v3_tlv(tx),
+            TpeProtocol::ConcertV3Binary => Ok(build_concert_v3_binary(tx)),
+            TpeProtocol::CaisseApIp | TpeProtocol::NeptingRc => build_caisse_ap_ip(tx),
+            TpeProtocol::SmilePay => build_smilepay(tx),
+            TpeProtocol::YavinLocal | TpeProtocol::YavinCloud => {
+                Err(format!("{}: aucun message de transaction", self.name()))
+            }
+        }
+    }
 }
@@ -0,0 +1,177 @@
+// ===================================
+// Serial Line - TPE serial port settings
+// ===================================
+//
+// Concert terminals talk 7E1 without flow control. Some PAX and older Ingenico
+// units need 8N1, hardware flow control or a forced DTR/RTS state.
+
+use serde::{Deserialize, Serialize};
+use tokio_serial::{SerialPort, SerialPortBuilder, SerialStream};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowControl {
+    None,
+    Software, // XON/XOFF
+    Hardware, // RTS/CTS
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SerialSettings {
+    pub data_bits: u8, // 5 to 8
+    pub parity: Parity,
+    pub stop_bits: u8, // 1 or 2
+    pub flow_control: FlowControl,
+    pub dtr: Option<bool>, // None leaves the line as the driver opens it
+    pub rts: Option<bool>,
+}
+
+/// Concert standard: 7 data bits, even parity, 1 stop bit
+impl Default for SerialSettings {
+    fn default() -> Self {
+        SerialSettings {
+            data_bits: 7,
+            parity: Parity::Even,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            dtr: None,
+            rts: None,
+        }
+    }
+}
+
+impl SerialSettings {
+    /// "7E1", "8N1 RTS/CTS"... for the logs
+    pub fn label(&self) -> String {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        let flow = match self.flow_control {
+            FlowControl::None => "",
+            FlowControl::Software => " XON/XOFF",
+            FlowControl::Hardware => " RTS/CTS",
+        };
+        format!("{}{}{}{}", self.data_bits, parity, self.stop_bits, flow)
+    }
+    
+    /// Reject settings no serial port can take, before they are saved
+    pub fn validate(&self) -> Result<(), String> {
+        self.data_bits()?;
+        self.stop_bits()?;
+        Ok(())
+    }
+    
+    fn data_bits(&self) -> Result<tokio_serial::DataBits, String> {
+        match self.data_bits {
+            5 => Ok(tokio_serial::DataBits::Five),
+            6 => Ok(tokio_serial::DataBits::Six),
+            7 => Ok(tokio_serial::DataBits::Seven),
+            8 => Ok(tokio_serial::DataBits::Eight),
+            other => Err(format!("Nombre de bits de données invalide: {}", other)),
+        }
+    }
+    
+    fn stop_bits(&self) -> Result<tokio_serial::StopBits, String> {
+        match self.stop_bits {
+            1 => Ok(tokio_serial::StopBits::One),
+            2 => Ok(tokio_serial::StopBits::Two),
+            other => Err(format!("Nombre de bits de stop invalide: {}", other)),
+        }
+    }
+    
+    /// Apply the line settings to a port about to be opened
+    pub(crate) fn configure(&self, builder: SerialPortBuilder) -> Result<SerialPortBuilder, String> {
+        let data_bits = self.data_bits()?;
+        let stop_bits = self.stop_bits()?;
+        let parity = match self.parity {
+            Parity::None => tokio_serial::Parity::None,
+            Parity::Even => tokio_serial::Parity::Even,
+            Parity::Odd => tokio_serial::Parity::Odd,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => tokio_serial::FlowControl::None,
+            FlowControl::Software => tokio_serial::FlowControl::Software,
+            FlowControl::Hardware => tokio_serial::FlowControl::Hardware,
+        };
+        
+        Ok(builder
+            .data_bits(data_bits)
+            .parity(parity)
+            .stop_bits(stop_bits)
+            .flow_control(flow_control))
+    }
+    
+    /// Force the modem control lines once the port is open
+    pub(crate) fn apply_control_lines(&self, port: &mut SerialStream) -> Result<(), String> {
+        if let Some(dtr) = self.dtr {
+            port.write_data_terminal_ready(dtr).map_err(|e| format!("DTR: {}", e))?;
+        }
+        if let Some(rts) = self.rts {
+            port.write_request_to_send(rts).map_err(|e| format!("RTS: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn defaults_to_concert_7e1() {
+        let settings = SerialSettings::default();
+        
+        assert_eq!(settings.label(), "7E1");
+        assert!(settings.validate().is_ok());
+    }
+    
+    #[test]
+    fn parses_saved_settings_and_fills_missing_fields() {
+        let settings: SerialSettings =
+            serde_json::from_str(r#"{"data_bits": 8, "parity": "none", "flow_control": "hardware", "rts": true}"#).unwrap();
+        
+        assert_eq!(settings.label(), "8N1 RTS/CTS");
+        assert_eq!(settings.stop_bits, 1);
+        assert_eq!(settings.dtr, None);
+        assert_eq!(settings.rts, Some(true));
+        assert!(settings.validate().is_ok());
+    }
+    
+    #[test]
+    fn rejects_unknown_parity_and_flow_control() {
+        assert!(serde_json::from_str::<SerialSettings>(r#"{"parity": "mark"}"#).is_err());
+        assert!(serde_json::from_str::<SerialSettings>(r#"{"flow_control": "dsr"}"#).is_err());
+        assert!(serde_json::from_str::<SerialSettings>(r#"{"data_bits": 300}"#).is_err());
+    }
+    
+    #[test]
+    fn rejects_impossible_bit_counts() {
+        let data_bits = SerialSettings { data_bits: 9, ..SerialSettings::default() };
+        let stop_bits = SerialSettings { stop_bits: 0, ..SerialSettings::default() };
+        
+        assert_eq!(data_bits.validate(), Err("Nombre de bits de données invalide: 9".to_string()));
+        assert_eq!(stop_bits.validate(), Err("Nombre de bits de stop invalide: 0".to_string()));
+        assert!(stop_bits.configure(tokio_serial::new("COM1", 9600)).is_err());
+    }
+    
+    #[test]
+    fn accepts_every_supported_combination() {
+        for data_bits in 5..=8 {
+            for stop_bits in 1..=2 {
+                let settings = SerialSettings { data_bits, stop_bits, ..SerialSettings::default() };
+                assert!(settings.configure(tokio_serial::new("COM1", 9600)).is_ok());
+            }
+        }
+    }
+}
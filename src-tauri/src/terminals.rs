@@ -0,0 +1,327 @@
+// ===================================
+// Terminal Registry - named TPE configurations
+// ===================================
+//
+// Terminals are saved by name next to the app's local data, with one of them
+// as the default. Payments can then be sent by terminal name instead of
+// passing every setting from the frontend.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use tauri::AppHandle;
+
+use crate::pending::{data_path, write_durably};
+use crate::protocols::{CardApplication, TpeProtocol};
+use crate::tpe::{self, log_to_file, TpeConfig, TpePaymentResponse};
+use crate::tpe_error::TpeError;
+
+const REGISTRY_FILE_NAME: &str = "ma-caisse-terminals.json";
+
+// Serializes read-modify-write cycles on the registry file
+static REGISTRY_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TerminalRegistry {
+    pub terminals: Vec<TpeConfig>,
+    #[serde(default)]
+    pub default_terminal: Option<String>,
+}
+
+impl TerminalRegistry {
+    fn position(&self, name: &str) -> Option<usize> {
+        let name = name.trim();
+        self.terminals.iter().position(|t| t.name == name)
+    }
+    
+    /// Terminal by name, or the default terminal when no name is given
+    fn resolve(&self, name: Option<&str>) -> Result<TpeConfig, TpeError> {
+        let name = match name.map(str::trim).filter(|n| !n.is_empty()) {
+            Some(name) => name,
+            None => self.default_terminal.as_deref().ok_or(TpeError::NoDefaultTerminal)?,
+        };
+        self.index(name).map(|i| self.terminals[i].clone())
+    }
+    
+    fn index(&self, name: &str) -> Result<usize, TpeError> {
+        self.position(name).ok_or_else(|| TpeError::UnknownTerminal { name: name.trim().to_string() })
+    }
+    
+    /// Register a new terminal (the first one becomes the default)
+    fn add(&mut self, terminal: TpeConfig) -> Result<(), TpeError> {
+        if self.position(&terminal.name).is_some() {
+            return Err(TpeError::DuplicateTerminal { name: terminal.name });
+        }
+        if self.default_terminal.is_none() {
+            self.default_terminal = Some(terminal.name.clone());
+        }
+        self.terminals.push(terminal);
+        Ok(())
+    }
+    
+    /// Replace the settings of terminal `name` (renaming it keeps it the default)
+    fn replace(&mut self, name: &str, terminal: TpeConfig) -> Result<(), TpeError> {
+        let index = self.index(name)?;
+        if self.position(&terminal.name).is_some_and(|other| other != index) {
+            return Err(TpeError::DuplicateTerminal { name: terminal.name });
+        }
+        if self.default_terminal.as_deref() == Some(name.trim()) {
+            self.default_terminal = Some(terminal.name.clone());
+        }
+        self.terminals[index] = terminal;
+        Ok(())
+    }
+    
+    /// Remove a terminal; the default moves to the first remaining one
+    fn remove(&mut self, name: &str) -> Result<(), TpeError> {
+        let index = self.index(name)?;
+        self.terminals.remove(index);
+        if self.default_terminal.as_deref() == Some(name.trim()) {
+            self.default_terminal = self.terminals.first().map(|t| t.name.clone());
+        }
+        Ok(())
+    }
+    
+    fn set_default(&mut self, name: &str) -> Result<(), TpeError> {
+        let index = self.index(name)?;
+        self.default_terminal = Some(self.terminals[index].name.clone());
+        Ok(())
+    }
+}
+
+fn load() -> Result<TerminalRegistry, TpeError> {
+    let path = data_path(REGISTRY_FILE_NAME);
+    let reason = match fs::read_to_string(&path) {
+        Ok(content) => match serde_json::from_str(&content) {
+            Ok(registry) => return Ok(registry),
+            Err(e) => format!("liste des TPE illisible ({}): {}", path.display(), e),
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(TerminalRegistry::default()),
+        Err(e) => format!("liste des TPE inaccessible ({}): {}", path.display(), e),
+    };
+    Err(TpeError::Storage { reason })
+}
+
+fn update<F: FnOnce(&mut TerminalRegistry) -> Result<(), TpeError>>(f: F) -> Result<TerminalRegistry, TpeError> {
+    let _guard = REGISTRY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut registry = load()?;
+    f(&mut registry)?;
+    
+    let json = serde_json::to_string_pretty(&registry).map_err(|e| TpeError::Storage { reason: e.to_string() })?;
+    write_durably(&data_path(REGISTRY_FILE_NAME), &json)
+        .map_err(|e| TpeError::Storage { reason: format!("liste des TPE non enregistrée ({})", e) })?;
+    Ok(registry)
+}
+
+/// Settings of a registered terminal (the default one when no name is given)
+pub fn get(name: Option<&str>) -> Result<TpeConfig, TpeError> {
+    let _guard = REGISTRY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    load()?.resolve(name)
+}
+
+/// Every registered terminal
+pub fn all() -> Result<Vec<TpeConfig>, TpeError> {
+    let _guard = REGISTRY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(load()?.terminals)
+}
+
+fn validate(mut terminal: TpeConfig) -> Result<TpeConfig, TpeError> {
+    terminal.name = terminal.name.trim().to_string();
+    terminal.port = terminal.port.trim().to_string();
+    if terminal.name.is_empty() {
+        return Err(TpeError::InvalidConfig { reason: "nom du TPE manquant".to_string() });
+    }
+    // Yavin Cloud goes through Yavin's servers, no local port needed
+    let is_cloud = TpeProtocol::from_version(terminal.protocol_version) == TpeProtocol::YavinCloud;
+    if terminal.port.is_empty() && !is_cloud {
+        return Err(TpeError::InvalidConfig { reason: format!("TPE '{}': port manquant", terminal.name) });
+    }
+    terminal.currency.validate().map_err(|reason| TpeError::InvalidConfig { reason })?;
+    terminal.serial.validate().map_err(|reason| TpeError::InvalidConfig {
+        reason: format!("TPE '{}': {}", terminal.name, reason),
+    })?;
+    terminal.timeouts.validate().map_err(|reason| TpeError::InvalidConfig {
+        reason: format!("TPE '{}': {}", terminal.name, reason),
+    })?;
+    Ok(terminal)
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+#[tauri::command]
+pub fn list_tpe_terminals() -> Result<TerminalRegistry, TpeError> {
+    let _guard = REGISTRY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    load()
+}
+
+/// Register a new terminal (the first one becomes the default)
+#[tauri::command]
+pub fn add_tpe_terminal(terminal: TpeConfig) -> Result<TerminalRegistry, TpeError> {
+    let terminal = validate(terminal)?;
+    log_to_file(&format!("Registry: adding terminal '{}' ({})", terminal.name, terminal.port));
+    update(|registry| registry.add(terminal))
+}
+
+/// Replace the settings of terminal `name` (renaming it keeps it the default)
+#[tauri::command]
+pub fn update_tpe_terminal(name: String, terminal: TpeConfig) -> Result<TerminalRegistry, TpeError> {
+    let terminal = validate(terminal)?;
+    log_to_file(&format!("Registry: updating terminal '{}' ({})", name, terminal.port));
+    update(|registry| registry.replace(&name, terminal))
+}
+
+#[tauri::command]
+pub fn remove_tpe_terminal(name: String) -> Result<TerminalRegistry, TpeError> {
+    log_to_file(&format!("Registry: removing terminal '{}'", name));
+    update(|registry| registry.remove(&name))
+}
+
+#[tauri::command]
+pub fn set_default_tpe_terminal(name: String) -> Result<TerminalRegistry, TpeError> {
+    log_to_file(&format!("Registry: default terminal '{}'", name));
+    update(|registry| registry.set_default(&name))
+}
+
+/// Pay on a registered terminal (the default one when no name is given)
+#[tauri::command]
+pub async fn send_tpe_payment_by_name(
+    app: AppHandle,
+    terminal_name: Option<String>,
+    amount_minor: u32,
+    pos_transaction_id: Option<String>,
+    payment_id: Option<String>,
+    card_application: Option<CardApplication>,
+) -> Result<TpePaymentResponse, TpeError> {
+    let config = get(terminal_name.as_deref())?;
+    log_to_file(&format!("=== PAY {} minor units on '{}' ({}) ===", amount_minor, config.name, config.port));
+    tpe::pay(app, config, amount_minor, pos_transaction_id, payment_id, card_application.unwrap_or_default()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn terminal(name: &str, port: &str) -> TpeConfig {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "port": port,
+            "baud_rate": 9600,
+            "pos_number": "01",
+            "protocol_version": 3,
+        }))
+        .unwrap()
+    }
+    
+    fn registry(names: &[&str]) -> TerminalRegistry {
+        let mut registry = TerminalRegistry::default();
+        for name in names {
+            registry.add(terminal(name, "COM3")).unwrap();
+        }
+        registry
+    }
+    
+    fn names(registry: &TerminalRegistry) -> Vec<&str> {
+        registry.terminals.iter().map(|t| t.name.as_str()).collect()
+    }
+    
+    #[test]
+    fn validate_trims_and_requires_name_and_port() {
+        let trimmed = validate(terminal("  Comptoir ", " COM3 ")).unwrap();
+        assert_eq!((trimmed.name.as_str(), trimmed.port.as_str()), ("Comptoir", "COM3"));
+        
+        assert_eq!(validate(terminal(" ", "COM3")).unwrap_err().code(), "InvalidConfig");
+        assert!(validate(terminal("Bar", "")).unwrap_err().to_string().contains("port manquant"));
+    }
+    
+    #[test]
+    fn validate_lets_yavin_cloud_go_without_port() {
+        let mut cloud = terminal("Cloud", "");
+        cloud.protocol_version = TpeProtocol::YavinCloud.version();
+        
+        assert!(validate(cloud).is_ok());
+    }
+    
+    #[test]
+    fn validate_rejects_bad_line_settings() {
+        let mut bad = terminal("Bar", "COM3");
+        bad.serial.stop_bits = 3;
+        
+        assert!(validate(bad).unwrap_err().to_string().contains("bits de stop"));
+    }
+    
+    #[test]
+    fn validate_rejects_bad_timeouts() {
+        let mut card_wait = terminal("Bar", "COM3");
+        card_wait.timeouts.card_wait_secs = Some(5);
+        let mut ack = terminal("Bar", "COM3");
+        ack.timeouts.ack_ms = 0;
+        
+        assert!(validate(card_wait).unwrap_err().to_string().contains("attente de la carte"));
+        assert!(validate(ack).unwrap_err().to_string().contains("accusé de réception"));
+    }
+    
+    #[test]
+    fn first_terminal_becomes_the_default() {
+        let registry = registry(&["Comptoir", "Terrasse"]);
+        
+        assert_eq!(registry.default_terminal.as_deref(), Some("Comptoir"));
+        assert_eq!(registry.resolve(None).unwrap().name, "Comptoir");
+        assert_eq!(registry.resolve(Some(" Terrasse ")).unwrap().name, "Terrasse");
+    }
+    
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut registry = registry(&["Comptoir", "Terrasse"]);
+        
+        assert_eq!(
+            registry.add(terminal("Comptoir", "COM4")),
+            Err(TpeError::DuplicateTerminal { name: "Comptoir".to_string() })
+        );
+        assert_eq!(
+            registry.replace("Terrasse", terminal("Comptoir", "COM4")),
+            Err(TpeError::DuplicateTerminal { name: "Comptoir".to_string() })
+        );
+        assert_eq!(names(&registry), ["Comptoir", "Terrasse"]);
+    }
+    
+    #[test]
+    fn renaming_the_default_keeps_it_the_default() {
+        let mut registry = registry(&["Comptoir", "Terrasse"]);
+        
+        registry.replace("Comptoir", terminal("Caisse 1", "COM5")).unwrap();
+        registry.replace("Terrasse", terminal("Terrasse", "COM6")).unwrap();
+        
+        assert_eq!(names(&registry), ["Caisse 1", "Terrasse"]);
+        assert_eq!(registry.default_terminal.as_deref(), Some("Caisse 1"));
+        assert_eq!(registry.resolve(None).unwrap().port, "COM5");
+    }
+    
+    #[test]
+    fn removing_the_default_moves_it_to_the_first_terminal() {
+        let mut registry = registry(&["Comptoir", "Terrasse", "Bar"]);
+        registry.set_default("Terrasse").unwrap();
+        
+        registry.remove("Comptoir").unwrap();
+        assert_eq!(registry.default_terminal.as_deref(), Some("Terrasse"));
+        
+        registry.remove("Terrasse").unwrap();
+        assert_eq!(registry.default_terminal.as_deref(), Some("Bar"));
+        
+        registry.remove("Bar").unwrap();
+        assert_eq!(registry.default_terminal, None);
+        assert_eq!(registry.resolve(None).unwrap_err(), TpeError::NoDefaultTerminal);
+    }
+    
+    #[test]
+    fn unknown_terminals_are_reported() {
+        let mut registry = registry(&["Comptoir"]);
+        let unknown = TpeError::UnknownTerminal { name: "Bar".to_string() };
+        
+        assert_eq!(registry.remove("Bar"), Err(unknown.clone()));
+        assert_eq!(registry.set_default("Bar"), Err(unknown.clone()));
+        assert_eq!(registry.resolve(Some("Bar")).unwrap_err(), unknown);
+    }
+}
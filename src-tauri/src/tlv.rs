@@ -0,0 +1,226 @@
+// ===================================
+// TLV Codec - Concert V3 / Caisse-AP messages
+// ===================================
+//
+// Field format: TAG (2 chars) + LENGTH (3 digits) + VALUE (LENGTH bytes).
+// Fields are decoded strictly in sequence, so a tag name appearing inside
+// another field's value is never mistaken for a field.
+
+/// Definition of a tag we build or interpret
+#[derive(Debug)]
+pub struct TagDef {
+    pub tag: &'static str,
+    pub label: &'static str,
+    pub max_len: usize,
+    pub numeric: bool,
+}
+
+const fn def(tag: &'static str, label: &'static str, max_len: usize, numeric: bool) -> TagDef {
+    TagDef { tag, label, max_len, numeric }
+}
+
+/// Largest value the 3-digit length field can describe
+pub const MAX_VALUE_LEN: usize = 999;
+
+/// Tags known to the POS, with the limits we enforce when encoding
+pub const KNOWN_TAGS: &[TagDef] = &[
+    // Request
+    def("CZ", "Version protocole", 4, true),
+    def("CA", "Numéro de caisse", 2, true),
+    def("CE", "Devise", 3, true),
+    def("BA", "Mode de réponse", 1, true),
+    def("CD", "Type de transaction", 1, true),
+    def("CB", "Montant", 12, true),
+    def("TI", "Identifiant transaction", 12, false),
+    def("RF", "Référence transaction initiale", 32, false),
+    def("LB", "Libellé", 32, false),
+    def("CC", "Application carte", 3, true),
+    // Response
+    def("AE", "Statut", 2, true),
+    def("AF", "Motif", 2, true),
+    def("CV", "Code validation", 2, false),
+    def("CO", "Code réponse", 3, false),
+    def("AC", "Numéro d'autorisation", 12, false),
+    def("AL", "Autorisation logiciel", 2, false),
+    def("PA", "Numéro de carte masqué", 19, false),
+    def("MA", "Marque carte", 32, false),
+    def("AI", "Identifiant application (AID)", 32, false),
+    def("ME", "Mode de lecture", 16, false),
+    def("TA", "Référence acquéreur", 32, false),
+    def("RC", "Code retour Nepting", 3, false),
+    def("MT", "Total des débits remis", 12, true),
+    def("MR", "Total des crédits remis", 12, true),
+    def("NT", "Nombre de transactions remises", 6, true),
+    def("TC", "Ticket porteur", MAX_VALUE_LEN, false),
+    def("TM", "Ticket commerçant", MAX_VALUE_LEN, false),
+];
+
+pub fn tag_def(tag: &str) -> Option<&'static TagDef> {
+    KNOWN_TAGS.iter().find(|d| d.tag == tag)
+}
+
+/// An ordered list of TLV fields
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlvMessage {
+    fields: Vec<(String, String)>,
+}
+
+impl TlvMessage {
+    pub fn new() -> Self {
+        TlvMessage::default()
+    }
+    
+    /// Append a field, checking the tag and the value against its definition
+    pub fn push(&mut self, tag: &str, value: &str) -> Result<&mut Self, String> {
+        if tag.len() != 2 || !tag.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("Tag TLV invalide: '{}'", tag));
+        }
+        if !value.is_ascii() {
+            return Err(format!("{}: caractères non ASCII dans '{}'", tag, value));
+        }
+        if value.len() > MAX_VALUE_LEN {
+            return Err(format!("{}: valeur trop longue ({} > {})", tag, value.len(), MAX_VALUE_LEN));
+        }
+        if let Some(def) = tag_def(tag) {
+            if value.len() > def.max_len {
+                return Err(format!("{} ({}): valeur trop longue ({} > {})", tag, def.label, value.len(), def.max_len));
+            }
+            if def.numeric && !value.chars().all(|c| c.is_ascii_digit()) {
+                return Err(format!("{} ({}): valeur non numérique '{}'", tag, def.label, value));
+            }
+        }
+        self.fields.push((tag.to_string(), value.to_string()));
+        Ok(self)
+    }
+    
+    /// Value of the first field with this tag
+    pub fn get(&self, tag: &str) -> Option<&str> {
+        self.fields.iter().find(|(t, _)| t == tag).map(|(_, v)| v.as_str())
+    }
+    
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+    
+    pub fn encode(&self) -> String {
+        self.fields
+            .iter()
+            .map(|(tag, value)| format!("{}{:03}{}", tag, value.len(), value))
+            .collect()
+    }
+}
+
+/// Decode a TLV body. Fails on a malformed length or a truncated value.
+pub fn decode(bytes: &[u8]) -> Result<TlvMessage, String> {
+    let mut message = TlvMessage::new();
+    let mut p = 0;
+    
+    while p < bytes.len() {
+        if p + 5 > bytes.len() {
+            return Err(format!("TLV tronqué à l'octet {}", p));
+        }
+        let tag = std::str::from_utf8(&bytes[p..p+2])
+            .map_err(|_| format!("Tag TLV illisible à l'octet {}", p))?;
+        let value_len = std::str::from_utf8(&bytes[p+2..p+5])
+            .ok()
+            .filter(|l| l.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|l| l.parse::<usize>().ok())
+            .ok_or_else(|| format!("Longueur TLV invalide pour {} à l'octet {}", tag, p))?;
+        let end = p + 5 + value_len;
+        if end > bytes.len() {
+            return Err(format!("{}: valeur tronquée ({} octets annoncés)", tag, value_len));
+        }
+        let value = String::from_utf8_lossy(&bytes[p+5..end]).to_string();
+        message.fields.push((tag.to_string(), value));
+        p = end;
+    }
+    
+    Ok(message)
+}
+
+/// Decode the TLV body of a frame, stripping STX..ETX(+LRC) framing when present
+pub fn decode_frame(data: &[u8]) -> Result<TlvMessage, String> {
+    let stx = data.iter().position(|&b| b == crate::protocols::STX);
+    let body = match stx {
+        Some(s) => {
+            let e = data[s..]
+                .iter()
+                .position(|&b| b == crate::protocols::ETX)
+                .map(|e| s + e)
+                .unwrap_or(data.len());
+            &data[s+1..e]
+        }
+        None => data,
+    };
+    
+    // Unframed TCP answers may end with control characters (ACK, EOT...)
+    let end = body.iter().rposition(|&b| b >= 0x20).map(|i| i + 1).unwrap_or(0);
+    decode(&body[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn message(fields: &[(&str, &str)]) -> TlvMessage {
+        let mut msg = TlvMessage::new();
+        for (tag, value) in fields {
+            msg.push(tag, value).unwrap();
+        }
+        msg
+    }
+    
+    #[test]
+    fn round_trip_request() {
+        let msg = message(&[("CZ", "0320"), ("CA", "01"), ("CB", "000000001250"), ("RF", "AB12"), ("LB", "CAISSE")]);
+        let encoded = msg.encode();
+        assert_eq!(encoded, "CZ0040320CA00201CB012000000001250RF004AB12LB006CAISSE");
+        assert_eq!(decode(encoded.as_bytes()).unwrap(), msg);
+    }
+    
+    #[test]
+    fn round_trip_empty_and_unknown_tags() {
+        let msg = message(&[("AC", ""), ("ZZ", "free text with AE002 10 inside")]);
+        assert_eq!(decode(msg.encode().as_bytes()).unwrap(), msg);
+    }
+    
+    #[test]
+    fn tag_inside_value_is_not_a_field() {
+        // "AE00210" inside LB must not be read as a status
+        let msg = decode(b"LB007AE00210AE00201").unwrap();
+        assert_eq!(msg.get("LB"), Some("AE00210"));
+        assert_eq!(msg.get("AE"), Some("01"));
+    }
+    
+    #[test]
+    fn rejects_bad_lengths() {
+        assert!(decode(b"AE0021").is_err()); // Truncated value
+        assert!(decode(b"AE0x210").is_err()); // Non numeric length
+        assert!(decode(b"AE00").is_err()); // Truncated header
+    }
+    
+    #[test]
+    fn push_enforces_known_tag_limits() {
+        let mut msg = TlvMessage::new();
+        assert!(msg.push("CA", "123").is_err());
+        assert!(msg.push("CB", "12.50").is_err());
+        assert!(msg.push("A", "x").is_err());
+        assert!(msg.push("RF", &"9".repeat(33)).is_err());
+        assert!(msg.push("ZZ", &"9".repeat(1000)).is_err());
+        assert!(msg.fields().is_empty());
+    }
+    
+    #[test]
+    fn decode_frame_strips_framing() {
+        let body = message(&[("AE", "10"), ("AC", "123456")]).encode();
+        let mut frame = vec![0x02];
+        frame.extend_from_slice(body.as_bytes());
+        frame.push(0x03);
+        frame.push(0x7F);
+        assert_eq!(decode_frame(&frame).unwrap().get("AC"), Some("123456"));
+        
+        let mut unframed = body.clone().into_bytes();
+        unframed.push(0x04);
+        assert_eq!(decode_frame(&unframed).unwrap().get("AE"), Some("10"));
+    }
+}
@@ -7,14 +7,36 @@
 // Protocol: Concert Standard (8 digits)
 
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs::OpenOptions;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::Write;
 use std::time::Duration;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::protocols::{bytes_to_hex, CardApplication, TpeProtocol, TpeTransaction, TransactionType};
+use crate::concert_link::{self, SendOutcome};
+use crate::currency::Currency;
+use crate::nepting;
+use crate::pending::{self, PendingPayment};
+use crate::serial_line::SerialSettings;
+use crate::tlv;
+use crate::tpe_error::TpeError;
+use crate::tpe_ids;
+use crate::tpe_sessions;
+use crate::tpe_timeouts::TpeTimeouts;
+use crate::yavin;
+use tauri::{AppHandle, Emitter};
+
+// Cancellation tokens of in-flight transactions, keyed by payment id
+static TPE_CANCEL_HANDLES: Lazy<Mutex<HashMap<String, CancelToken>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static TPE_PAYMENT_COUNTER: AtomicU32 = AtomicU32::new(0);
 
-// Global cancellation flag to interrupt blocking TPE operations
-static TPE_CANCEL_FLAG: AtomicBool = AtomicBool::new(false);
 // ===================================
 // Types
 // ===================================
@@ -25,23 +47,106 @@ pub struct TpeConfig {
     pub port: String, // Can be "COM3" or "192.168.1.50:8888"
     pub baud_rate: u32,
     pub pos_number: String,
-    pub protocol_version: u8, // 2 = Concert V2 (8 digits), 3 = Concert V3 (10 digits)
+    pub protocol_version: u8, // See TpeProtocol::from_version (2 = Concert V2, 3 = Concert V3 TLV, ...)
+    #[serde(default)]
+    pub terminal_id: Option<String>, // Yavin terminal serial number
+    #[serde(default)]
+    pub api_key: Option<String>, // Yavin Cloud API key
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default)]
+    pub serial: SerialSettings, // Line settings of serial terminals (7E1 by default)
+    #[serde(default)]
+    pub timeouts: TpeTimeouts, // Per-phase time limits (connection, ACK, card wait, test)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TpePaymentRequest {
-    pub amount_cents: u32,
+    pub amount_minor: u32,
     pub payment_mode: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct TpePaymentResponse {
     pub success: bool,
     pub transaction_result: String,
-    pub amount_cents: u32,
+    pub amount_minor: u32, // In minor units of the terminal's currency (cents for EUR, francs for XPF)
     pub authorization_number: Option<String>,
     pub error_message: Option<String>,
     pub raw_response: Option<String>,
+    #[serde(default)]
+    pub masked_pan: Option<String>,
+    #[serde(default)]
+    pub card_brand: Option<String>,
+    #[serde(default)]
+    pub entry_mode: Option<String>, // CONTACTLESS, CHIP, MAGSTRIPE, MANUAL
+    #[serde(default)]
+    pub acquirer_transaction_id: Option<String>,
+    #[serde(default)]
+    pub currency: Option<String>, // Currency reported by the terminal (numeric or alpha code)
+    #[serde(default)]
+    pub hold_reference: Option<String>, // Pre-authorizations: reference to complete or cancel the hold
+    #[serde(default)]
+    pub cardholder_ticket: Vec<String>, // Card ticket lines returned by the terminal (customer copy)
+    #[serde(default)]
+    pub merchant_ticket: Vec<String>, // Merchant copy, when the terminal sends one
+    #[serde(default)]
+    pub tags: HashMap<String, String>, // Every TLV tag returned by the terminal
+    #[serde(default)]
+    pub payment_id: Option<String>, // Id to pass to cancel_tpe_transaction
+    #[serde(default)]
+    pub tpe_transaction_id: Option<String>, // TI sent to the terminal, unique per till
+    #[serde(default)]
+    pub card_application: Option<CardApplication>, // Application used by an approved payment
+    #[serde(default, skip_deserializing)]
+    pub error: Option<TpeError>, // Why the transaction did not go through (refusal, cancellation...)
+}
+
+impl TpePaymentResponse {
+    /// Fill card and acquirer details from the TLV tags returned by the terminal
+    /// AC = authorization number, PA = masked PAN, MA = card brand,
+    /// AI = application identifier (brand fallback), ME = entry mode,
+    /// TA = acquirer transaction id, CE = currency,
+    /// TC / TM = cardholder / merchant ticket text
+    pub(crate) fn with_tags(mut self, tags: &[(String, String)]) -> Self {
+        let tag_value = |tag: &str| {
+            tags.iter()
+                .find(|(t, _)| t == tag)
+                .map(|(_, v)| v.trim())
+                .filter(|v| !v.is_empty())
+        };
+        
+        self.authorization_number = tag_value("AC").map(str::to_string);
+        self.masked_pan = tag_value("PA").map(str::to_string);
+        self.card_brand = tag_value("MA")
+            .map(str::to_string)
+            .or_else(|| tag_value("AI").and_then(brand_from_aid));
+        self.entry_mode = tag_value("ME").map(entry_mode_label);
+        self.acquirer_transaction_id = tag_value("TA").map(str::to_string);
+        self.currency = tag_value("CE").map(str::to_string);
+        self.cardholder_ticket = ticket_lines(tags, "TC");
+        self.merchant_ticket = ticket_lines(tags, "TM");
+        self.tags = tags.iter().cloned().collect();
+        self
+    }
+}
+
+/// Ticket lines, from one field per line or fields holding CR/LF separated lines.
+/// Blank lines inside the ticket are kept, leading and trailing ones dropped.
+fn ticket_lines(tags: &[(String, String)], tag: &str) -> Vec<String> {
+    let text = tags.iter()
+        .filter(|(t, _)| t == tag)
+        .map(|(_, v)| v.replace("\r\n", "\n"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let lines: Vec<String> = text.split(['\r', '\n']).map(|line| line.trim_end().to_string()).collect();
+    
+    let first = lines.iter().position(|line| !line.is_empty());
+    let last = lines.iter().rposition(|line| !line.is_empty());
+    match (first, last) {
+        (Some(first), Some(last)) => lines[first..=last].to_vec(),
+        _ => Vec::new(),
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,73 +154,133 @@ pub struct TpeTestResult {
     pub connected: bool,
     pub message: String,
     pub raw_data: Option<String>,
+    #[serde(default, skip_deserializing)]
+    pub error: Option<TpeError>,
+}
+
+impl TpeTestResult {
+    fn failed(error: TpeError) -> Self {
+        TpeTestResult {
+            connected: false,
+            message: error.to_string(),
+            raw_data: None,
+            error: Some(error),
+        }
+    }
 }
 
 // ===================================
 // Protocol Constants
 // ===================================
 
-const STX: u8 = 0x02;
-const ETX: u8 = 0x03;
-const ACK: u8 = 0x06;
-const NAK: u8 = 0x15;
-const ENQ: u8 = 0x05;
-const EOT: u8 = 0x04;
-const CAN: u8 = 0x18;
+pub(crate) const STX: u8 = 0x02;
+pub(crate) const ETX: u8 = 0x03;
+pub(crate) const ACK: u8 = 0x06;
+pub(crate) const NAK: u8 = 0x15;
+pub(crate) const ENQ: u8 = 0x05;
+pub(crate) const EOT: u8 = 0x04;
+pub(crate) const CAN: u8 = 0x18;
 
 // ===================================
 // Abstraction Layer (Serial vs TCP)
 // ===================================
 
-// Trait object to handle both SerialPort and TcpStream
-trait TpeStream: Read + Write + Send {}
-impl<T: Read + Write + Send> TpeStream for T {}
+// Trait object to handle both the serial port and the TCP socket
+pub(crate) trait TpeStream: AsyncRead + AsyncWrite + Unpin + Send {
+    /// The exchange ended in an unknown state (timeout, cancellation, unreadable
+    /// answer): the terminal may still answer on this connection, never reuse it
+    fn discard(&mut self) {}
+}
+impl TpeStream for TcpStream {}
+impl TpeStream for SerialStream {}
 
-fn connect(connection_str: &str, baud_rate: u32) -> Result<Box<dyn TpeStream>, String> {
+/// TCP keepalive of terminal connections, so a warm connection to a terminal that
+/// was unplugged or lost its network fails its check instead of eating a payment
+const KEEPALIVE_IDLE: Duration = Duration::from_secs(10);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+const KEEPALIVE_RETRIES: u32 = 3;
+
+/// Open the terminal's port (`connect_timeout` only applies to TCP)
+pub(crate) async fn connect(
+    connection_str: &str,
+    baud_rate: u32,
+    serial: &SerialSettings,
+    connect_timeout: Duration,
+) -> Result<Box<dyn TpeStream>, TpeError> {
     let clean_str = connection_str.trim_end_matches("+ASCII");
     // Check if it's an IP address (contains ':')
     if clean_str.contains(':') {
-        connect_tcp(clean_str)
+        connect_tcp(clean_str, connect_timeout).await
     } else {
-        connect_serial(clean_str, baud_rate)
+        connect_serial(clean_str, baud_rate, serial)
     }
 }
 
-fn connect_tcp(address: &str) -> Result<Box<dyn TpeStream>, String> {
+async fn connect_tcp(address: &str, timeout: Duration) -> Result<Box<dyn TpeStream>, TpeError> {
     log_to_file(&format!("Connecting TCP to {}", address));
-    // Standard connection timeout increased to 10s for slow terminals/wakeup
-    match TcpStream::connect_timeout(&address.parse().map_err(|e| format!("Invalid IP: {}", e))?, Duration::from_secs(10)) {
+    match open_tcp(address, timeout).await {
+        Ok(stream) => Ok(Box::new(stream)),
+        Err(e) => {
+            log_to_file(&format!("TCP Error {}: {:?}", address, e));
+            Err(e)
+        }
+    }
+}
+
+/// Open a TCP connection to the terminal (no logging: also used by the session monitor)
+pub(crate) async fn open_tcp(address: &str, timeout: Duration) -> Result<TcpStream, TpeError> {
+    let addr: std::net::SocketAddr = address.parse().map_err(|e| TpeError::InvalidConfig {
+        reason: format!("adresse IP invalide '{}' ({})", address, e),
+    })?;
+    let result = match tokio::time::timeout(timeout, TcpStream::connect(addr)).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("délai de {} s dépassé", timeout.as_secs())),
+    };
+    match result {
         Ok(stream) => {
-            stream.set_read_timeout(Some(Duration::from_secs(10))).ok();
-            stream.set_write_timeout(Some(Duration::from_secs(10))).ok();
             // OPTIMIZATION: Disable Nagle's algorithm for lower latency
             stream.set_nodelay(true).ok();
-            Ok(Box::new(stream))
+            let keepalive = socket2::TcpKeepalive::new()
+                .with_time(KEEPALIVE_IDLE)
+                .with_interval(KEEPALIVE_INTERVAL)
+                .with_retries(KEEPALIVE_RETRIES);
+            if let Err(e) = socket2::SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+                log_to_file(&format!("TCP keepalive not enabled on {}: {}", address, e));
+            }
+            Ok(stream)
         },
-        Err(e) => {
-            let msg = format!("TCP Error {}: {}", address, e);
-            log_to_file(&msg);
-            Err(msg)
-        }
+        Err(reason) => Err(TpeError::ConnectFailed { target: address.to_string(), reason }),
     }
 }
 
-fn connect_serial(port_name: &str, baud_rate: u32) -> Result<Box<dyn TpeStream>, String> {
-    log_to_file(&format!("Opening Serial {} at {}", port_name, baud_rate));
-    serialport::new(port_name, baud_rate)
-        .timeout(Duration::from_secs(3))
-        .data_bits(serialport::DataBits::Seven)
-        .parity(serialport::Parity::Even)
-        .stop_bits(serialport::StopBits::One)
-        .open()
-        .map_err(|e| {
-            let msg = format!("Serial Error {}: {}", port_name, e);
-            log_to_file(&msg);
-            msg
-        })
-        .map(|p| Box::new(p) as Box<dyn TpeStream>)
+fn connect_serial(port_name: &str, baud_rate: u32, serial: &SerialSettings) -> Result<Box<dyn TpeStream>, TpeError> {
+    log_to_file(&format!("Opening Serial {} at {} {}", port_name, baud_rate, serial.label()));
+    let failed = |reason: String| {
+        log_to_file(&format!("Serial Error {}: {}", port_name, reason));
+        TpeError::ConnectFailed { target: port_name.to_string(), reason }
+    };
+    let mut port = serial
+        .configure(tokio_serial::new(port_name, baud_rate))
+        .map_err(|reason| TpeError::InvalidConfig { reason })?
+        .open_native_async()
+        .map_err(|e| failed(e.to_string()))?;
+    serial.apply_control_lines(&mut port).map_err(failed)?;
+    Ok(Box::new(port))
 }
 
+/// Write and flush a whole frame within `timeout`
+pub(crate) async fn send_bytes<S: AsyncWrite + Unpin + ?Sized>(stream: &mut S, data: &[u8], timeout: Duration) -> Result<(), TpeError> {
+    let write = async {
+        stream.write_all(data).await?;
+        stream.flush().await
+    };
+    let reason = match tokio::time::timeout(timeout, write).await {
+        Ok(Ok(())) => return Ok(()),
+        Ok(Err(e)) => format!("échec d'envoi : {}", e),
+        Err(_) => format!("échec d'envoi : délai de {} s dépassé", timeout.as_secs_f64()),
+    };
+    Err(TpeError::ConnectionLost { reason })
+}
 
 // ===================================
 // Logging Helper - Robust TPE Debug Logs
@@ -140,7 +305,7 @@ fn get_log_file_path() -> Option<std::path::PathBuf> {
     Some(std::path::PathBuf::from("ma-caisse-tpe-debug.log"))
 }
 
-fn log_to_file(message: &str) {
+pub(crate) fn log_to_file(message: &str) {
     let timestamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f");
     let log_entry = format!("[{}] {}", timestamp, message);
     
@@ -170,12 +335,12 @@ fn log_to_file(message: &str) {
 
 /// Tauri command to get all TPE logs
 #[tauri::command]
-pub fn get_tpe_logs() -> Result<String, String> {
+pub fn get_tpe_logs() -> Result<String, TpeError> {
     let mut result = String::new();
     
     // Add header with log file location
     if let Some(log_path) = get_log_file_path() {
-        result.push_str(&format!("=== TPE Debug Logs ===\n"));
+        result.push_str("=== TPE Debug Logs ===\n");
         result.push_str(&format!("Log file: {}\n", log_path.display()));
         result.push_str(&format!("Generated: {}\n", chrono::Local::now().format("%Y-%m-%d %H:%M:%S")));
         result.push_str("===========================\n\n");
@@ -206,7 +371,7 @@ pub fn get_tpe_logs() -> Result<String, String> {
 
 /// Tauri command to clear TPE logs
 #[tauri::command]
-pub fn clear_tpe_logs() -> Result<String, String> {
+pub fn clear_tpe_logs() -> Result<String, TpeError> {
     // Clear memory buffer
     if let Ok(mut buffer) = TPE_LOG_BUFFER.lock() {
         buffer.clear();
@@ -221,621 +386,1022 @@ pub fn clear_tpe_logs() -> Result<String, String> {
     Ok("Logs cleared".to_string())
 }
 
-fn bytes_to_hex(data: &[u8]) -> String {
-    data.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
-}
-
-fn calculate_lrc(data: &[u8]) -> u8 {
-    data.iter().fold(0u8, |acc, &byte| acc ^ byte)
-}
-
 // ===================================
 // TPE Commands
 // ===================================
 
 #[tauri::command]
-pub async fn test_tpe_connection(port_name: String, baud_rate: u32) -> TpeTestResult {
+pub async fn test_tpe_connection(
+    port_name: String,
+    baud_rate: u32,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> TpeTestResult {
     log_to_file(&format!("=== TEST CONNECTION {} ===", port_name));
     
-    let result = tokio::task::spawn_blocking(move || {
-        let clean_str = port_name.trim_end_matches("+ASCII");
-        let is_tcp = clean_str.contains(':');
-        
-        let stream_res = connect(&port_name, baud_rate);
-        
-        match stream_res {
-            Ok(mut stream) => {
-                log_to_file("Connection opened");
-                
-                // For TCP (Nepting), just verify connection works
-                // The TPE may not respond to ENQ as it uses TLV protocol
-                if is_tcp {
-                    log_to_file("TCP connection successful (Nepting mode)");
-                    return TpeTestResult {
-                        connected: true,
-                        message: "Connected to TPE via TCP ✓".to_string(),
-                        raw_data: None,
-                    };
-                }
-                
-                // For Serial (Concert), send ENQ and expect ACK
-                if let Err(e) = stream.write_all(&[ENQ]) {
-                    return TpeTestResult {
-                        connected: false,
-                        message: format!("Write Error: {}", e),
-                        raw_data: None,
-                    };
-                }
-                let _ = stream.flush();
-                
-                std::thread::sleep(Duration::from_millis(300));
-                
-                let mut buffer = [0u8; 64];
-                match stream.read(&mut buffer) {
-                    Ok(n) if n > 0 => {
-                        let hex = bytes_to_hex(&buffer[..n]);
-                        log_to_file(&format!("Response: {}", hex));
-                        
-                        TpeTestResult {
-                            connected: true,
-                            message: if buffer[0] == ACK {
-                                "Connected - ACK Received ✓".to_string()
-                            } else {
-                                format!("Connected - Response: {}", hex)
-                            },
-                            raw_data: Some(hex),
-                        }
-                    }
-                    Ok(_) => TpeTestResult {
-                        connected: true,
-                        message: "Connected, no data received".to_string(),
-                        raw_data: None,
-                    },
-                    Err(e) => {
-                        log_to_file(&format!("Read error: {}", e));
-                        TpeTestResult {
-                            connected: false,
-                            message: format!("Error: {}", e),
-                            raw_data: None,
-                        }
-                    }
+    let clean_str = port_name.trim_end_matches("+ASCII").trim();
+    let timeouts = timeouts.unwrap_or_default();
+    if let Err(reason) = timeouts.validate() {
+        return TpeTestResult::failed(TpeError::InvalidConfig { reason });
+    }
+    
+    // For TCP (Nepting), just verify connection works through the terminal's session
+    // (reuses the warm connection when there is one). The TPE may not respond to ENQ
+    // as it uses TLV protocol
+    if clean_str.contains(':') {
+        return match tpe_sessions::probe(clean_str, timeouts.connect()).await {
+            Ok(()) => {
+                log_to_file("TCP connection successful (Nepting mode)");
+                TpeTestResult {
+                    connected: true,
+                    message: "Connected to TPE via TCP ✓".to_string(),
+                    raw_data: None,
+                    error: None,
                 }
             }
-            Err(e) => TpeTestResult {
-                connected: false,
-                message: e,
-                raw_data: None,
-            },
+            Err(e) => TpeTestResult::failed(e),
+        };
+    }
+    
+    let mut stream = match connect(clean_str, baud_rate, &serial.unwrap_or_default(), timeouts.connect()).await {
+        Ok(stream) => stream,
+        Err(e) => return TpeTestResult::failed(e),
+    };
+    log_to_file("Connection opened");
+    
+    // For Serial (Concert), send ENQ and expect ACK
+    if let Err(e) = send_bytes(&mut stream, &[ENQ], timeouts.write()).await {
+        return TpeTestResult::failed(e);
+    }
+    
+    let mut buffer = [0u8; 64];
+    match tokio::time::timeout(timeouts.test(), stream.read(&mut buffer)).await {
+        Ok(Ok(n)) if n > 0 => {
+            let hex = bytes_to_hex(&buffer[..n]);
+            log_to_file(&format!("Response: {}", hex));
+            
+            TpeTestResult {
+                connected: true,
+                message: if buffer[0] == ACK {
+                    "Connected - ACK Received ✓".to_string()
+                } else {
+                    format!("Connected - Response: {}", hex)
+                },
+                raw_data: Some(hex),
+                error: None,
+            }
         }
-    }).await;
-
-    match result {
-        Ok(res) => res,
-        Err(e) => TpeTestResult {
-            connected: false,
-            message: format!("Thread error: {}", e),
+        Ok(Ok(_)) | Err(_) => TpeTestResult {
+            connected: true,
+            message: "Connected, no data received".to_string(),
             raw_data: None,
+            error: None,
+        },
+        Ok(Err(e)) => {
+            log_to_file(&format!("Read error: {}", e));
+            TpeTestResult::failed(TpeError::ConnectionLost { reason: e.to_string() })
         }
     }
 }
 
-fn build_payment_message(amount_cents: u32, pos_number: &str, protocol_version: u8) -> Vec<u8> {
-    // Safely handle pos_number to be exactly 2 digits
-    let pos_num = if pos_number.len() >= 2 { 
-        pos_number[..2].to_string() 
-    } else if pos_number.len() == 1 { 
-        format!("0{}", pos_number) 
-    } else { 
-        "01".to_string() 
-    };
-    
-    let data = if protocol_version == 2 {
-        // Concert V3 TLV Format (FIT302 documentation)
-        // Format: TAG + LENGTH(3 digits) + DATA
-        // STX + TLV_DATA + ETX + LRC pour USB série
-        
-        // CZ: Version protocole = "0300" (fixe)
-        let cz = "CZ0040300";
-        
-        // CJ: Identifiant protocole caisse (12 chars)
-        let cj = "CJ012000000000001";
-        
-        // CA: N° de caisse (2 chars)
-        let ca = format!("CA002{:02}", pos_num);
-        
-        // CB: Montant en centimes (variable, 2-12 chars)
-        let amount_str = format!("{}", amount_cents);
-        let cb = format!("CB{:03}{}", amount_str.len(), amount_str);
-        
-        // CD: Type action = 0 (débit)
-        let cd = "CD0010";
-        
-        // CE: Devise = 978 (EUR)
-        let ce = "CE003978";
-        
-        format!("{}{}{}{}{}{}", cz, cj, ca, cb, cd, ce)
-    } else if protocol_version == 3 {
-        // Concert V3 serial format (13 chars):
-        // Essai SANS type de transaction - format série simple
-        //   N° caisse: 2 chars  
-        //   Amount: 8 chars (centimes)
-        //   Currency: 3 chars
-        // Total: 13 chars
-        let amount = format!("{:08}", amount_cents);
-        format!("{}{}{}", pos_num, amount, "978")
-    } else if protocol_version == 4 {
-        // Concert V3 TLV format (Caisse-AP style)
-        // TPE uses Concert V3 spec 3.01 (not 3.20!)
-        fn tlv(tag: &str, value: &str) -> String {
-            format!("{}{:03}{}", tag, value.len(), value)
+// ===================================
+// Progress Events
+// ===================================
+
+/// Event name listened to by the PaymentModal
+const TPE_PROGRESS_EVENT: &str = "tpe-progress";
+
+/// Protocol phase of a transaction, as shown to the cashier
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TpePhase {
+    Connecting,
+    Handshake,
+    Sending,
+    WaitingAck,
+    WaitingCard,
+    PinEntry,
+    Authorizing,
+    FrameReceived,
+    Completed,
+    Cancelled,
+}
+
+impl TpePhase {
+    fn message(&self) -> &'static str {
+        match self {
+            TpePhase::Connecting => "Connexion au TPE…",
+            TpePhase::Handshake => "Établissement de la liaison…",
+            TpePhase::Sending => "Envoi du montant au TPE…",
+            TpePhase::WaitingAck => "Attente de l'accusé de réception…",
+            TpePhase::WaitingCard => "Insérez, présentez ou glissez la carte",
+            TpePhase::PinEntry => "Saisie du code PIN…",
+            TpePhase::Authorizing => "Autorisation en cours…",
+            TpePhase::FrameReceived => "Réponse du TPE reçue",
+            TpePhase::Completed => "Transaction terminée",
+            TpePhase::Cancelled => "Annulation en cours…",
         }
-        
-        let mut msg = String::new();
-        msg.push_str(&tlv("CZ", "0301")); // VERSION 3.01, not 3.20!
-        msg.push_str(&tlv("CA", &pos_num));
-        msg.push_str(&tlv("CE", "978"));
-        msg.push_str(&tlv("CD", "0"));
-        msg.push_str(&tlv("CB", &format!("{:012}", amount_cents)));
-        msg.push_str(&tlv("TI", &format!("{:06}", std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs() % 1000000)));
-        
-        msg
-    } else {
-        // Default: SmilePay or other - use V3 binary
-        let tx_type = "00";
-        let amount = format!("{:012}", amount_cents);
-        format!("{}{}{}{}", tx_type, pos_num, amount, "978")
-    };
-    
-    println!("Building Concert V{} message ({}chars): {}", protocol_version, data.len(), data);
-    log_to_file(&format!("Concert V{} message ({}chars): {}", protocol_version, data.len(), data));
-    
-    // LRC = XOR de DATA + ETX (protocole Ingenico Concert V3)
-    let mut lrc_input: Vec<u8> = data.as_bytes().to_vec();
-    lrc_input.push(ETX);
-    let lrc = calculate_lrc(&lrc_input);
-    
-    let mut msg: Vec<u8> = Vec::new();
-    msg.push(STX);
-    msg.extend_from_slice(data.as_bytes());
-    msg.push(ETX);
-    msg.push(lrc);
-    
-    msg
+    }
 }
 
-/// Build a Caisse-AP over IP message (Concert V3 on TCP)
-/// Based on official protocol: https://github.com/akretion/caisse-ap-ip
-/// Format: TAG(2 letters) + LENGTH(3 digits) + VALUE
-/// Wrapped in STX ... ETX+LRC
-fn build_caisse_ap_ip_message(amount_cents: u32, pos_id: &str) -> Vec<u8> {
-    // Helper to create a TLV field
-    fn tlv(tag: &str, value: &str) -> String {
-        format!("{}{:03}{}", tag, value.len(), value)
+/// Payload of the "tpe-progress" event
+#[derive(Debug, Serialize, Clone)]
+pub struct TpeProgressEvent {
+    pub payment_id: String,
+    pub phase: TpePhase,
+    pub message: String,
+    pub frame: Option<String>, // Hex dump of the received frame
+}
+
+/// Emits progress events tagged with the payment id (silent without an app handle)
+#[derive(Clone)]
+pub struct ProgressReporter {
+    app: Option<AppHandle>,
+    payment_id: String,
+}
+
+impl ProgressReporter {
+    fn new(app: Option<AppHandle>, payment_id: &str) -> Self {
+        ProgressReporter { app, payment_id: payment_id.to_string() }
     }
     
-    let mut msg = String::new();
-    
-    // CZ = Protocol version (must be first!) - "0320" for version 3.2 (was 0300)
-    msg.push_str(&tlv("CZ", "0320"));
-    
-    // CJ = Concert Protocol Identifier (Standard)
-    // REMOVED: Sending a specific CJ might cause timeouts if it doesn't match the TPE's expected ID.
-    // Let the TPE use its default.
-    // msg.push_str(&tlv("CJ", "012345678901"));
+    /// Reporter that emits nothing (tests, protocol detection)
+    pub(crate) fn silent() -> Self {
+        ProgressReporter { app: None, payment_id: String::new() }
+    }
     
-    // CA = POS number (caisse number)
-    // Reverted to standard "01" after testing "1".
-    let ca = if pos_id.is_empty() { "01" } else { pos_id };
-    msg.push_str(&tlv("CA", ca));
+    pub(crate) fn phase(&self, phase: TpePhase) {
+        self.emit(phase, None);
+    }
     
-    // CE = Currency ISO number (978 = EUR)
-    msg.push_str(&tlv("CE", "978"));
+    /// Report a frame received from the terminal, plus the phase its display text suggests
+    pub(crate) fn frame(&self, data: &[u8]) {
+        self.emit(TpePhase::FrameReceived, Some(bytes_to_hex(data)));
+        
+        let text = String::from_utf8_lossy(data).to_uppercase();
+        if text.contains("PIN") || text.contains("CODE") {
+            self.phase(TpePhase::PinEntry);
+        } else if text.contains("AUTORIS") || text.contains("APPEL") {
+            self.phase(TpePhase::Authorizing);
+        }
+    }
     
-    // BA = Answer mode: "0" = answer at end of transaction
-    // "1" failed (timeout/no response). Reverting to "0".
-    msg.push_str(&tlv("BA", "0"));
+    fn emit(&self, phase: TpePhase, frame: Option<String>) {
+        let Some(app) = &self.app else { return };
+        let event = TpeProgressEvent {
+            payment_id: self.payment_id.clone(),
+            phase,
+            message: phase.message().to_string(),
+            frame,
+        };
+        if let Err(e) = app.emit(TPE_PROGRESS_EVENT, event) {
+            log_to_file(&format!("Failed to emit progress event: {:?}", e));
+        }
+    }
+}
+
+// ===================================
+// Cancellation Handles
+// ===================================
+
+/// Cancellation signal of one transaction. It can be awaited, so a pending read
+/// or HTTP call is interrupted as soon as the cashier cancels.
+#[derive(Clone)]
+pub struct CancelToken(Arc<watch::Sender<bool>>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken(Arc::new(watch::channel(false).0))
+    }
     
-    // CD = Transaction type: "0" = debit
-    msg.push_str(&tlv("CD", "0"));
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
     
-    // CB = Amount in cents (12 digits padded)
-    let amount_str = format!("{:012}", amount_cents);
-    msg.push_str(&tlv("CB", &amount_str));
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
     
-    // TI = Transaction ID (Numeric only - 6 digits)
-    let tx_id = format!("{:06}", std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs() % 1000000);
-    msg.push_str(&tlv("TI", &tx_id));
+    /// Resolves once the transaction is cancelled (immediately if it already is)
+    pub async fn cancelled(&self) {
+        let mut receiver = self.0.subscribe();
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+}
+
+impl Default for CancelToken {
+    fn default() -> Self {
+        CancelToken::new()
+    }
+}
+
+/// Cancellation token of one transaction, registered under its payment id
+/// for as long as the handle lives
+struct CancelHandle {
+    payment_id: String,
+    token: CancelToken,
+}
+
+impl CancelHandle {
+    fn register(payment_id: Option<String>) -> Self {
+        let payment_id = payment_id
+            .filter(|id| !id.trim().is_empty())
+            .unwrap_or_else(new_payment_id);
+        let token = CancelToken::new();
+        if let Ok(mut handles) = TPE_CANCEL_HANDLES.lock() {
+            handles.insert(payment_id.clone(), token.clone());
+        }
+        log_to_file(&format!("Payment id: {}", payment_id));
+        CancelHandle { payment_id, token }
+    }
+}
+
+impl Drop for CancelHandle {
+    fn drop(&mut self) {
+        if let Ok(mut handles) = TPE_CANCEL_HANDLES.lock() {
+            handles.remove(&self.payment_id);
+        }
+    }
+}
+
+fn new_payment_id() -> String {
+    let n = TPE_PAYMENT_COUNTER.fetch_add(1, Ordering::SeqCst);
+    format!("tpe-{}-{}", chrono::Local::now().format("%Y%m%d%H%M%S%3f"), n)
+}
+
+/// Cancel the transaction registered under `payment_id`. Only that one: the
+/// other terminals of the shop keep their payments.
+#[tauri::command]
+pub fn cancel_tpe_transaction(payment_id: String) -> Result<String, TpeError> {
+    log_to_file(&format!("Requesting TPE cancellation ({})...", payment_id));
     
-    // LB = Label on ticket (Optional)
-    msg.push_str(&tlv("LB", "CAISSE"));
+    let handles = TPE_CANCEL_HANDLES.lock().unwrap_or_else(|e| e.into_inner());
+    match handles.get(&payment_id) {
+        Some(token) => {
+            token.cancel();
+            Ok("Cancellation requested".to_string())
+        }
+        None => Err(TpeError::UnknownPayment { payment_id }),
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_tpe_payment(
+    app: AppHandle,
+    port_name: String,
+    baud_rate: u32,
+    pos_number: String,
+    protocol_version: u8,
+    amount_minor: u32,
+    terminal_id: Option<String>,
+    api_key: Option<String>,
+    pos_transaction_id: Option<String>,
+    payment_id: Option<String>,
+    currency: Option<Currency>,
+    serial: Option<SerialSettings>,
+    card_application: Option<CardApplication>,
+    timeouts: Option<TpeTimeouts>,
+) -> Result<TpePaymentResponse, TpeError> {
+    log_to_file(&format!("=== PAY {} minor units on {} ===", amount_minor, port_name));
+    let config = TpeConfig {
+        name: String::new(),
+        port: port_name,
+        baud_rate,
+        pos_number,
+        protocol_version,
+        terminal_id,
+        api_key,
+        currency: currency.unwrap_or_default(),
+        serial: serial.unwrap_or_default(),
+        timeouts: timeouts.unwrap_or_default(),
+    };
+    pay(app, config, amount_minor, pos_transaction_id, payment_id, card_application.unwrap_or_default()).await
+}
+
+/// Debit on a configured terminal (ports suffixed "+ASCII" use the plain text fallback)
+pub(crate) async fn pay(
+    app: AppHandle,
+    config: TpeConfig,
+    amount_minor: u32,
+    pos_transaction_id: Option<String>,
+    payment_id: Option<String>,
+    application: CardApplication,
+) -> Result<TpePaymentResponse, TpeError> {
+    // Explicit ASCII mode requested (legacy fallback): the plain text command has no application field
+    if config.port.ends_with("+ASCII") && application != CardApplication::Any {
+        return Err(TpeError::Unsupported {
+            protocol: "Mode ASCII".to_string(),
+            operation: format!("choix de l'application {}", application.label()),
+        });
+    }
     
-    println!("Built Caisse-AP IP message Data ({}bytes): {}", msg.len(), msg);
+    let tx = TpeTransaction::debit(amount_minor, &config.pos_number)
+        .with_pos_transaction_id(pos_transaction_id)
+        .with_application(application);
+    execute_transaction(Some(app), config, tx, payment_id).await
+}
+
+/// Send a credit (remboursement) back to the card, referencing the original transaction
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_tpe_refund(
+    app: AppHandle,
+    port_name: String,
+    baud_rate: u32,
+    pos_number: String,
+    protocol_version: u8,
+    amount_minor: u32,
+    original_reference: Option<String>,
+    payment_id: Option<String>,
+    currency: Option<Currency>,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> Result<TpePaymentResponse, TpeError> {
+    log_to_file(&format!(
+        "=== REFUND {} minor units on {} (ref: {:?}) ===",
+        amount_minor, port_name, original_reference
+    ));
     
-    // Calculate LRC (XOR of all bytes in Data + ETX)
-    let mut lrc_input: Vec<u8> = msg.as_bytes().to_vec();
-    lrc_input.push(ETX);
-    let lrc = calculate_lrc(&lrc_input);
+    if port_name.ends_with("+ASCII") {
+        return Err(TpeError::Unsupported {
+            protocol: "Mode ASCII".to_string(),
+            operation: TransactionType::Credit.label().to_string(),
+        });
+    }
     
-    let mut framed_msg: Vec<u8> = Vec::new();
-    framed_msg.push(STX);
-    framed_msg.extend_from_slice(msg.as_bytes());
-    framed_msg.push(ETX);
-    framed_msg.push(lrc);
+    let tx = TpeTransaction::credit(amount_minor, &pos_number, original_reference);
+    let config = direct_config(port_name, baud_rate, pos_number, protocol_version, currency, serial, timeouts);
+    execute_transaction(Some(app), config, tx, payment_id).await
+}
+
+// ===================================
+// Pre-authorization Commands
+// ===================================
+// Hold on a card (deposits), later captured in whole or part, or released.
+// Only the TLV protocols (Concert V3 TLV, Caisse-AP IP, SmilePay) carry them.
+
+/// Put a hold of `amount_minor` on the card. The response carries the
+/// hold_reference to pass to the completion or the cancellation.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_tpe_preauthorization(
+    app: AppHandle,
+    port_name: String,
+    baud_rate: u32,
+    pos_number: String,
+    protocol_version: u8,
+    amount_minor: u32,
+    pos_transaction_id: Option<String>,
+    payment_id: Option<String>,
+    currency: Option<Currency>,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> Result<TpePaymentResponse, TpeError> {
+    log_to_file(&format!("=== PRE-AUTH {} minor units on {} ===", amount_minor, port_name));
     
-    framed_msg
+    let tx = TpeTransaction::pre_authorization(amount_minor, &pos_number).with_pos_transaction_id(pos_transaction_id);
+    let config = direct_config(port_name, baud_rate, pos_number, protocol_version, currency, serial, timeouts);
+    execute_transaction(Some(app), config, tx, payment_id).await
 }
 
+/// Capture `amount_minor` (at most the held amount) from a pre-authorization
 #[tauri::command]
-pub fn cancel_tpe_transaction() -> Result<String, String> {
-    println!("Requesting TPE cancellation...");
-    log_to_file("Requesting TPE cancellation...");
-    TPE_CANCEL_FLAG.store(true, Ordering::SeqCst);
-    Ok("Cancellation requested".to_string())
+#[allow(clippy::too_many_arguments)]
+pub async fn complete_tpe_preauthorization(
+    app: AppHandle,
+    port_name: String,
+    baud_rate: u32,
+    pos_number: String,
+    protocol_version: u8,
+    amount_minor: u32,
+    hold_reference: String,
+    pos_transaction_id: Option<String>,
+    payment_id: Option<String>,
+    currency: Option<Currency>,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> Result<TpePaymentResponse, TpeError> {
+    log_to_file(&format!(
+        "=== PRE-AUTH COMPLETION {} minor units on {} (ref: {}) ===",
+        amount_minor, port_name, hold_reference
+    ));
+    
+    let hold_reference = require_hold_reference(&hold_reference)?;
+    let tx = TpeTransaction::completion(amount_minor, &pos_number, hold_reference)
+        .with_pos_transaction_id(pos_transaction_id);
+    let config = direct_config(port_name, baud_rate, pos_number, protocol_version, currency, serial, timeouts);
+    execute_transaction(Some(app), config, tx, payment_id).await
 }
 
+/// Release a pre-authorization without capturing anything
 #[tauri::command]
-pub async fn send_tpe_payment(
+#[allow(clippy::too_many_arguments)]
+pub async fn cancel_tpe_preauthorization(
+    app: AppHandle,
     port_name: String,
     baud_rate: u32,
     pos_number: String,
     protocol_version: u8,
-    amount_cents: u32,
-) -> Result<TpePaymentResponse, String> {
-    // Reset cancellation flag at start of new transaction
-    TPE_CANCEL_FLAG.store(false, Ordering::SeqCst);
+    amount_minor: u32,
+    hold_reference: String,
+    payment_id: Option<String>,
+    currency: Option<Currency>,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> Result<TpePaymentResponse, TpeError> {
+    log_to_file(&format!(
+        "=== PRE-AUTH CANCELLATION {} minor units on {} (ref: {}) ===",
+        amount_minor, port_name, hold_reference
+    ));
     
-    log_to_file(&format!("=== PAY {} cents on {} ===", amount_cents, port_name));
+    let hold_reference = require_hold_reference(&hold_reference)?;
+    let tx = TpeTransaction::pre_auth_cancellation(amount_minor, &pos_number, hold_reference);
+    let config = direct_config(port_name, baud_rate, pos_number, protocol_version, currency, serial, timeouts);
+    execute_transaction(Some(app), config, tx, payment_id).await
+}
+
+fn require_hold_reference(hold_reference: &str) -> Result<&str, TpeError> {
+    let hold_reference = hold_reference.trim();
+    if hold_reference.is_empty() {
+        return Err(TpeError::InvalidRequest { reason: "référence de pré-autorisation manquante".to_string() });
+    }
+    Ok(hold_reference)
+}
+
+/// Terminal settings passed directly by a command (no Yavin credentials)
+fn direct_config(
+    port_name: String,
+    baud_rate: u32,
+    pos_number: String,
+    protocol_version: u8,
+    currency: Option<Currency>,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> TpeConfig {
+    TpeConfig {
+        name: String::new(),
+        port: port_name,
+        baud_rate,
+        pos_number,
+        protocol_version,
+        terminal_id: None,
+        api_key: None,
+        currency: currency.unwrap_or_default(),
+        serial: serial.unwrap_or_default(),
+        timeouts: timeouts.unwrap_or_default(),
+    }
+}
+
+/// Resolve the configured protocol and run the transaction
+/// (without an app handle no progress event is emitted)
+pub(crate) async fn execute_transaction(
+    app: Option<AppHandle>,
+    config: TpeConfig,
+    tx: TpeTransaction,
+    payment_id: Option<String>,
+) -> Result<TpePaymentResponse, TpeError> {
+    // Each transaction gets its own cancellation token and progress stream
+    let cancel = CancelHandle::register(payment_id);
+    let payment_id = cancel.payment_id.clone();
+    let progress = ProgressReporter::new(app, &payment_id);
+    progress.phase(TpePhase::Connecting);
     
-    // Explicit ASCII mode requested (legacy fallback)
-    if port_name.ends_with("+ASCII") {
-        let clean_port = port_name.replace("+ASCII", "");
-        return tokio::task::spawn_blocking(move || {
-             let mut stream = connect(&clean_port, baud_rate)?;
-             try_alternate_format(&mut stream, amount_cents)
-         }).await.map_err(|e| format!("Thread error: {}", e))?;
+    // Strip legacy +ASCII suffix and hidden whitespace
+    let ascii = config.port.ends_with("+ASCII");
+    let connection_addr = config.port.trim_end_matches("+ASCII").trim().to_string();
+    let is_tcp = connection_addr.contains(':');
+    let baud_rate = config.baud_rate;
+    
+    // The configured protocol picks framing, handshake and response parser
+    let protocol = TpeProtocol::from_version(config.protocol_version).for_transport(is_tcp);
+    log_to_file(&format!("Protocol: {} (version {}) over {}, {:?}", protocol.name(), config.protocol_version, if is_tcp { "TCP" } else { "serial" }, tx.tx_type));
+    
+    config.currency.validate().map_err(|reason| TpeError::InvalidConfig { reason })?;
+    config.timeouts.validate().map_err(|reason| TpeError::InvalidConfig { reason })?;
+    let timeouts = config.timeouts.clone();
+    if let Some(operation) = protocol.unsupported_operation(&tx) {
+        return Err(TpeError::Unsupported { protocol: protocol.name().to_string(), operation });
     }
     
-    // Check if TCP connection (IP:port format)
-    // Strip legacy +ASCII suffix if present
-    let clean_str = port_name.trim_end_matches("+ASCII").trim();
-    let is_tcp = clean_str.contains(':');
+    // Unique per till, so terminal logs match our sales one to one
+    let tpe_transaction_id = tpe_ids::next_id(&tx.pos_number, tx.pos_transaction_id.as_deref())
+        .map_err(|reason| TpeError::Storage { reason })?;
+    let currency = config.currency.clone();
+    let tx = tx
+        .with_currency(currency.clone())
+        .with_tpe_transaction_id(tpe_transaction_id.clone());
     
-    // TCP connections use Caisse-AP over IP (ASCII TLV format)
-    if is_tcp {
-        println!("--- CAISSE-AP IP MODE ---");
-        let pos_number_clone = pos_number.clone();
-        let connection_addr = clean_str.to_string();
-        
-        // Debug: Log exact bytes of address to catch hidden chars
-        println!("Connecting to address: '{}' (Bytes: {:?})", connection_addr, connection_addr.as_bytes());
-        log_to_file(&format!("Connecting to address: '{}'", connection_addr));
-        
-        return tokio::task::spawn_blocking(move || {
-            // Use the CLEAN address for connection
-            let mut stream = connect(&connection_addr, 0)?; // baud_rate ignored for TCP
-            
-            // Always use standard Caisse-AP (Concert V3)
-            println!("--- CAISSE-AP (CONCERT) MODE ---");
-            let message_bytes = build_caisse_ap_ip_message(amount_cents, &pos_number_clone);
-            
-            println!("Sending Payment Request ({} bytes)", message_bytes.len());
-            // Log hex for debugging
-            log_to_file(&format!("Sending Hex: {}", bytes_to_hex(&message_bytes)));
-            
-            stream.write_all(&message_bytes).map_err(|e| format!("Send failed: {}", e))?;
-            let _ = stream.flush();
-            
-            // Wait for response (up to 150 seconds for payment to allow user interaction)
-            println!("Waiting for Caisse-AP response...");
-            log_to_file("Waiting for Caisse-AP response...");
-            
-            let mut response_buf = [0u8; 1024];
-            let mut total_read = 0;
-            let start = std::time::Instant::now();
-            let timeout = Duration::from_secs(150);
-            
-            while start.elapsed() < timeout {
-                if TPE_CANCEL_FLAG.load(Ordering::SeqCst) {
-                     println!("!!! CANCELLATION REQUESTED !!!");
-                     log_to_file("!!! CANCELLATION REQUESTED !!! - Sending CAN sequence");
-                     // Send CAN (0x18) x 3 + EOT (0x04) to force cancel
-                     let _ = stream.write_all(&[CAN, CAN, CAN, EOT]); 
-                     let _ = stream.flush();
-                     return Ok(TpePaymentResponse {
-                         success: false,
-                         transaction_result: "CANCELLED".to_string(),
-                         amount_cents,
-                         authorization_number: None,
-                         error_message: Some("Transaction cancelled by user".to_string()),
-                         raw_response: None,
-                     });
-                }
+    // Recorded by the transport right before the request leaves, once the request is
+    // built and the link is up, so a crash mid-exchange can be recovered
+    // (a reconciliation moves no money: nothing to recover)
+    let journal_entry = tx.tx_type.moves_money().then(|| PendingPayment::new(&payment_id, &config, &tx));
+    let journaled = journal_entry.is_some();
+    let tx_type = tx.tx_type;
+    let application = tx.application;
+    
+    let result = if protocol.is_http() {
+        let terminal_id = config.terminal_id.unwrap_or_default();
+        let card_wait = timeouts.card_wait(protocol);
+        match protocol {
+            TpeProtocol::YavinLocal => {
+                yavin::run_local_payment(&connection_addr, &terminal_id, &tx, journal_entry, card_wait, &cancel.token, &progress).await
+            }
+            _ => {
+                yavin::run_cloud_payment(
+                    &connection_addr,
+                    &config.api_key.unwrap_or_default(),
+                    &terminal_id,
+                    &tx,
+                    journal_entry,
+                    card_wait,
+                    &cancel.token,
+                    &progress,
+                ).await
+            }
+        }
+    } else {
+        async {
+            // Network terminals go through their session (warm connection when the protocol keeps it).
+            // The plain text fallback opens its own connection.
+            let mut stream = if is_tcp && !ascii {
+                tpe_sessions::checkout(&connection_addr, protocol.keeps_connection(), timeouts.connect()).await?
+            } else {
+                connect(&connection_addr, baud_rate, &config.serial, timeouts.connect()).await?
+            };
+            if ascii {
+                return try_alternate_format(&mut stream, &tx, journal_entry, &timeouts).await;
+            }
+            let result = run_transaction(&mut stream, protocol, &tx, journal_entry, &timeouts, &cancel.token, &progress)
+                .await
+                .and_then(|response| check_transaction_id(response, &tx));
+            // Only a clean answer leaves the connection reusable: a late answer would
+            // otherwise be read as the answer to the next payment
+            if !matches!(&result, Ok(response) if response.error.is_none()) {
+                stream.discard();
+            }
+            result
+        }.await
+    };
+    
+    // Only a definitive answer clears the journal; errors after sending stay pending
+    // (requests that never left were not recorded, or resolved when their send failed)
+    match &result {
+        Ok(response) if journaled && settles_payment(response) => pending::resolve(&payment_id),
+        Ok(TpePaymentResponse { error: Some(e), .. }) | Err(e) if journaled => pending::mark_failed(&payment_id, &e.to_string()),
+        _ => {}
+    }
+    
+    if cancel.token.is_cancelled() {
+        progress.phase(TpePhase::Cancelled);
+    } else {
+        progress.phase(TpePhase::Completed);
+    }
+    
+    result.map(|response| TpePaymentResponse {
+        payment_id: Some(payment_id),
+        tpe_transaction_id: Some(tpe_transaction_id),
+        ..with_error_code(check_application(check_pre_authorization(check_currency(response, &currency), tx_type), tx_type, application))
+    })
+}
 
-                match stream.read(&mut response_buf[total_read..]) {
-                    Ok(0) => {
-                        if total_read > 0 {
-                            break; // Got data and connection closed
-                        }
-                        std::thread::sleep(Duration::from_millis(10)); // Reduced from 100ms
-                    }
-                    Ok(n) => {
-                        total_read += n;
-                        println!("Received {} bytes: {}", n, String::from_utf8_lossy(&response_buf[..total_read]));
-                        
-                        // Check if we have a complete message (ETX=0x03)
-                        if response_buf[..total_read].contains(&0x03) {
-                             // Give a bit of time for LRC
-                             std::thread::sleep(Duration::from_millis(10)); // Reduced from 200ms
-                             // Try one more read just in case
-                             if let Ok(n2) = stream.read(&mut response_buf[total_read..]) {
-                                 if n2 > 0 { total_read += n2; }
-                             }
-                             break;
-                        }
-                        
-                        // Otherwise continue reading immediately or small sleep if fragmentation likely
-                        // std::thread::sleep(Duration::from_millis(50)); Removed to speed up
-                    }
-                    Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                        std::thread::sleep(Duration::from_millis(10)); // Reduced from 100ms
-                        continue;
-                    }
-                    Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                        std::thread::sleep(Duration::from_millis(10)); // Reduced from 100ms
-                        continue;
-                    }
-                    Err(e) => {
-                        log_to_file(&format!("Read error: {}", e));
-                        return Err(format!("Read error: {}", e));
-                    }
-                }
+/// Does the answer tell what happened to the card? An approval or a refusal does;
+/// a cancellation does not (the terminal may still approve after the CAN), nor does
+/// an answer we could not read
+fn settles_payment(response: &TpePaymentResponse) -> bool {
+    response.success || !matches!(response.error, Some(TpeError::Cancelled) | Some(TpeError::Protocol { .. }))
+}
+
+/// An answer carrying another transaction id (TI) belongs to an earlier exchange,
+/// such as a late answer after a cancellation: it must not settle this one
+fn check_transaction_id(response: TpePaymentResponse, tx: &TpeTransaction) -> Result<TpePaymentResponse, TpeError> {
+    match (response.tags.get("TI"), tx.tpe_transaction_id.as_deref()) {
+        (Some(answered), Some(sent)) if answered.trim() != sent => {
+            log_to_file(&format!("Answer for transaction {} received while waiting for {}", answered, sent));
+            Err(TpeError::Protocol {
+                reason: format!("réponse d'une autre transaction (TI {} au lieu de {})", answered.trim(), sent),
+            })
+        }
+        _ => Ok(response),
+    }
+}
+
+/// A failed answer without a more precise error is a refusal, under the terminal's result code
+fn with_error_code(mut response: TpePaymentResponse) -> TpePaymentResponse {
+    if !response.success && response.error.is_none() {
+        response.error = Some(TpeError::Refused { code: response.transaction_result.clone() });
+    }
+    response
+}
+
+/// Report the card application of an approved payment: the CC tag when the
+/// terminal echoes it, else the card brand, else the requested application.
+/// Card payments default to bank cards.
+fn check_application(mut response: TpePaymentResponse, tx_type: TransactionType, requested: CardApplication) -> TpePaymentResponse {
+    if !response.success || !tx_type.moves_money() {
+        return response;
+    }
+    let used = response.tags.get("CC")
+        .and_then(|code| CardApplication::from_code(code))
+        .or_else(|| response.card_brand.as_deref().and_then(application_from_brand))
+        .unwrap_or(match requested {
+            CardApplication::Any => CardApplication::BankCard,
+            requested => requested,
+        });
+    if requested != CardApplication::Any && used != requested {
+        log_to_file(&format!("Application mismatch: requested {:?}, terminal used {:?}", requested, used));
+    }
+    response.card_application = Some(used);
+    response
+}
+
+/// Fill the hold reference of an approved pre-authorization and the amount
+/// actually captured by a completion (CB tag), when the terminal reports it.
+/// The hold is referenced by RF when echoed, else by the acquirer reference (TA),
+/// else by the authorization number.
+fn check_pre_authorization(mut response: TpePaymentResponse, tx_type: TransactionType) -> TpePaymentResponse {
+    if !response.success {
+        return response;
+    }
+    match tx_type {
+        TransactionType::PreAuthorization => {
+            response.hold_reference = response.tags.get("RF")
+                .cloned()
+                .or_else(|| response.acquirer_transaction_id.clone())
+                .or_else(|| response.authorization_number.clone())
+                .filter(|r| !r.trim().is_empty());
+            if response.hold_reference.is_none() {
+                log_to_file("Pre-authorization approved without any reference");
+                response.error_message = Some("Pré-autorisation acceptée mais le TPE n'a renvoyé aucune référence".to_string());
             }
-            
-            if total_read > 0 {
-                // IMPORTANT: Some terminals expect an ACK after sending their response
-                // otherwise they might consider the transaction as failed/refused.
-                println!("Sending ACK (+EOT) to confirm receipt...");
-                let _ = stream.write_all(&[0x06, 0x04]); // ACK + EOT
-                let _ = stream.flush();
-                std::thread::sleep(Duration::from_millis(100)); // Reduced from 500ms
-                
-                let response_str = String::from_utf8_lossy(&response_buf[..total_read]).to_string();
-                let response_hex = bytes_to_hex(&response_buf[..total_read]);
-                
-                println!("Caisse-AP RAW HEX: {}", response_hex);
-                println!("Caisse-AP RAW STR: {}", response_str);
-                log_to_file(&format!("RAW HEX: {}", response_hex));
-                log_to_file(&format!("RAW STR: {}", response_str));
-                
-                // Parse Caisse-AP response manually and robustly
-                let mut response_tags = Vec::new();
-                let mut p = 0;
-                let bytes = response_str.as_bytes();
-                let len = bytes.len();
-                
-                // Basic TLV parser: Tag(2) + Len(3) + Value(Len)
-                while p + 5 <= len {
-                    // Try to parse length at p+2
-                    let len_slice = &bytes[p+2..p+5];
-                    if let Ok(len_str) = std::str::from_utf8(len_slice) {
-                        if let Ok(value_len) = len_str.parse::<usize>() {
-                            if p + 5 + value_len <= len {
-                                let tag = String::from_utf8_lossy(&bytes[p..p+2]).to_string();
-                                let value = String::from_utf8_lossy(&bytes[p+5..p+5+value_len]).to_string();
-                                response_tags.push((tag, value));
-                                p += 5 + value_len;
-                                continue;
-                            }
-                        }
-                    }
-                    // If parsing failed, advance by 1 byte to try finding sync
-                    p += 1;
+        }
+        TransactionType::Completion => {
+            if let Some(captured) = response.tags.get("CB").and_then(|cb| cb.trim().parse::<u32>().ok()) {
+                if captured != response.amount_minor {
+                    log_to_file(&format!("Completion: requested {} minor units, captured {}", response.amount_minor, captured));
+                    response.amount_minor = captured;
                 }
-                
-                println!("Parsed tags: {:?}", response_tags);
-                log_to_file(&format!("Parsed tags: {:?}", response_tags));
-                
-                // Determine success
-                // CV = Code Validation (00 = OK)
-                // CO = Code Reponse (00 = OK)
-                // AC = Authorization Code (If returned, usually means success)
-                // AL = Autorisation Logiciel (Often 1 but can be ignored if AC is present)
-                let cv = response_tags.iter().find(|(t, _)| t == "CV").map(|(_, v)| v.as_str());
-                let co = response_tags.iter().find(|(t, _)| t == "CO").map(|(_, v)| v.as_str());
-                let ac = response_tags.iter().find(|(t, _)| t == "AC").map(|(_, v)| v.as_str());
-                let al = response_tags.iter().find(|(t, _)| t == "AL").map(|(_, v)| v.as_str());
-                
-                // Logic: 
-                // 1. Classic success: CV=00 or CO=00
-                // 2. Auth success: AC exists and is not empty (ignoring AL=1 in this case)
-                let has_auth_code = ac.map_or(false, |v| !v.is_empty());
-                let is_approved_classic = matches!(cv, Some("00")) || matches!(co, Some("00"));
-                
-                // Success if Classic OK OR Has Auth Code
-                let result_success = is_approved_classic || has_auth_code;
-                
-                println!("DECISION: Success={}, Classic={}, HasAuthCode={}, TagAL={:?}", 
-                    result_success, is_approved_classic, has_auth_code, al);
-                log_to_file(&format!("DECISION: Success={}, AC={:?}, CV={:?}, CO={:?}", result_success, ac, cv, co));
-                
-                let error_msg = if !result_success {
-                    // Cleaner error message for user
-                    log_to_file(&format!("Transaction Refused DETAILS: {:?}", response_tags));
-                    
-                    // Try to find a meaningful error cause
-                    if let Some(co_val) = co {
-                        Some(format!("Paiement refusé (Code: {})", co_val))
-                    } else if let Some(cv_val) = cv {
-                        Some(format!("Paiement refusé (Validation: {})", cv_val))
-                    } else {
-                        Some("Paiement refusé".to_string())
-                    }
-                } else {
-                    None
-                };
-                
-                let auth_num = ac.map(|v| v.to_string());
-                
-                Ok(TpePaymentResponse {
-                    success: result_success,
-                    transaction_result: if result_success { "APPROVED".to_string() } else { "REFUSED".to_string() },
-                    amount_cents,
-                    authorization_number: None,
-                    error_message: error_msg,
-                    raw_response: Some(response_str),
-                })
-            } else {
-                log_to_file("No response from TPE");
-                Err("No response from TPE (timeout)".to_string())
             }
-        }).await.map_err(|e| format!("Thread error: {}", e))?;
+        }
+        _ => {}
+    }
+    response
+}
+
+/// Compare the currency the terminal reports with the configured one.
+/// A refusal in the wrong currency means the terminal does not support ours.
+fn check_currency(mut response: TpePaymentResponse, currency: &Currency) -> TpePaymentResponse {
+    let Some(reported) = response.currency.clone() else {
+        return response;
+    };
+    if currency.matches(&reported) {
+        return response;
     }
     
-    // Serial connections use Concert V3 binary protocol
-    println!("--- CONCERT V3 MODE (Serial) ---");
+    log_to_file(&format!("Currency mismatch: configured {} ({}), terminal reported {}", currency.alpha, currency.numeric, reported));
+    response.error_message = Some(if response.success {
+        format!("Attention : le TPE a encaissé en devise {} au lieu de {}", reported, currency.alpha)
+    } else {
+        format!("Devise {} non supportée par le TPE (devise du terminal : {})", currency.alpha, reported)
+    });
+    response
+}
 
-    let result = tokio::task::spawn_blocking(move || {
-        let mut stream = connect(&port_name, baud_rate)?;
-        
-        // Step 1: ENQ
-        stream.write_all(&[ENQ]).map_err(|e| format!("ENQ failed: {}", e))?;
-        let _ = stream.flush();
-        std::thread::sleep(Duration::from_millis(200));
-        
-        let mut buf = [0u8; 64];
-        let handshake_res = match stream.read(&mut buf) {
-            Ok(n) if n > 0 => {
-                let hex = bytes_to_hex(&buf[..n]);
-                println!("Handshake received: {}", hex);
-                Some(buf[0])
-            },
-            _ => None
+/// Run one transaction exchange on an open stream using the given protocol
+async fn run_transaction(
+    stream: &mut Box<dyn TpeStream>,
+    protocol: TpeProtocol,
+    tx: &TpeTransaction,
+    journal: Option<PendingPayment>,
+    timeouts: &TpeTimeouts,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<TpePaymentResponse, TpeError> {
+    let amount_minor = tx.amount_minor;
+    
+    // Step 1: ENQ handshake (Concert link protocols only)
+    let mut cancelled = cancel.is_cancelled();
+    if protocol.uses_enq_handshake() && !cancelled {
+        progress.phase(TpePhase::Handshake);
+        let established = tokio::select! {
+            res = concert_link::establish(stream, timeouts) => Some(res),
+            _ = cancel.cancelled() => None,
         };
-        
-        if handshake_res != Some(ACK) {
-            println!("Handshake NOT ACK (expected 06, got {:?})", handshake_res);
-            if handshake_res == Some(ENQ) {
-                println!("TPE sent ENQ, replying with ACK...");
-                let _ = stream.write_all(&[ACK]);
-                let _ = stream.flush();
-                std::thread::sleep(Duration::from_millis(200));
+        match established {
+            Some(res) => res?,
+            None => cancelled = true,
+        }
+    }
+    
+    // Cancelled during the handshake: nothing was sent yet
+    if cancelled {
+        log_to_file("Cancelled before sending the request");
+        let _ = send_bytes(stream, &[EOT], timeouts.write()).await;
+        return Ok(cancelled_response(amount_minor));
+    }
+    
+    // Step 2: Send Message
+    let message = protocol.build_request(tx).map_err(|reason| TpeError::InvalidRequest { reason })?;
+    progress.phase(TpePhase::Sending);
+    log_to_file(&format!("Sending {:?} request ({} bytes): {}", tx.tx_type, message.len(), bytes_to_hex(&message)));
+    
+    // From here the terminal may debit the card: journal the request right before it leaves
+    let journal_id = journal.as_ref().map(|entry| entry.payment_id.clone());
+    journal.clone().map_or(Ok(()), pending::record)?;
+    let not_sent = |e: TpeError| {
+        if let Some(id) = &journal_id {
+            pending::resolve(id);
+        }
+        e
+    };
+    
+    // Step 3: Wait for ACK, retransmitting on NAK (Concert link protocols only)
+    if protocol.uses_enq_handshake() {
+        progress.phase(TpePhase::WaitingAck);
+        match concert_link::send_frame(stream, &message, timeouts).await? {
+            SendOutcome::NotSent(e) => return Err(not_sent(e)),
+            SendOutcome::Acked => log_to_file("Request acknowledged by TPE"),
+            SendOutcome::NoAnswer => log_to_file("No ACK received after message"),
+            // Format rejected (NAK after retries, EOT or ENQ): try alternate format
+            SendOutcome::Rejected(reply) => {
+                let raw = bytes_to_hex(&[reply]);
+                // The ASCII fallback can only express a debit
+                if tx.tx_type != TransactionType::Debit {
+                    log_to_file(&format!("{:?} request rejected by terminal ({})", tx.tx_type, raw));
+                    return Err(TpeError::Protocol { reason: format!("format rejeté par le TPE ({})", raw) });
+                }
+                log_to_file(&format!("Standard format rejected ({}), trying simple ASCII", raw));
+                return try_alternate_format(stream, tx, journal, timeouts).await;
             }
-        } else {
-            println!("Handshake OK (ACK received)");
         }
+    } else {
+        send_bytes(stream, &message, timeouts.write()).await.map_err(not_sent)?;
+    }
+    
+    // Step 4: Wait for Response (card wait of the terminal, 150s on IP and 120s on serial by default)
+    let timeout = timeouts.card_wait(protocol);
+    log_to_file(&format!("Waiting for payment ({}s)...", timeout.as_secs()));
+    
+    progress.phase(TpePhase::WaitingCard);
+    let read = if protocol.uses_enq_handshake() {
+        // The link layer checks the LRC and acknowledges the frame itself
+        concert_link::receive_frame(stream, timeout, timeouts, cancel, progress).await?
+    } else {
+        read_response(stream, protocol, timeout, timeouts, cancel, progress).await?
+    };
+    let response = match read {
+        ResponseRead::Data(data) => data,
+        ResponseRead::Cancelled => return Ok(cancelled_response(amount_minor)),
+        ResponseRead::TimedOut => {
+            log_to_file("No response from TPE");
+            return Err(TpeError::Timeout { seconds: timeout.as_secs_f64() });
+        }
+    };
+    
+    // A lone NAK: the terminal could not read the request, nothing was debited
+    if response.first() == Some(&NAK) && !response.contains(&STX) {
+        log_to_file("Request rejected by TPE (NAK)");
+        return Err(not_sent(TpeError::Protocol { reason: format!("format rejeté par le TPE ({})", bytes_to_hex(&[NAK])) }));
+    }
+    
+    // IMPORTANT: Caisse-AP terminals expect an ACK after sending their response,
+    // otherwise they might consider the transaction as failed/refused.
+    if !protocol.uses_enq_handshake() {
+        let _ = send_bytes(stream, &[ACK, EOT], timeouts.write()).await;
+    }
+    
+    let raw = bytes_to_hex(&response);
+    log_to_file(&format!("RAW HEX: {}", raw));
+    log_to_file(&format!("RAW STR: {}", String::from_utf8_lossy(&response)));
+    
+    match protocol {
+        TpeProtocol::CaisseApIp => parse_caisse_ap_response(&response, amount_minor),
+        TpeProtocol::NeptingRc => Ok(nepting::parse_response(&response, amount_minor)),
+        _ => parse_response(&response, amount_minor, &raw, protocol),
+    }
+}
+
+pub(crate) fn cancelled_response(amount_minor: u32) -> TpePaymentResponse {
+    TpePaymentResponse {
+        success: false,
+        transaction_result: "CANCELLED".to_string(),
+        amount_minor,
+        error_message: Some(TpeError::Cancelled.to_string()),
+        error: Some(TpeError::Cancelled),
+        ..Default::default()
+    }
+}
+
+/// Outcome of waiting for the terminal's answer
+pub(crate) enum ResponseRead {
+    Data(Vec<u8>),
+    Cancelled,
+    TimedOut,
+}
+
+/// Send CAN (0x18) x 3 + EOT (0x04) to force the terminal to abort
+pub(crate) async fn send_cancel_sequence<S: AsyncWrite + Unpin + ?Sized>(stream: &mut S, write_timeout: Duration) {
+    log_to_file("!!! CANCELLATION REQUESTED !!! - Sending CAN sequence");
+    let _ = send_bytes(stream, &[CAN, CAN, CAN, EOT], write_timeout).await;
+}
+
+/// Read the terminal's answer until a full STX..ETX+LRC frame, an EOT abort,
+/// a cancellation request or the timeout (unframed link, used by Caisse-AP IP
+/// and Nepting RC)
+async fn read_response(
+    stream: &mut Box<dyn TpeStream>,
+    protocol: TpeProtocol,
+    timeout: Duration,
+    timeouts: &TpeTimeouts,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<ResponseRead, TpeError> {
+    let mut response = [0u8; 1024];
+    let mut total = 0;
+    let deadline = tokio::time::Instant::now() + timeout;
+    
+    while total < response.len() {
+        let read = tokio::select! {
+            res = stream.read(&mut response[total..]) => Some(res),
+            _ = cancel.cancelled() => None,
+            _ = tokio::time::sleep_until(deadline) => break,
+        };
         
-        // Step 2: Send Message
-        let message = build_payment_message(amount_cents, &pos_number, protocol_version);
-        println!("Sending standard message: {}", bytes_to_hex(&message));
-        stream.write_all(&message).map_err(|e| format!("Send failed: {}", e))?;
-        let _ = stream.flush();
-        
-        // Step 3: Wait for ACK
-        std::thread::sleep(Duration::from_millis(500));
-        let mut ack_buf = [0u8; 64];
-        match stream.read(&mut ack_buf) {
-            Ok(n) if n > 0 => {
-                 let raw = bytes_to_hex(&ack_buf[..n]);
-                 println!("ACK step received: {}", raw);
-                 
-                 // If TPE sends ENQ, it might expect an ACK from POS
-                 if ack_buf[0] == ENQ && n == 1 {
-                    println!("TPE sent ENQ, replying with ACK...");
-                    let _ = stream.write_all(&[ACK]);
-                    let _ = stream.flush();
-                    // Optionally wait a bit more for a real ACK or proceed to Step 4
-                 }
-
-                 // If format rejected (ENQ EOT or NAK), try alternate format
-                 if (ack_buf[0] == ENQ || ack_buf[0] == EOT || ack_buf[0] == NAK) && !port_name.ends_with("+ASCII") {
-                    log_to_file("Standard format rejected, trying simple ASCII");
-                    println!("Standard format rejected ({}). Attempting ASCII fallback...", raw);
-                    return try_alternate_format(&mut stream, amount_cents);
+        let n = match read {
+            None => {
+                send_cancel_sequence(stream, timeouts.write()).await;
+                return Ok(ResponseRead::Cancelled);
+            }
+            Some(Ok(0)) => {
+                if total > 0 {
+                    break; // Got data and connection closed
                 }
+                log_to_file("Connection closed by TPE before any answer");
+                return Err(TpeError::ConnectionLost { reason: "connexion fermée par le TPE sans réponse".to_string() });
             }
-            _ => {
-                log_to_file("No ACK received");
-                println!("No ACK received after message");
+            Some(Ok(n)) => n,
+            Some(Err(e)) => {
+                log_to_file(&format!("Read error: {}", e));
+                return Err(TpeError::ConnectionLost { reason: e.to_string() });
             }
-        }
+        };
         
-        // Step 4: Wait for Response (120s)
-        log_to_file("Waiting for payment...");
+        let chunk = &response[total..total+n];
+        progress.frame(chunk);
         
-        // Since we are in a blocking thread with a trait object, we can't easily set timeout on the trait directly
-        // But the connect_tcp/connect_serial sets internal timeouts.
-        // We will loop with reads.
+        // CRITICAL: If TPE sends ENQ, it's asking if we are ready to receive the response.
+        // We must reply with ACK (06).
+        if chunk.contains(&ENQ) {
+            log_to_file("TPE sent ENQ in response loop, replying with ACK");
+            let _ = send_bytes(stream, &[ACK], timeouts.write()).await;
+            // Don't break, wait for the actual STX...ETX data
+        }
         
-        let mut response = [0u8; 256];
-        let mut total = 0;
-        let start = std::time::Instant::now();
+        total += n;
         
-        loop {
-            if start.elapsed().as_secs() > 120 {
-                println!("Timeout waiting for payment response (120s)");
-                return Err("Timeout (120s)".to_string());
-            }
-            
-            match stream.read(&mut response[total..]) {
-                Ok(n) if n > 0 => {
-                    let chunk = &response[total..total+n];
-                    let current_raw = bytes_to_hex(chunk);
-                    println!("Received data chunk: {}", current_raw);
-                    
-                    // CRITICAL: If TPE sends ENQ, it's asking if we are ready to receive the response.
-                    // We must reply with ACK (06).
-                    if chunk.contains(&ENQ) {
-                        println!("TPE sent ENQ in response loop, replying with ACK...");
-                        let _ = stream.write_all(&[ACK]).ok();
-                        let _ = stream.flush().ok();
-                        // Don't break, wait for the actual STX...ETX data
-                    }
-
-                    total += n;
-                    
-                    // Stop if we have a full message or terminal aborts
-                    if response[..total].contains(&ETX) {
-                        println!("End of response message detected (ETX)");
-                        break;
-                    }
-                    
-                    if response[..total].contains(&EOT) && !response[..total].contains(&STX) {
-                        println!("Terminal sent EOT (Abort/End) without data.");
-                        break;
-                    }
-                }
-                Ok(_) => std::thread::sleep(Duration::from_millis(200)),
-                Err(e) => {
-                     if e.kind() == std::io::ErrorKind::TimedOut || e.kind() == std::io::ErrorKind::WouldBlock {
-                         continue;
-                     }
-                     println!("Read error during payment: {}", e);
-                     return Err(format!("Read error: {}", e));
+        // Stop if we have a full message (ETX + LRC)
+        if let Some(etx_pos) = response[..total].iter().position(|&b| b == ETX) {
+            if etx_pos + 1 == total {
+                // LRC not received yet, give it a short grace period
+                if let Ok(Ok(n2)) = tokio::time::timeout(timeouts.lrc_grace(), stream.read(&mut response[total..])).await {
+                    total += n2;
                 }
             }
+            break;
+        }
+        
+        // Nepting RC answers may come without framing: stop once the RC code is in
+        if protocol == TpeProtocol::NeptingRc && nepting::answer_complete(&response[..total]) {
+            break;
         }
         
-        let _ = stream.write_all(&[ACK]);
+        // Terminal aborts
+        if response[..total].contains(&EOT) && !response[..total].contains(&STX) {
+            log_to_file("Terminal sent EOT (Abort/End) without data");
+            break;
+        }
+    }
+    
+    if total > 0 {
+        Ok(ResponseRead::Data(response[..total].to_vec()))
+    } else {
+        Ok(ResponseRead::TimedOut)
+    }
+}
+
+/// Parse a Caisse-AP IP response from its TLV tags
+fn parse_caisse_ap_response(data: &[u8], amount_minor: u32) -> Result<TpePaymentResponse, TpeError> {
+    let response_str = String::from_utf8_lossy(data).to_string();
+    
+    let response_tags = match tlv::decode_frame(data) {
+        Ok(message) => message,
+        Err(e) => {
+            log_to_file(&format!("Unreadable TLV response: {}", e));
+            return Ok(TpePaymentResponse {
+                success: false,
+                transaction_result: "??".to_string(),
+                amount_minor,
+                error_message: Some(format!("Réponse TPE illisible: {}", e)),
+                raw_response: Some(response_str),
+                error: Some(TpeError::Protocol { reason: e }),
+                ..Default::default()
+            });
+        }
+    };
+    
+    log_to_file(&format!("Parsed tags: {:?}", response_tags));
+    
+    // Determine success
+    // CV = Code Validation (00 = OK)
+    // CO = Code Reponse (00 = OK)
+    // AC = Authorization Code (If returned, usually means success)
+    // AL = Autorisation Logiciel (Often 1 but can be ignored if AC is present)
+    let cv = response_tags.get("CV");
+    let co = response_tags.get("CO");
+    let ac = response_tags.get("AC");
+    let al = response_tags.get("AL");
+    
+    // Logic: 
+    // 1. Classic success: CV=00 or CO=00
+    // 2. Auth success: AC exists and is not empty (ignoring AL=1 in this case)
+    let has_auth_code = ac.is_some_and(|v| !v.is_empty());
+    let is_approved_classic = matches!(cv, Some("00")) || matches!(co, Some("00"));
+    
+    // Success if Classic OK OR Has Auth Code
+    let result_success = is_approved_classic || has_auth_code;
+    
+    log_to_file(&format!("DECISION: Success={}, AC={:?}, CV={:?}, CO={:?}, AL={:?}", result_success, ac, cv, co, al));
+    
+    let error_msg = if !result_success {
+        // Cleaner error message for user
+        log_to_file(&format!("Transaction Refused DETAILS: {:?}", response_tags));
         
-        let raw = bytes_to_hex(&response[..total]);
-        println!("Final raw response from TPE: {}", raw);
-        log_to_file(&format!("Data: {}", raw));
-        parse_response(&response[..total], amount_cents, &raw)
-    }).await;
+        // Try to find a meaningful error cause
+        if let Some(co_val) = co {
+            Some(format!("Paiement refusé (Code: {})", co_val))
+        } else if let Some(cv_val) = cv {
+            Some(format!("Paiement refusé (Validation: {})", cv_val))
+        } else {
+            Some("Paiement refusé".to_string())
+        }
+    } else {
+        None
+    };
+    
+    Ok(TpePaymentResponse {
+        success: result_success,
+        transaction_result: if result_success { "APPROVED".to_string() } else { "REFUSED".to_string() },
+        amount_minor,
+        error_message: error_msg,
+        raw_response: Some(response_str),
+        ..Default::default()
+    }.with_tags(response_tags.fields()))
+}
 
-      match result {
-        Ok(res) => res,
-        Err(e) => Err(format!("Thread error: {}", e))
+/// Card brand from the EMV application identifier (RID prefix)
+fn brand_from_aid(aid: &str) -> Option<String> {
+    let aid = aid.to_uppercase();
+    let brand = if aid.starts_with("A000000042") {
+        "CB"
+    } else if aid.starts_with("A000000003") {
+        "VISA"
+    } else if aid.starts_with("A000000004") {
+        "MASTERCARD"
+    } else if aid.starts_with("A000000025") {
+        "AMEX"
+    } else {
+        return None;
+    };
+    Some(brand.to_string())
+}
+
+/// Meal and holiday voucher networks, recognized from the brand the terminal reports
+fn application_from_brand(brand: &str) -> Option<CardApplication> {
+    let brand = brand.to_uppercase();
+    if ["ANCV", "CHEQUE-VACANCES", "CHEQUES-VACANCES", "CHQ VACANCES"].iter().any(|b| brand.contains(b)) {
+        Some(CardApplication::HolidayVoucher)
+    } else if ["CONECS", "TICKET RESTAURANT", "TITRE-RESTAURANT", "TITRE RESTAURANT", "SWILE", "EDENRED", "APETIZ", "PASS RESTAURANT", "UP DEJEUNER", "BIMPLI"]
+        .iter()
+        .any(|b| brand.contains(b))
+    {
+        Some(CardApplication::MealVoucher)
+    } else {
+        None
     }
 }
 
-fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymentResponse, String> {
+/// Normalize the terminal's entry mode code
+fn entry_mode_label(mode: &str) -> String {
+    match mode.to_uppercase().as_str() {
+        "C" | "SC" | "NFC" | "CTLS" | "CONTACTLESS" => "CONTACTLESS".to_string(),
+        "P" | "I" | "ICC" | "EMV" | "CHIP" => "CHIP".to_string(),
+        "M" | "B" | "MAG" | "MAGSTRIPE" => "MAGSTRIPE".to_string(),
+        "K" | "MANUAL" => "MANUAL".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn parse_response(data: &[u8], amount_minor: u32, raw: &str, protocol: TpeProtocol) -> Result<TpePaymentResponse, TpeError> {
     let stx = data.iter().position(|&b| b == STX);
     let etx = data.iter().position(|&b| b == ETX);
     
@@ -845,15 +1411,15 @@ fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymen
             let body_str = String::from_utf8_lossy(body);
             log_to_file(&format!("Response body: {} ({}chars)", body_str, body_str.len()));
             
-            // Check if this is a TLV response (contains AE tag)
-            if body_str.contains("AE") {
+            // Check if this is a TLV response (decodes cleanly and has an AE tag)
+            if let Some(tags) = tlv::decode(body).ok().filter(|m| m.get("AE").is_some()) {
                 // TLV format response - parse AE and AF tags
                 // Format: AE002XX where XX is the status code
                 // AE values: 00=pending, 01=not performed, 10=performed (success!)
                 // AF values: 09=format error, 11=abandoned, etc.
                 
-                let ae_code = extract_tlv_value(&body_str, "AE");
-                let af_code = extract_tlv_value(&body_str, "AF");
+                let ae_code = tags.get("AE").unwrap_or_default().to_string();
+                let af_code = tags.get("AF").unwrap_or_default().to_string();
                 
                 log_to_file(&format!("TLV Response - AE='{}', AF='{}'", ae_code, af_code));
                 
@@ -863,11 +1429,11 @@ fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymen
                     return Ok(TpePaymentResponse {
                         success: true,
                         transaction_result: "10".to_string(),
-                        amount_cents,
-                        authorization_number: None,
+                        amount_minor,
                         error_message: None,
                         raw_response: Some(raw.to_string()),
-                    });
+                        ..Default::default()
+                    }.with_tags(tags.fields()));
                 } else {
                     // Transaction failed - map AF error codes
                     let error_msg = match af_code.as_str() {
@@ -883,18 +1449,27 @@ fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymen
                     return Ok(TpePaymentResponse {
                         success: false,
                         transaction_result: format!("AE={},AF={}", ae_code, af_code),
-                        amount_cents,
-                        authorization_number: None,
+                        amount_minor,
                         error_message: Some(format!("{} (AE={}, AF={})", error_msg, ae_code, af_code)),
                         raw_response: Some(raw.to_string()),
-                    });
+                        ..Default::default()
+                    }.with_tags(tags.fields()));
                 }
             }
             
             // Binary format response (V2/V3)
             // V2: TYPE(1) + RESULT(2) + ...
             // V3: TYPE(2) + RESULT(2) + ...
-            let result_code = if body_str.len() >= 3 {
+            // The configured binary format tells where the code is (a V3 debit type "00"
+            // would otherwise read as an approval); other protocols guess.
+            let fixed_code = match protocol {
+                TpeProtocol::ConcertV2 => body_str.get(1..3),
+                TpeProtocol::ConcertV3Binary => body_str.get(2..4),
+                _ => None,
+            };
+            let result_code = if let Some(code) = fixed_code {
+                code.to_string()
+            } else if body_str.len() >= 3 {
                 let v2_code = &body_str[1..3.min(body_str.len())];
                 let v3_code = if body_str.len() >= 4 { &body_str[2..4] } else { "" };
                 
@@ -919,10 +1494,11 @@ fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymen
                 return Ok(TpePaymentResponse {
                     success: true,
                     transaction_result: "00".to_string(),
-                    amount_cents,
+                    amount_minor,
                     authorization_number: None,
                     error_message: None,
                     raw_response: Some(raw.to_string()),
+                    ..Default::default()
                 });
             } else {
                 let error_msg = match result_code.as_str() {
@@ -937,10 +1513,11 @@ fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymen
                 return Ok(TpePaymentResponse {
                     success: false,
                     transaction_result: result_code.clone(),
-                    amount_cents,
+                    amount_minor,
                     authorization_number: None,
                     error_message: Some(format!("{} (code: {})", error_msg, result_code)),
                     raw_response: Some(raw.to_string()),
+                    ..Default::default()
                 });
             }
         }
@@ -950,204 +1527,56 @@ fn parse_response(data: &[u8], amount_cents: u32, raw: &str) -> Result<TpePaymen
     Ok(TpePaymentResponse {
         success: false,
         transaction_result: "??".to_string(),
-        amount_cents,
+        amount_minor,
         authorization_number: None,
         error_message: Some(format!("Format de réponse invalide: {}", raw)),
         raw_response: Some(raw.to_string()),
+        error: Some(TpeError::Protocol { reason: "réponse sans STX/ETX".to_string() }),
+        ..Default::default()
     })
 }
 
-/// Extract value from TLV format: TAG(2) + LENGTH(3) + VALUE
-fn extract_tlv_value(data: &str, tag: &str) -> String {
-    if let Some(pos) = data.find(tag) {
-        let start = pos + 2; // Skip tag
-        if start + 3 <= data.len() {
-            if let Ok(len) = data[start..start+3].parse::<usize>() {
-                let value_start = start + 3;
-                if value_start + len <= data.len() {
-                    return data[value_start..value_start+len].to_string();
-                }
-            }
-        }
-    }
-    String::new()
-}
-
 // function build_nepting_message removed
 
-/// Send payment using Caisse-AP protocol (for TCP connections) - UNUSED, kept for reference
-fn send_nepting_payment(address: &str, amount_cents: u32, pos_id: &str) -> Result<TpePaymentResponse, String> {
-    log_to_file(&format!("Caisse-AP payment: {} cents to {}", amount_cents, address));
-    
-    // Build Caisse-AP message
-    let tlv_message = build_caisse_ap_ip_message(amount_cents, pos_id);
-    
-    // Connect to terminal
-    let clean_addr = address.trim_end_matches("+ASCII");
-    let mut stream = TcpStream::connect_timeout(
-        &clean_addr.parse().map_err(|e| format!("Invalid IP: {}", e))?,
-        Duration::from_secs(3)
-    ).map_err(|e| format!("TCP connection failed: {}", e))?;
-    
-    stream.set_read_timeout(Some(Duration::from_secs(120))).ok();
-    stream.set_write_timeout(Some(Duration::from_secs(5))).ok();
-    
-    // Send TLV message (no ENQ/ACK handshake needed)
-    println!("Sending TLV (hex): {}", bytes_to_hex(&tlv_message));
-    stream.write_all(&tlv_message)
-        .map_err(|e| format!("Send TLV failed: {}", e))?;
-    stream.flush().ok();
-    
-    // Wait for response
-    println!("Waiting for Nepting response...");
-    let mut response_buf = [0u8; 1024];
-    let mut total = 0;
-    let start = std::time::Instant::now();
-    
-    // Give the terminal time to process the request
-    std::thread::sleep(Duration::from_millis(500));
-    
-    loop {
-        if start.elapsed().as_secs() > 120 {
-            println!("Timeout! Total bytes received: {}", total);
-            return Err("Timeout waiting for Nepting response (120s)".to_string());
-        }
-        
-        match stream.read(&mut response_buf[total..]) {
-            Ok(n) if n > 0 => {
-                let raw_hex = bytes_to_hex(&response_buf[total..total+n]);
-                total += n;
-                let current = String::from_utf8_lossy(&response_buf[..total]);
-                println!("Nepting chunk ({} bytes): HEX={} TEXT={}", n, raw_hex, current.trim());
-                
-                // Check if we have a complete response (contains RC = Response Code)
-                if current.contains("RC") {
-                    println!("Complete response received");
-                    break;
-                }
-            }
-            Ok(_) => {
-                // Empty read - wait a bit and continue
-                if total > 0 {
-                    println!("Empty read after {} bytes total", total);
-                    break;
-                }
-                std::thread::sleep(Duration::from_millis(200));
-            }
-            Err(e) => {
-                if e.kind() == std::io::ErrorKind::TimedOut || e.kind() == std::io::ErrorKind::WouldBlock {
-                    if total > 0 { 
-                        println!("Timeout/WouldBlock after {} bytes - assuming complete", total);
-                        break; 
-                    }
-                    continue;
-                }
-                println!("Read error: {} (total received: {})", e, total);
-                return Err(format!("Read error: {}", e));
-            }
-        }
-    }
-    
-    let response_str = String::from_utf8_lossy(&response_buf[..total]).to_string();
-    println!("Final Nepting response: {}", response_str);
-    log_to_file(&format!("Nepting response: {}", response_str));
-    
-    // Parse TLV response - look for RC (Response Code)
-    // RC003000 = Success (code "000")
-    // RC003007 = Error 007
-    if let Some(rc_pos) = response_str.find("RC") {
-        let after_rc = &response_str[rc_pos + 2..];
-        if after_rc.len() >= 6 {
-            let len_str = &after_rc[0..3];
-            if let Ok(len) = len_str.parse::<usize>() {
-                if after_rc.len() >= 3 + len {
-                    let code = &after_rc[3..3+len];
-                    println!("Nepting Response Code: {}", code);
-                    
-                    if code == "000" || code == "00" || code == "0" {
-                        return Ok(TpePaymentResponse {
-                            success: true,
-                            transaction_result: "0".to_string(),
-                            amount_cents,
-                            authorization_number: None,
-                            error_message: None,
-                            raw_response: Some(response_str),
-                        });
-                    } else {
-                        return Ok(TpePaymentResponse {
-                            success: false,
-                            transaction_result: code.to_string(),
-                            amount_cents,
-                            authorization_number: None,
-                            error_message: Some(format!("Nepting error code: {}", code)),
-                            raw_response: Some(response_str),
-                        });
-                    }
-                }
-            }
-        }
-    }
-    
-    // If no RC found, check for simple OK/KO
-    if response_str.contains("OK") {
-        return Ok(TpePaymentResponse {
-            success: true,
-            transaction_result: "OK".to_string(),
-            amount_cents,
-            authorization_number: None,
-            error_message: None,
-            raw_response: Some(response_str),
-        });
-    }
-    
-    Ok(TpePaymentResponse {
-        success: false,
-        transaction_result: "?".to_string(),
-        amount_cents,
-        authorization_number: None,
-        error_message: Some(format!("Nepting response: {}", response_str)),
-        raw_response: Some(response_str),
-    })
-}
-
-
-
-/// Try alternate ASCII format (Simple "DEBIT X.XX EUR")
-fn try_alternate_format(stream: &mut Box<dyn TpeStream>, amount_cents: u32) -> Result<TpePaymentResponse, String> {
+/// Try alternate ASCII format (Simple "DEBIT X.XX EUR"). The plain text debit is
+/// journaled like a framed request.
+async fn try_alternate_format(
+    stream: &mut Box<dyn TpeStream>,
+    tx: &TpeTransaction,
+    journal: Option<PendingPayment>,
+    timeouts: &TpeTimeouts,
+) -> Result<TpePaymentResponse, TpeError> {
     log_to_file("Trying ASCII format: amount in plain text");
-    println!("--- FALLBACK ASCII MODE ---");
+    let amount_minor = tx.amount_minor;
     
     // Some TPEs accept plain: "DEBIT 10.00 EUR\r\n"
-    let amount_euros = amount_cents as f64 / 100.0;
     // Using \r (CR) is standard for line ending in serial
-    let message = format!("DEBIT {:.2} EUR\r", amount_euros); 
+    let message = format!("DEBIT {} {}\r", tx.currency.format_minor(amount_minor), tx.currency.alpha);
     
+    journal.map_or(Ok(()), pending::record)?;
     log_to_file(&format!("Sending fallback: {}", message.trim()));
-    println!("Sending ASCII: \"{}\"", message.escape_debug());
-    if let Err(e) = stream.write_all(message.as_bytes()) {
-        println!("Error sending ASCII: {}", e);
+    // A failed send is an answer (Ok): the journal entry is resolved with it
+    if let Err(e) = send_bytes(stream, message.as_bytes(), timeouts.write()).await {
+        log_to_file(&format!("Error sending ASCII: {}", e));
         return Ok(TpePaymentResponse {
             success: false,
             transaction_result: "?".to_string(),
-            amount_cents,
+            amount_minor,
             authorization_number: None,
-            error_message: Some(format!("Send fallback failed: {}", e)),
+            error_message: Some(format!("Mode ASCII : {}", e)),
             raw_response: None,
+            error: Some(e),
+            ..Default::default()
         });
     }
-    let _ = stream.flush();
-    
-    println!("Waiting for ASCII response...");
-    std::thread::sleep(Duration::from_millis(500));
     
     // Wait for response to ASCII command
     let mut buf = [0u8; 256];
-    match stream.read(&mut buf) {
-        Ok(n) if n > 0 => {
+    match tokio::time::timeout(timeouts.ascii_answer(), stream.read(&mut buf)).await {
+        Ok(Ok(n)) if n > 0 => {
             let hex = bytes_to_hex(&buf[..n]);
             let text = String::from_utf8_lossy(&buf[..n]);
             log_to_file(&format!("Alternate response: {} ({})", text.trim(), hex));
-            println!("Received ASCII response: {} ({})", text.trim(), hex);
             
             // If we get simple "OK" or similar, consider generic success or just return raw for user to see
             // Usually returns status
@@ -1155,15 +1584,199 @@ fn try_alternate_format(stream: &mut Box<dyn TpeStream>, amount_cents: u32) -> R
             Ok(TpePaymentResponse {
                 success: false, // Assume false unless we parse specific success char, let user decide based on display
                 transaction_result: "?".to_string(),
-                amount_cents,
+                amount_minor,
                 authorization_number: None,
                 error_message: Some(format!("Mode ASCII utilisé. Réponse: {}", text.trim())),
                 raw_response: Some(format!("ASCII: {} | HEX: {}", text.trim(), hex)),
+                error: Some(TpeError::Protocol { reason: format!("réponse ASCII non interprétée: {}", text.trim()) }),
+                ..Default::default()
             })
         }
         _ => {
-            println!("No response to ASCII fallback.");
-            Err("Pas de réponse au format alternatif".to_string())
+            log_to_file("No response to ASCII fallback");
+            Err(TpeError::Timeout { seconds: timeouts.ascii_answer().as_secs_f64() })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn cancel_only_hits_the_given_payment() {
+        let first = CancelHandle::register(Some("till-1-payment".to_string()));
+        let second = CancelHandle::register(Some("till-2-payment".to_string()));
+        
+        cancel_tpe_transaction("till-1-payment".to_string()).unwrap();
+        let unknown = cancel_tpe_transaction("till-3-payment".to_string());
+        
+        assert!(first.token.is_cancelled());
+        assert!(!second.token.is_cancelled());
+        assert_eq!(unknown, Err(TpeError::UnknownPayment { payment_id: "till-3-payment".to_string() }));
+    }
+    
+    #[test]
+    fn answer_for_another_transaction_is_rejected() {
+        let tx = TpeTransaction::debit(500, "01").with_tpe_transaction_id("000042".to_string());
+        let answer = |ti: &str| TpePaymentResponse { success: true, ..Default::default() }.with_tags(&[("TI".to_string(), ti.to_string())]);
+        
+        assert!(check_transaction_id(answer("000042"), &tx).is_ok());
+        assert!(check_transaction_id(TpePaymentResponse::default(), &tx).is_ok());
+        let err = check_transaction_id(answer("000041"), &tx).unwrap_err();
+        assert_eq!(err.code(), "Protocol");
+        assert!(err.to_string().contains("TI 000041"));
+    }
+    
+    #[tokio::test]
+    async fn stuck_send_reports_a_french_reason() {
+        let (mut pos, _terminal) = tokio::io::duplex(1);
+        
+        let err = send_bytes(&mut pos, &[ENQ, ACK], Duration::from_millis(50)).await.unwrap_err();
+        
+        assert_eq!(err, TpeError::ConnectionLost { reason: "échec d'envoi : délai de 0.05 s dépassé".to_string() });
+    }
+    
+    fn tags(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields.iter().map(|(tag, value)| (tag.to_string(), value.to_string())).collect()
+    }
+    
+    /// Approved answer carrying the given tags
+    fn approved(fields: &[(&str, &str)]) -> TpePaymentResponse {
+        TpePaymentResponse { success: true, amount_minor: 1250, ..Default::default() }.with_tags(&tags(fields))
+    }
+    
+    #[test]
+    fn caisse_ap_approval_from_validation_or_authorization_code() {
+        let frame = crate::protocols::frame_message("CV00200AC006123456CE003978MA004VISAME001C");
+        let response = parse_caisse_ap_response(&frame, 1250).unwrap();
+        
+        assert!(response.success);
+        assert_eq!(response.transaction_result, "APPROVED");
+        assert_eq!(response.authorization_number.as_deref(), Some("123456"));
+        assert_eq!(response.currency.as_deref(), Some("978"));
+        assert_eq!(response.card_brand.as_deref(), Some("VISA"));
+        assert_eq!(response.entry_mode.as_deref(), Some("CONTACTLESS"));
+        
+        // An authorization number approves even with AL=1
+        let response = parse_caisse_ap_response(b"AC006A1B2C3AL0011", 1250).unwrap();
+        assert!(response.success);
+        assert_eq!(response.error_message, None);
+    }
+    
+    #[test]
+    fn caisse_ap_refusal_reports_the_response_code() {
+        let response = parse_caisse_ap_response(b"CV00205CO003116", 1250).unwrap();
+        assert!(!response.success);
+        assert_eq!(response.transaction_result, "REFUSED");
+        assert_eq!(response.error_message.as_deref(), Some("Paiement refusé (Code: 116)"));
+        
+        let response = parse_caisse_ap_response(b"CV00205", 1250).unwrap();
+        assert_eq!(response.error_message.as_deref(), Some("Paiement refusé (Validation: 05)"));
+    }
+    
+    #[test]
+    fn unreadable_caisse_ap_answer_is_a_protocol_error() {
+        let response = parse_caisse_ap_response(b"CV0x200", 1250).unwrap();
+        
+        assert!(!response.success);
+        assert_eq!(response.transaction_result, "??");
+        assert_eq!(response.amount_minor, 1250);
+        assert_eq!(response.error.map(|e| e.code()), Some("Protocol"));
+    }
+    
+    #[test]
+    fn brand_comes_from_the_aid_when_not_named() {
+        assert_eq!(brand_from_aid("a0000000421010").as_deref(), Some("CB"));
+        assert_eq!(brand_from_aid("A0000000031010").as_deref(), Some("VISA"));
+        assert_eq!(brand_from_aid("A0000000041010").as_deref(), Some("MASTERCARD"));
+        assert_eq!(brand_from_aid("A00000002501").as_deref(), Some("AMEX"));
+        assert_eq!(brand_from_aid("A0000001523010"), None);
+        
+        assert_eq!(approved(&[("AI", "A0000000421010")]).card_brand.as_deref(), Some("CB"));
+        assert_eq!(approved(&[("MA", "VISA"), ("AI", "A0000000421010")]).card_brand.as_deref(), Some("VISA"));
+    }
+    
+    #[test]
+    fn entry_modes_are_normalized() {
+        assert_eq!(entry_mode_label("c"), "CONTACTLESS");
+        assert_eq!(entry_mode_label("NFC"), "CONTACTLESS");
+        assert_eq!(entry_mode_label("ICC"), "CHIP");
+        assert_eq!(entry_mode_label("M"), "MAGSTRIPE");
+        assert_eq!(entry_mode_label("K"), "MANUAL");
+        assert_eq!(entry_mode_label("x9"), "X9");
+    }
+    
+    #[test]
+    fn ticket_lines_from_fields_or_line_breaks() {
+        let per_field = tags(&[("TC", ""), ("TC", "CARTE BANCAIRE"), ("TC", ""), ("TC", "MONTANT 12,50 EUR  "), ("TC", "")]);
+        let one_field = tags(&[("TM", "\r\nCOMMERCANT\r\n\r\nA CONSERVER\r")]);
+        
+        assert_eq!(ticket_lines(&per_field, "TC"), vec!["CARTE BANCAIRE", "", "MONTANT 12,50 EUR"]);
+        assert_eq!(ticket_lines(&one_field, "TM"), vec!["COMMERCANT", "", "A CONSERVER"]);
+        assert!(ticket_lines(&per_field, "TM").is_empty());
+    }
+    
+    #[test]
+    fn application_from_echo_then_brand_then_request() {
+        let check = |response, requested| check_application(response, TransactionType::Debit, requested).card_application;
+        
+        assert_eq!(check(approved(&[("CC", "002"), ("MA", "VISA")]), CardApplication::Any), Some(CardApplication::MealVoucher));
+        assert_eq!(check(approved(&[("MA", "Swile")]), CardApplication::Any), Some(CardApplication::MealVoucher));
+        assert_eq!(check(approved(&[("MA", "ANCV Connect")]), CardApplication::Any), Some(CardApplication::HolidayVoucher));
+        assert_eq!(check(approved(&[("MA", "CB")]), CardApplication::Any), Some(CardApplication::BankCard));
+        assert_eq!(check(approved(&[]), CardApplication::HolidayVoucher), Some(CardApplication::HolidayVoucher));
+        
+        let refused = TpePaymentResponse::default();
+        assert_eq!(check(refused, CardApplication::MealVoucher), None);
+        let totals = check_application(approved(&[]), TransactionType::Reconciliation, CardApplication::Any);
+        assert_eq!(totals.card_application, None);
+    }
+    
+    #[test]
+    fn voucher_networks_are_recognized_from_the_brand() {
+        assert_eq!(application_from_brand("Titre-Restaurant Edenred"), Some(CardApplication::MealVoucher));
+        assert_eq!(application_from_brand("CONECS"), Some(CardApplication::MealVoucher));
+        assert_eq!(application_from_brand("cheques-vacances"), Some(CardApplication::HolidayVoucher));
+        assert_eq!(application_from_brand("MASTERCARD"), None);
+    }
+    
+    #[test]
+    fn pre_authorization_hold_reference_falls_back() {
+        let hold = |fields| check_pre_authorization(approved(fields), TransactionType::PreAuthorization).hold_reference;
+        
+        assert_eq!(hold(&[("RF", "HOLD1"), ("TA", "ACQ1"), ("AC", "123456")]).as_deref(), Some("HOLD1"));
+        assert_eq!(hold(&[("TA", "ACQ1"), ("AC", "123456")]).as_deref(), Some("ACQ1"));
+        assert_eq!(hold(&[("AC", "123456")]).as_deref(), Some("123456"));
+        
+        let unreferenced = check_pre_authorization(approved(&[]), TransactionType::PreAuthorization);
+        assert_eq!(unreferenced.hold_reference, None);
+        assert!(unreferenced.error_message.unwrap().contains("aucune référence"));
+    }
+    
+    #[test]
+    fn completion_reports_the_captured_amount() {
+        let partial = check_pre_authorization(approved(&[("CB", "000000000800")]), TransactionType::Completion);
+        let unreadable = check_pre_authorization(approved(&[("CB", "n/a")]), TransactionType::Completion);
+        let refused = TpePaymentResponse { amount_minor: 1250, ..Default::default() }.with_tags(&tags(&[("CB", "800")]));
+        
+        assert_eq!(partial.amount_minor, 800);
+        assert_eq!(unreadable.amount_minor, 1250);
+        assert_eq!(check_pre_authorization(refused, TransactionType::Completion).amount_minor, 1250);
+    }
+    
+    #[test]
+    fn currency_mismatch_is_reported() {
+        let eur = Currency::default();
+        let check = |fields, success| {
+            let response = TpePaymentResponse { success, ..Default::default() }.with_tags(&tags(fields));
+            check_currency(response, &eur).error_message
+        };
+        
+        assert_eq!(check(&[("CE", "978")], true), None);
+        assert_eq!(check(&[("CE", "eur")], true), None);
+        assert_eq!(check(&[], true), None);
+        assert!(check(&[("CE", "953")], true).unwrap().starts_with("Attention"));
+        assert!(check(&[("CE", "953")], false).unwrap().contains("non supportée"));
+    }
+}
@@ -0,0 +1,331 @@
+// ===================================
+// TPE Protocol Detection - setup wizard
+// ===================================
+//
+// Probes the configured port with each framing and suggests the settings to save.
+// A serial probe is a 0.00 debit aborted (CAN) as soon as the terminal acknowledges
+// it; the terminal's answer to the abort tells whether it understood the framing
+// (AF=09: format not understood). Probing stops at the first confirmed framing and
+// the plain text mode is only inferred, never sent. HTTP terminals get a status request.
+
+use serde::Serialize;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+
+use crate::concert_link::{self, SendOutcome};
+use crate::currency::Currency;
+use crate::nepting;
+use crate::protocols::{bytes_to_hex, TpeProtocol, TpeTransaction};
+use crate::serial_line::SerialSettings;
+use crate::tlv;
+use crate::tpe_error::TpeError;
+use crate::tpe_timeouts::TpeTimeouts;
+use crate::tpe_ids;
+use crate::tpe::{
+    connect, log_to_file, send_bytes, send_cancel_sequence, CancelToken, ProgressReporter,
+    ResponseRead, TpeConfig, TpeStream, ACK, EOT, ETX, STX,
+};
+use crate::yavin;
+
+/// Framings tried on a serial port, most capable first
+const SERIAL_CANDIDATES: [TpeProtocol; 3] = [
+    TpeProtocol::ConcertV3Tlv,
+    TpeProtocol::ConcertV3Binary,
+    TpeProtocol::ConcertV2,
+];
+
+/// AF code (result code in the fixed formats) of a message the terminal could not read
+const FORMAT_NOT_UNDERSTOOD: &str = "09";
+
+/// Result of one probe
+#[derive(Debug, Serialize)]
+pub struct ProtocolProbe {
+    pub protocol_version: u8,
+    pub protocol: String,
+    pub ascii: bool, // Plain text "+ASCII" mode
+    pub answered: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TpeDetectionResult {
+    pub port: String,
+    pub probes: Vec<ProtocolProbe>,
+    pub suggested: Option<TpeConfig>, // Settings to save (port may get the +ASCII suffix)
+    pub message: String,
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+/// Probe the configured port with every protocol and suggest a configuration.
+/// `protocol_version` is the current setting, kept when it is equivalent to the
+/// detected one (SmilePay speaks Concert V3 TLV, Yavin Cloud is not probed).
+#[tauri::command]
+pub async fn detect_tpe_protocol(
+    port_name: String,
+    baud_rate: u32,
+    pos_number: String,
+    protocol_version: Option<u8>,
+    serial: Option<SerialSettings>,
+    timeouts: Option<TpeTimeouts>,
+) -> Result<TpeDetectionResult, TpeError> {
+    let port = port_name.trim_end_matches("+ASCII").trim().to_string();
+    log_to_file(&format!("=== DETECT PROTOCOL on {} ===", port));
+    let tx = TpeTransaction::debit(0, &pos_number);
+    let serial = serial.unwrap_or_default();
+    let timeouts = timeouts.unwrap_or_default();
+    timeouts.validate().map_err(|reason| TpeError::InvalidConfig { reason })?;
+    
+    let probes = if port.starts_with("http://") || port.starts_with("https://") {
+        vec![probe_yavin_local(&port).await]
+    } else if port.contains(':') {
+        let id = tpe_ids::next_id(&pos_number, None).map_err(|reason| TpeError::Storage { reason })?;
+        let tx = tx.with_tpe_transaction_id(id);
+        let caisse_ap = probe_caisse_ap(&port, &tx, &timeouts).await;
+        if caisse_ap.answered {
+            vec![caisse_ap]
+        } else {
+            vec![caisse_ap, probe_yavin_local(&port).await]
+        }
+    } else {
+        probe_serial(&port, baud_rate, &serial, &timeouts, &tx).await?
+    };
+    
+    for probe in &probes {
+        log_to_file(&format!("Probe {} (ascii: {}): {} - {}", probe.protocol, probe.ascii, probe.answered, probe.detail));
+    }
+    
+    let suggested = probes.iter().find(|p| p.answered).map(|probe| {
+        let detected = TpeProtocol::from_version(probe.protocol_version);
+        let current = protocol_version.map(TpeProtocol::from_version);
+        let version = match (detected, current) {
+            (TpeProtocol::ConcertV3Tlv, Some(TpeProtocol::SmilePay))
+            | (TpeProtocol::YavinLocal, Some(TpeProtocol::YavinCloud)) => protocol_version.unwrap_or(detected.version()),
+            _ if probe.ascii => protocol_version.unwrap_or(detected.version()),
+            _ => detected.version(),
+        };
+        TpeConfig {
+            name: String::new(),
+            port: if probe.ascii { format!("{}+ASCII", port) } else { port.clone() },
+            baud_rate,
+            pos_number: pos_number.clone(),
+            protocol_version: version,
+            terminal_id: None,
+            api_key: None,
+            currency: Currency::default(),
+            serial: serial.clone(),
+            timeouts: timeouts.clone(),
+        }
+    });
+    
+    let message = match probes.iter().find(|p| p.answered) {
+        Some(probe) if probe.ascii => "Le TPE ne répond qu'en mode texte (ASCII)".to_string(),
+        Some(probe) => format!("Protocole détecté : {}", probe.protocol),
+        None => format!("Aucun protocole n'a répondu sur {}", port),
+    };
+    
+    Ok(TpeDetectionResult { port, probes, suggested, message })
+}
+
+// ===================================
+// Serial - Concert framings, then plain text
+// ===================================
+
+async fn probe_serial(
+    port: &str,
+    baud_rate: u32,
+    serial: &SerialSettings,
+    timeouts: &TpeTimeouts,
+    tx: &TpeTransaction,
+) -> Result<Vec<ProtocolProbe>, TpeError> {
+    let mut stream = connect(port, baud_rate, serial, timeouts.connect()).await?;
+    let mut probes: Vec<ProtocolProbe> = Vec::new();
+    let mut link_answered = false;
+    
+    for protocol in SERIAL_CANDIDATES {
+        match probe_concert(&mut stream, protocol, tx, timeouts).await {
+            Ok(probe) => {
+                link_answered = true;
+                let confirmed = probe.answered;
+                probes.push(probe);
+                if confirmed {
+                    break;
+                }
+            }
+            Err(e) => {
+                // No answer to ENQ: the other framings would not get one either
+                probes.push(ProtocolProbe {
+                    protocol_version: protocol.version(),
+                    protocol: protocol.name().to_string(),
+                    ascii: false,
+                    answered: false,
+                    detail: e.to_string(),
+                });
+                break;
+            }
+        }
+    }
+    
+    // The link answers but no framing is understood: plain text terminal. Not sent,
+    // as a text "DEBIT" cannot be aborted.
+    if link_answered && !probes.iter().any(|p| p.answered) {
+        probes.push(ProtocolProbe {
+            protocol_version: TpeProtocol::ConcertV2.version(),
+            protocol: "Texte (ASCII)".to_string(),
+            ascii: true,
+            answered: true,
+            detail: "Lien établi mais aucune trame Concert comprise (mode texte supposé, non testé)".to_string(),
+        });
+    }
+    
+    Ok(probes)
+}
+
+/// Send a 0.00 debit in the given framing, abort it as soon as the terminal takes it
+/// and read the answer to the abort. Err when the link cannot be established at all.
+async fn probe_concert(
+    stream: &mut Box<dyn TpeStream>,
+    protocol: TpeProtocol,
+    tx: &TpeTransaction,
+    timeouts: &TpeTimeouts,
+) -> Result<ProtocolProbe, TpeError> {
+    log_to_file(&format!("Probing {}", protocol.name()));
+    concert_link::establish(stream, timeouts).await?;
+    
+    let frame = protocol.build_request(tx).map_err(|reason| TpeError::InvalidRequest { reason })?;
+    let (answered, detail) = match concert_link::send_frame(stream, &frame, timeouts).await {
+        // The ACK is only the link layer: the answer says whether the message was understood
+        Ok(SendOutcome::Acked) | Ok(SendOutcome::NoAnswer) => {
+            let answer = abort_probe(stream, timeouts).await;
+            probe_verdict(protocol, answer)
+        }
+        Ok(SendOutcome::Rejected(byte)) => (false, format!("Trame refusée par le TPE ({})", bytes_to_hex(&[byte]))),
+        Ok(SendOutcome::NotSent(e)) => (false, e.to_string()),
+        Err(e) => (false, e.to_string()),
+    };
+    
+    Ok(ProtocolProbe {
+        protocol_version: protocol.version(),
+        protocol: protocol.name().to_string(),
+        ascii: false,
+        answered,
+        detail,
+    })
+}
+
+/// Cancel the probe transaction and read the terminal's answer so the link is idle again
+async fn abort_probe(stream: &mut Box<dyn TpeStream>, timeouts: &TpeTimeouts) -> Result<ResponseRead, TpeError> {
+    send_cancel_sequence(stream, timeouts.write()).await;
+    concert_link::receive_frame(stream, timeouts.abort(), timeouts, &CancelToken::new(), &ProgressReporter::silent()).await
+}
+
+/// Framing confirmed when the terminal answered with an application message it did
+/// not flag as unreadable
+fn probe_verdict(protocol: TpeProtocol, answer: Result<ResponseRead, TpeError>) -> (bool, String) {
+    let frame = match answer {
+        Ok(ResponseRead::Data(frame)) if frame.len() > 3 => frame,
+        Ok(_) => return (false, "Trame acquittée mais aucune réponse du TPE".to_string()),
+        Err(e) => return (false, e.to_string()),
+    };
+    let body = &frame[1..frame.len() - 2]; // STX ... ETX LRC
+    log_to_file(&format!("Probe answer: {}", String::from_utf8_lossy(body)));
+    
+    let code = match protocol {
+        TpeProtocol::ConcertV2 => String::from_utf8_lossy(body).get(1..3).map(str::to_string),
+        TpeProtocol::ConcertV3Binary => String::from_utf8_lossy(body).get(2..4).map(str::to_string),
+        _ => tlv::decode(body).ok().and_then(|message| message.get("AF").map(str::to_string)),
+    };
+    if code.as_deref() == Some(FORMAT_NOT_UNDERSTOOD) {
+        return (false, format!("Trame non comprise par le TPE (code {})", FORMAT_NOT_UNDERSTOOD));
+    }
+    (true, "Trame comprise par le TPE".to_string())
+}
+
+// ===================================
+// TCP/IP - Caisse-AP, then Yavin local API
+// ===================================
+
+async fn probe_caisse_ap(address: &str, tx: &TpeTransaction, timeouts: &TpeTimeouts) -> ProtocolProbe {
+    let protocol = TpeProtocol::CaisseApIp;
+    let result = async {
+        let mut stream = connect(address, 0, &SerialSettings::default(), timeouts.connect()).await?;
+        let request = protocol.build_request(tx).map_err(|reason| TpeError::InvalidRequest { reason })?;
+        send_bytes(&mut stream, &request, timeouts.write()).await?;
+        let answer = read_tcp_answer(&mut stream, timeouts.probe()).await;
+        if answer.is_empty() {
+            send_cancel_sequence(&mut stream, timeouts.write()).await;
+        } else {
+            let _ = send_bytes(&mut stream, &[ACK, EOT], timeouts.write()).await;
+        }
+        Ok::<_, TpeError>(answer)
+    }
+    .await;
+    
+    let mut protocol = protocol;
+    let (answered, detail) = match result {
+        Ok(answer) if answer.is_empty() => (false, "Pas de réponse du TPE".to_string()),
+        Ok(answer) => match tlv::decode_frame(&answer) {
+            // Only an RC code, no Caisse-AP result tags: Nepting firmware
+            Ok(message) if message.get("RC").is_some() && message.get("CV").is_none() && message.get("CO").is_none() => {
+                protocol = TpeProtocol::NeptingRc;
+                (true, format!("Code RC reçu ({})", message.get("RC").unwrap_or_default()))
+            }
+            Ok(message) if !message.fields().is_empty() => {
+                (true, format!("Réponse TLV reçue ({} champs)", message.fields().len()))
+            }
+            _ => (false, format!("Réponse non TLV: {}", bytes_to_hex(&answer))),
+        },
+        Err(e) => (false, e.to_string()),
+    };
+    
+    ProtocolProbe {
+        protocol_version: protocol.version(),
+        protocol: protocol.name().to_string(),
+        ascii: false,
+        answered,
+        detail,
+    }
+}
+
+/// Collect the answer until its ETX (+LRC), the connection closes or the probe times out
+async fn read_tcp_answer(stream: &mut Box<dyn TpeStream>, timeout: Duration) -> Vec<u8> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let mut answer = Vec::new();
+    let mut buf = [0u8; 256];
+    
+    loop {
+        match tokio::time::timeout_at(deadline, stream.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => {
+                answer.extend_from_slice(&buf[..n]);
+                if let Some(etx) = answer.iter().position(|&b| b == ETX) {
+                    if answer.len() > etx + 1 {
+                        return answer;
+                    }
+                }
+                // Nepting terminals may answer a bare RC tag without framing
+                if !answer.contains(&STX) && nepting::answer_complete(&answer) {
+                    return answer;
+                }
+            }
+            _ => return answer,
+        }
+    }
+}
+
+async fn probe_yavin_local(address: &str) -> ProtocolProbe {
+    let protocol = TpeProtocol::YavinLocal;
+    let (answered, detail) = match yavin::probe_local(address, Duration::from_secs(3)).await {
+        Ok(status) => (true, format!("API locale joignable ({})", status)),
+        Err(e) => (false, e.to_string()),
+    };
+    
+    ProtocolProbe {
+        protocol_version: protocol.version(),
+        protocol: protocol.name().to_string(),
+        ascii: false,
+        answered,
+        detail,
+    }
+}
@@ -0,0 +1,198 @@
+// ===================================
+// TPE Errors - stable error codes for every TPE command
+// ===================================
+//
+// TPE commands fail with a TpeError. The frontend tells the cases apart by
+// `code`, which never changes once released, and shows `message`, built from
+// the localization table below. Refusals and cancellations are answers, not
+// failures: they come back in TpePaymentResponse.error with the same codes.
+//
+// Serialized as { "code": "Timeout", "message": "...", "seconds": 150 }:
+// the fields of the variant are sent alongside the code.
+
+use serde::ser::{SerializeMap, Serializer};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TpeError {
+    /// The terminal cannot be reached (TCP connection, serial port, HTTP API)
+    ConnectFailed { target: String, reason: String },
+    /// The terminal did not answer in time
+    Timeout { seconds: f64 },
+    /// The terminal or the bank refused the transaction
+    Refused { code: String },
+    /// Cancelled by the cashier
+    Cancelled,
+    /// The connection dropped during the exchange
+    ConnectionLost { reason: String },
+    /// The terminal's answer could not be understood
+    Protocol { reason: String },
+    /// The protocol of the terminal cannot carry this operation
+    Unsupported { protocol: String, operation: String },
+    /// Invalid terminal settings (address, currency, serial line, API key...)
+    InvalidConfig { reason: String },
+    /// Invalid or missing request parameter (hold reference, TLV value...)
+    InvalidRequest { reason: String },
+    UnknownTerminal { name: String },
+    NoDefaultTerminal,
+    DuplicateTerminal { name: String },
+    /// No transaction in progress or in the journal under this id
+    UnknownPayment { payment_id: String },
+    /// The journal, the transaction counter or the terminal list could not be saved or read
+    Storage { reason: String },
+}
+
+/// French messages by code, "{field}" is replaced by the field of the error
+const MESSAGES_FR: &[(&str, &str)] = &[
+    ("ConnectFailed", "TPE injoignable ({target}) : {reason}"),
+    ("Timeout", "Pas de réponse du TPE (délai de {seconds} s dépassé)"),
+    ("Refused", "Paiement refusé (code {code})"),
+    ("Cancelled", "Transaction annulée"),
+    ("ConnectionLost", "Connexion au TPE perdue : {reason}"),
+    ("Protocol", "Réponse du TPE incompréhensible : {reason}"),
+    ("Unsupported", "{protocol} : opération non prise en charge ({operation})"),
+    ("InvalidConfig", "Configuration du TPE invalide : {reason}"),
+    ("InvalidRequest", "Demande invalide : {reason}"),
+    ("UnknownTerminal", "TPE '{name}' introuvable"),
+    ("NoDefaultTerminal", "Aucun TPE par défaut configuré"),
+    ("DuplicateTerminal", "Un TPE nommé '{name}' existe déjà"),
+    ("UnknownPayment", "Aucune transaction TPE connue pour {payment_id}"),
+    ("Storage", "Enregistrement TPE impossible : {reason}"),
+];
+
+/// Field of a variant, sent as a number or a string
+enum Field<'a> {
+    Number(f64),
+    Text(&'a str),
+}
+
+impl TpeError {
+    /// Stable code sent to the frontend
+    pub fn code(&self) -> &'static str {
+        match self {
+            TpeError::ConnectFailed { .. } => "ConnectFailed",
+            TpeError::Timeout { .. } => "Timeout",
+            TpeError::Refused { .. } => "Refused",
+            TpeError::Cancelled => "Cancelled",
+            TpeError::ConnectionLost { .. } => "ConnectionLost",
+            TpeError::Protocol { .. } => "Protocol",
+            TpeError::Unsupported { .. } => "Unsupported",
+            TpeError::InvalidConfig { .. } => "InvalidConfig",
+            TpeError::InvalidRequest { .. } => "InvalidRequest",
+            TpeError::UnknownTerminal { .. } => "UnknownTerminal",
+            TpeError::NoDefaultTerminal => "NoDefaultTerminal",
+            TpeError::DuplicateTerminal { .. } => "DuplicateTerminal",
+            TpeError::UnknownPayment { .. } => "UnknownPayment",
+            TpeError::Storage { .. } => "Storage",
+        }
+    }
+    
+    fn fields(&self) -> Vec<(&'static str, Field<'_>)> {
+        match self {
+            TpeError::ConnectFailed { target, reason } => vec![("target", Field::Text(target)), ("reason", Field::Text(reason))],
+            TpeError::Timeout { seconds } => vec![("seconds", Field::Number(*seconds))],
+            TpeError::Refused { code } => vec![("code", Field::Text(code))],
+            TpeError::Unsupported { protocol, operation } => {
+                vec![("protocol", Field::Text(protocol)), ("operation", Field::Text(operation))]
+            }
+            TpeError::ConnectionLost { reason }
+            | TpeError::Protocol { reason }
+            | TpeError::InvalidConfig { reason }
+            | TpeError::InvalidRequest { reason }
+            | TpeError::Storage { reason } => vec![("reason", Field::Text(reason))],
+            TpeError::UnknownTerminal { name } | TpeError::DuplicateTerminal { name } => vec![("name", Field::Text(name))],
+            TpeError::UnknownPayment { payment_id } => vec![("payment_id", Field::Text(payment_id))],
+            TpeError::Cancelled | TpeError::NoDefaultTerminal => Vec::new(),
+        }
+    }
+    
+    /// User-facing message from the localization table
+    pub fn message(&self) -> String {
+        let template = MESSAGES_FR
+            .iter()
+            .find(|(code, _)| *code == self.code())
+            .map_or(self.code(), |(_, template)| template);
+        self.fields().iter().fold(template.to_string(), |message, (name, value)| {
+            let value = match value {
+                Field::Number(n) => n.to_string(),
+                Field::Text(t) => t.to_string(),
+            };
+            message.replace(&format!("{{{}}}", name), &value)
+        })
+    }
+}
+
+impl fmt::Display for TpeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl std::error::Error for TpeError {}
+
+impl Serialize for TpeError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = self.fields();
+        let mut map = serializer.serialize_map(Some(fields.len() + 2))?;
+        map.serialize_entry("code", self.code())?;
+        map.serialize_entry("message", &self.message())?;
+        for (name, value) in &fields {
+            match value {
+                Field::Number(n) => map.serialize_entry(name, n)?,
+                Field::Text(t) => map.serialize_entry(name, t)?,
+            }
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn every_code_has_a_message() {
+        let errors = [
+            TpeError::ConnectFailed { target: "192.168.1.50:8888".to_string(), reason: "refused".to_string() },
+            TpeError::Timeout { seconds: 150.0 },
+            TpeError::Refused { code: "05".to_string() },
+            TpeError::Cancelled,
+            TpeError::ConnectionLost { reason: "reset".to_string() },
+            TpeError::Protocol { reason: "LRC".to_string() },
+            TpeError::Unsupported { protocol: "Concert V2".to_string(), operation: "Télécollecte".to_string() },
+            TpeError::InvalidConfig { reason: "devise".to_string() },
+            TpeError::InvalidRequest { reason: "référence".to_string() },
+            TpeError::UnknownTerminal { name: "Bar".to_string() },
+            TpeError::NoDefaultTerminal,
+            TpeError::DuplicateTerminal { name: "Bar".to_string() },
+            TpeError::UnknownPayment { payment_id: "pay-1".to_string() },
+            TpeError::Storage { reason: "disque plein".to_string() },
+        ];
+        
+        for error in errors {
+            let message = error.message();
+            assert_ne!(message, error.code());
+            assert!(!message.contains('{'), "{}", message);
+        }
+    }
+    
+    #[test]
+    fn fills_the_template_with_the_fields() {
+        assert_eq!(TpeError::Timeout { seconds: 0.6 }.message(), "Pas de réponse du TPE (délai de 0.6 s dépassé)");
+        assert_eq!(TpeError::Refused { code: "051".to_string() }.to_string(), "Paiement refusé (code 051)");
+    }
+    
+    #[test]
+    fn serializes_code_message_and_fields() {
+        let json = serde_json::to_value(TpeError::Timeout { seconds: 150.0 }).unwrap();
+        
+        assert_eq!(json["code"], "Timeout");
+        assert_eq!(json["seconds"], 150.0);
+        assert_eq!(json["message"], "Pas de réponse du TPE (délai de 150 s dépassé)");
+        assert_eq!(serde_json::to_value(TpeError::Cancelled).unwrap(), serde_json::json!({
+            "code": "Cancelled",
+            "message": "Transaction annulée",
+        }));
+    }
+}
@@ -0,0 +1,113 @@
+// ===================================
+// TPE Transaction Ids - persistent per-till counter for the TI tag
+// ===================================
+//
+// Each till numbers its terminal transactions 1, 2, 3... up to 999999, then
+// starts over at 1; a number is never reused before that, even across restarts.
+// The counter is saved before the id is sent, so a crash may skip a number but
+// never sends the same one twice.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::pending::{data_path, write_durably};
+use crate::protocols::format_pos_number;
+use crate::tpe::log_to_file;
+
+const COUNTER_FILE_NAME: &str = "ma-caisse-tpe-counter.json";
+
+/// Largest id, so it always fits the 6 digits terminals print
+const MAX_ID: u64 = 999_999;
+
+// Serializes read-increment-write cycles on the counter file
+static COUNTER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Reserve the next TPE transaction id of the till (POS number).
+/// The TPE log links it to our POS transaction id.
+pub fn next_id(pos_number: &str, pos_transaction_id: Option<&str>) -> Result<String, String> {
+    let _guard = COUNTER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let till = format_pos_number(pos_number);
+    let id = format!("{:06}", allocate(&data_path(COUNTER_FILE_NAME), &till)?);
+    
+    log_to_file(&format!(
+        "TPE transaction id {} (till {}) <-> POS transaction {}",
+        id,
+        till,
+        pos_transaction_id.unwrap_or("-")
+    ));
+    Ok(id)
+}
+
+/// Increment and save the till's counter in `path`; callers hold COUNTER_LOCK
+fn allocate(path: &PathBuf, till: &str) -> Result<u64, String> {
+    // Starting over would reuse ids: an unreadable counter blocks payments instead
+    let mut counters: BTreeMap<String, u64> = match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map_err(|e| format!("Compteur de transactions TPE illisible ({}): {}", path.display(), e))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+        Err(e) => return Err(format!("Compteur de transactions TPE inaccessible ({}): {}", path.display(), e)),
+    };
+    
+    let counter = counters.entry(till.to_string()).or_insert(0);
+    *counter = if *counter >= MAX_ID { 1 } else { *counter + 1 };
+    let id = *counter;
+    
+    let json = serde_json::to_string_pretty(&counters).map_err(|e| e.to_string())?;
+    write_durably(path, &json).map_err(|e| format!("Impossible d'enregistrer le compteur de transactions TPE: {}", e))?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    
+    fn counter_file(name: &str) -> PathBuf {
+        let path = data_path(&format!("tpe-counter-{}.json", name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+    
+    #[test]
+    fn counts_per_till_and_survives_a_restart() {
+        let path = counter_file("restart");
+        
+        assert_eq!(allocate(&path, "01"), Ok(1));
+        assert_eq!(allocate(&path, "01"), Ok(2));
+        assert_eq!(allocate(&path, "02"), Ok(1));
+        // Only the file carries the state: a restart picks up where it stopped
+        assert_eq!(allocate(&path, "01"), Ok(3));
+        assert!(fs::read_to_string(&path).unwrap().contains("\"01\": 3"));
+    }
+    
+    #[test]
+    fn rolls_over_after_the_largest_id() {
+        let path = counter_file("rollover");
+        write_durably(&path, &format!("{{\"01\": {}}}", MAX_ID - 1)).unwrap();
+        
+        assert_eq!(allocate(&path, "01"), Ok(MAX_ID));
+        assert_eq!(allocate(&path, "01"), Ok(1));
+    }
+    
+    #[test]
+    fn unreadable_counter_blocks_ids() {
+        let path = counter_file("unreadable");
+        write_durably(&path, "not json").unwrap();
+        
+        assert!(allocate(&path, "01").unwrap_err().contains("illisible"));
+    }
+    
+    #[test]
+    fn concurrent_payments_never_share_an_id() {
+        let handles: Vec<_> = (0..8)
+            .map(|_| std::thread::spawn(|| (0..10).map(|_| next_id("97", None).unwrap()).collect::<Vec<_>>()))
+            .collect();
+        let ids: Vec<String> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+        
+        assert_eq!(ids.len(), 80);
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 80);
+    }
+}
@@ -0,0 +1,144 @@
+// ===================================
+// TPE Reconciliation - end of day totals (télécollecte)
+// ===================================
+//
+// Asks the terminal for the totals of its day (the télécollecte sends them to
+// the bank) and compares them with the card sales recorded by the till.
+// Only the TLV protocols (Concert V3 TLV, Caisse-AP IP, SmilePay) carry it.
+
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::currency::Currency;
+use crate::protocols::{TpeTransaction, TransactionType};
+use crate::terminals;
+use crate::tpe_error::TpeError;
+use crate::tpe::{execute_transaction, log_to_file, TpePaymentResponse};
+
+#[derive(Debug, Serialize)]
+pub struct TpeReconciliation {
+    pub success: bool,
+    pub terminal_name: String,
+    pub currency: Currency, // Amounts below are in its minor units
+    pub terminal_debit_minor: Option<u64>,  // MT: debits sent to the bank
+    pub terminal_credit_minor: Option<u64>, // MR: credits (refunds) sent to the bank
+    pub terminal_total_minor: Option<i64>,  // Debits minus credits
+    pub terminal_count: Option<u32>,        // NT: transactions in the batch
+    pub local_card_total_minor: i64,        // Card sales recorded by the till for the closure
+    pub difference_minor: Option<i64>,      // Terminal minus till, None when the terminal gave no total
+    pub balanced: bool,
+    pub ticket: Vec<String>, // Totals ticket printed by the terminal, when it sends one
+    pub error_message: Option<String>,
+    pub raw_response: Option<String>,
+}
+
+/// Compare the terminal's answer with the till's card total
+pub(crate) fn reconcile(response: TpePaymentResponse, terminal_name: &str, currency: &Currency, local_card_total_minor: i64) -> TpeReconciliation {
+    let amount = |tag: &str| response.tags.get(tag).and_then(|v| v.trim().parse::<u64>().ok());
+    
+    let debits = amount("MT");
+    let credits = amount("MR");
+    let total = debits.map(|d| d as i64 - credits.unwrap_or(0) as i64);
+    let difference = total.map(|t| t - local_card_total_minor);
+    let balanced = response.success && difference == Some(0);
+    
+    let error_message = match (response.success, difference) {
+        (false, _) => Some(response.error_message.unwrap_or_else(|| "Télécollecte refusée par le TPE".to_string())),
+        (true, None) => Some("Le TPE n'a renvoyé aucun total".to_string()),
+        (true, Some(0)) => None,
+        (true, Some(difference)) => Some(format!(
+            "Écart de {} {} entre le TPE et la caisse",
+            currency.format_minor(difference),
+            currency.alpha
+        )),
+    };
+    
+    TpeReconciliation {
+        success: response.success,
+        terminal_name: terminal_name.to_string(),
+        currency: currency.clone(),
+        terminal_debit_minor: debits,
+        terminal_credit_minor: credits,
+        terminal_total_minor: total,
+        terminal_count: response.tags.get("NT").and_then(|v| v.trim().parse().ok()),
+        local_card_total_minor,
+        difference_minor: difference,
+        balanced,
+        ticket: if response.merchant_ticket.is_empty() { response.cardholder_ticket } else { response.merchant_ticket },
+        error_message,
+        raw_response: response.raw_response,
+    }
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+/// Run the télécollecte on a registered terminal (the default one when no name
+/// is given) and compare its totals with `local_card_total_minor`
+#[tauri::command]
+pub async fn run_tpe_reconciliation(
+    app: AppHandle,
+    terminal_name: Option<String>,
+    local_card_total_minor: i64,
+    payment_id: Option<String>,
+) -> Result<TpeReconciliation, TpeError> {
+    let config = terminals::get(terminal_name.as_deref())?;
+    log_to_file(&format!(
+        "=== RECONCILIATION on '{}' ({}), till card total {} minor units ===",
+        config.name, config.port, local_card_total_minor
+    ));
+    
+    if config.port.ends_with("+ASCII") {
+        return Err(TpeError::Unsupported {
+            protocol: "Mode ASCII".to_string(),
+            operation: TransactionType::Reconciliation.label().to_string(),
+        });
+    }
+    
+    let name = config.name.clone();
+    let currency = config.currency.clone();
+    let tx = TpeTransaction::reconciliation(&config.pos_number);
+    let response = execute_transaction(Some(app), config, tx, payment_id).await?;
+    let reconciliation = reconcile(response, &name, &currency, local_card_total_minor);
+    
+    log_to_file(&format!(
+        "Reconciliation: terminal {:?} minor units ({:?} transactions), till {} minor units, difference {:?}",
+        reconciliation.terminal_total_minor,
+        reconciliation.terminal_count,
+        local_card_total_minor,
+        reconciliation.difference_minor
+    ));
+    Ok(reconciliation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn totals(debits: &str, credits: &str) -> TpePaymentResponse {
+        let mut response = TpePaymentResponse { success: true, ..Default::default() };
+        response.tags.insert("MT".to_string(), debits.to_string());
+        response.tags.insert("MR".to_string(), credits.to_string());
+        response
+    }
+    
+    #[test]
+    fn balanced_totals_have_no_message() {
+        let res = reconcile(totals("000000003250", "000000000250"), "TPE", &Currency::default(), 3000);
+        
+        assert!(res.balanced);
+        assert_eq!(res.difference_minor, Some(0));
+        assert_eq!(res.error_message, None);
+    }
+    
+    #[test]
+    fn shortfall_is_shown_in_the_terminal_currency() {
+        let eur = reconcile(totals("000000001000", "0"), "TPE", &Currency::default(), 1205);
+        let xpf = reconcile(totals("1000", "0"), "TPE", &Currency::known("XPF").unwrap(), 1205);
+        
+        assert_eq!(eur.difference_minor, Some(-205));
+        assert_eq!(eur.error_message.as_deref(), Some("Écart de -2.05 EUR entre le TPE et la caisse"));
+        assert_eq!(xpf.error_message.as_deref(), Some("Écart de -205 XPF entre le TPE et la caisse"));
+    }
+}
@@ -0,0 +1,364 @@
+// ===================================
+// TPE Sessions - warm TCP connections to network terminals
+// ===================================
+//
+// Each network terminal gets a session. When its protocol keeps the connection
+// between transactions (Caisse-AP IP, Concert over a TCP bridge), the idle
+// connection stays open and the next payment reuses it instead of connecting
+// again. A background monitor checks every registered terminal, reconnects
+// dropped connections (which also keeps sleeping terminals awake) and reports
+// each terminal going online or offline with a "tpe-status" event.
+// Terminals that hang up after each answer (Nepting RC) only get the check.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+
+use crate::protocols::TpeProtocol;
+use crate::terminals;
+use crate::tpe_error::TpeError;
+use crate::tpe::{log_to_file, open_tcp, TpeStream};
+
+/// Time between two checks of the registered terminals
+const MONITOR_INTERVAL: Duration = Duration::from_secs(20);
+
+static SESSIONS: Lazy<Mutex<HashMap<String, Session>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static MONITOR_STARTED: AtomicBool = AtomicBool::new(false);
+static APP_HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+/// State of one terminal, keyed by its "host:port" address
+#[derive(Default)]
+struct Session {
+    terminal_name: String,
+    keep_warm: bool,
+    idle: Option<TcpStream>, // Warm connection waiting for the next transaction
+    in_use: u32,             // Connections handed out (transactions or checks in progress)
+    online: Option<bool>,    // None until the first connection attempt
+    last_seen: Option<String>,
+    last_error: Option<TpeError>,
+}
+
+impl Session {
+    fn status(&self, address: &str) -> TpeSessionStatus {
+        TpeSessionStatus {
+            terminal_name: self.terminal_name.clone(),
+            address: address.to_string(),
+            online: self.online,
+            connected: self.idle.is_some() || self.in_use > 0,
+            last_seen: self.last_seen.clone(),
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+/// Payload of the "tpe-status" event and of get_tpe_sessions
+#[derive(Debug, Serialize, Clone)]
+pub struct TpeSessionStatus {
+    pub terminal_name: String,
+    pub address: String,
+    pub online: Option<bool>,
+    pub connected: bool, // A connection to the terminal is open
+    pub last_seen: Option<String>,
+    pub last_error: Option<TpeError>,
+}
+
+fn sessions() -> MutexGuard<'static, HashMap<String, Session>> {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record the outcome of a connection attempt, announcing online/offline changes
+fn set_status(address: &str, online: bool, error: Option<&TpeError>) {
+    let status = {
+        let mut sessions = sessions();
+        let session = sessions.entry(address.to_string()).or_default();
+        let changed = session.online != Some(online);
+        session.online = Some(online);
+        if online {
+            session.last_seen = Some(chrono::Local::now().to_rfc3339());
+            session.last_error = None;
+        } else {
+            session.last_error = error.cloned();
+        }
+        if !changed {
+            return;
+        }
+        session.status(address)
+    };
+    
+    log_to_file(&format!(
+        "Terminal '{}' ({}) {}",
+        status.terminal_name,
+        address,
+        if online { "online".to_string() } else { format!("offline: {:?}", status.last_error) }
+    ));
+    if let Some(app) = APP_HANDLE.get() {
+        let _ = app.emit("tpe-status", status);
+    }
+}
+
+/// Take the warm connection of a terminal (None when there is none) and count
+/// the caller as a user of the session until `give_back` or `release`
+fn take(address: &str) -> Option<TcpStream> {
+    let mut sessions = sessions();
+    let session = sessions.entry(address.to_string()).or_default();
+    session.in_use += 1;
+    session.idle.take()
+}
+
+/// Keep a healthy connection as the terminal's warm connection
+fn give_back(address: &str, stream: TcpStream, healthy: bool) {
+    let mut sessions = sessions();
+    let Some(session) = sessions.get_mut(address) else {
+        return;
+    };
+    session.in_use = session.in_use.saturating_sub(1);
+    if healthy && session.keep_warm && session.idle.is_none() {
+        session.idle = Some(stream);
+    }
+}
+
+fn release(address: &str) {
+    if let Some(session) = sessions().get_mut(address) {
+        session.in_use = session.in_use.saturating_sub(1);
+    }
+}
+
+/// Is the idle connection still open? A terminal that vanished without closing
+/// shows up as a read error once TCP keepalive gives up. Bytes the terminal sent
+/// while idle are logged and discarded.
+fn is_alive(stream: &TcpStream, address: &str) -> bool {
+    let mut buf = [0u8; 256];
+    loop {
+        match stream.try_read(&mut buf) {
+            Ok(0) => return false, // Closed by the terminal
+            Ok(n) => log_to_file(&format!(
+                "Session {}: discarding {} idle bytes: {}",
+                address,
+                n,
+                String::from_utf8_lossy(&buf[..n])
+            )),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Connection for a transaction: the terminal's warm connection when it is
+/// still open, else a new one. It goes back to the session when dropped,
+/// unless `keep_warm` is false or the transport failed.
+pub(crate) async fn checkout(address: &str, keep_warm: bool, connect_timeout: Duration) -> Result<Box<dyn TpeStream>, TpeError> {
+    let warm = take(address);
+    if let Some(session) = sessions().get_mut(address) {
+        session.keep_warm = keep_warm;
+    }
+    
+    let stream = match warm {
+        Some(stream) if is_alive(&stream, address) => {
+            log_to_file(&format!("Reusing warm connection to {}", address));
+            stream
+        }
+        _ => {
+            log_to_file(&format!("Connecting TCP to {}", address));
+            match open_tcp(address, connect_timeout).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log_to_file(&format!("TCP Error {}: {:?}", address, e));
+                    release(address);
+                    set_status(address, false, Some(&e));
+                    return Err(e);
+                }
+            }
+        }
+    };
+    
+    set_status(address, true, None);
+    Ok(Box::new(PooledStream {
+        address: address.to_string(),
+        stream: Some(stream),
+        healthy: true,
+    }))
+}
+
+/// Check that the terminal answers on TCP, reconnecting its warm connection
+/// when it dropped. A terminal busy with a transaction counts as online.
+pub(crate) async fn probe(address: &str, connect_timeout: Duration) -> Result<(), TpeError> {
+    if sessions().get(address).is_some_and(|s| s.in_use > 0) {
+        return Ok(());
+    }
+    
+    if let Some(stream) = take(address) {
+        if is_alive(&stream, address) {
+            give_back(address, stream, true);
+            set_status(address, true, None);
+            return Ok(());
+        }
+        log_to_file(&format!("Warm connection to {} lost, reconnecting", address));
+    }
+    
+    match open_tcp(address, connect_timeout).await {
+        Ok(stream) => {
+            // Kept only when the protocol keeps its connection
+            give_back(address, stream, true);
+            set_status(address, true, None);
+            Ok(())
+        }
+        Err(e) => {
+            release(address);
+            set_status(address, false, Some(&e));
+            Err(e)
+        }
+    }
+}
+
+/// Check every registered network terminal and forget the removed ones
+async fn check_terminals() {
+    let terminals = match terminals::all() {
+        Ok(terminals) => terminals,
+        Err(e) => {
+            log_to_file(&format!("Session monitor: {}", e));
+            return;
+        }
+    };
+    
+    let watched: Vec<(String, String, bool, Duration)> = terminals
+        .iter()
+        .filter_map(|terminal| {
+            let address = terminal.port.trim_end_matches("+ASCII").trim();
+            let protocol = TpeProtocol::from_version(terminal.protocol_version).for_transport(true);
+            if protocol.is_http() || !address.contains(':') {
+                return None;
+            }
+            // The plain text fallback opens its own connection
+            let keep_warm = protocol.keeps_connection() && !terminal.port.ends_with("+ASCII");
+            Some((address.to_string(), terminal.name.clone(), keep_warm, terminal.timeouts.connect()))
+        })
+        .collect();
+    
+    {
+        let mut sessions = sessions();
+        sessions.retain(|address, session| session.in_use > 0 || watched.iter().any(|(a, _, _, _)| a == address));
+        for (address, name, keep_warm, _) in &watched {
+            let session = sessions.entry(address.clone()).or_default();
+            session.terminal_name = name.clone();
+            session.keep_warm = *keep_warm;
+            if !keep_warm {
+                session.idle = None;
+            }
+        }
+    }
+    
+    for (address, _, _, connect_timeout) in &watched {
+        let _ = probe(address, *connect_timeout).await;
+    }
+}
+
+// ===================================
+// Pooled Connection
+// ===================================
+
+/// TCP connection handed to a transaction, returned to its session on drop
+struct PooledStream {
+    address: String,
+    stream: Option<TcpStream>, // Some until dropped
+    healthy: bool,             // Cleared on any transport error, when the terminal hangs up or on discard()
+}
+
+impl TpeStream for PooledStream {
+    fn discard(&mut self) {
+        self.healthy = false;
+    }
+}
+
+impl PooledStream {
+    fn inner(&mut self) -> Pin<&mut TcpStream> {
+        Pin::new(self.stream.as_mut().expect("pooled stream used after drop"))
+    }
+}
+
+impl AsyncRead for PooledStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let had_room = buf.remaining() > 0;
+        let filled = buf.filled().len();
+        let result = this.inner().poll_read(cx, buf);
+        match &result {
+            Poll::Ready(Err(_)) => this.healthy = false,
+            Poll::Ready(Ok(())) if had_room && buf.filled().len() == filled => this.healthy = false, // EOF
+            _ => {}
+        }
+        result
+    }
+}
+
+impl AsyncWrite for PooledStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = this.inner().poll_write(cx, data);
+        if let Poll::Ready(Err(_)) = result {
+            this.healthy = false;
+        }
+        result
+    }
+    
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let result = this.inner().poll_flush(cx);
+        if let Poll::Ready(Err(_)) = result {
+            this.healthy = false;
+        }
+        result
+    }
+    
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.healthy = false;
+        this.inner().poll_shutdown(cx)
+    }
+}
+
+impl Drop for PooledStream {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            give_back(&self.address, stream, self.healthy);
+        }
+    }
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+/// Start the background monitor of the registered network terminals (once per app run)
+#[tauri::command]
+pub async fn start_tpe_monitor(app: AppHandle) {
+    let _ = APP_HANDLE.set(app);
+    if MONITOR_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    
+    log_to_file("Starting TPE session monitor");
+    tokio::spawn(async {
+        loop {
+            check_terminals().await;
+            tokio::time::sleep(MONITOR_INTERVAL).await;
+        }
+    });
+}
+
+/// Online/offline state of the network terminals
+#[tauri::command]
+pub fn get_tpe_sessions() -> Vec<TpeSessionStatus> {
+    let sessions = sessions();
+    let mut statuses: Vec<TpeSessionStatus> = sessions.iter().map(|(address, s)| s.status(address)).collect();
+    statuses.sort_by(|a, b| a.terminal_name.cmp(&b.terminal_name));
+    statuses
+}
@@ -0,0 +1,930 @@
+// ===================================
+// TPE Simulator - Concert V2/V3 (pty) and Caisse-AP IP (TCP)
+// ===================================
+//
+// Plays the terminal side of the exchange so payments can be tested without
+// hardware, by hand (src/bin/tpe-simulator.rs) or end to end in the tests below.
+// Each transaction plays the next scripted outcome, then "approved" once the
+// script is exhausted. The answer format follows the request: TLV for TLV
+// requests, fixed V2 (14 chars) or V3 (19 chars) otherwise. Approved debits
+// add up to the totals reported by a reconciliation (télécollecte) request.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::protocols::{calculate_lrc, frame_message, TransactionType};
+use crate::tlv;
+use crate::tpe::{ACK, CAN, ENQ, EOT, ETX, NAK, STX};
+
+/// Time the simulated terminal waits for each step of the POS
+const STEP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Time for the POS's EOT after our ACK (none comes when it stopped waiting for the ACK)
+const EOT_WAIT: Duration = Duration::from_millis(300);
+
+/// AF code of a transaction the POS aborted
+const ABANDONED: &str = "11";
+
+/// Retransmissions of a frame the POS NAKs
+const MAX_RETRANSMISSIONS: usize = 3;
+
+/// Outcome played for one transaction
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimOutcome {
+    Approved,
+    Refused(String),   // AF code (Concert) or CO code (Caisse-AP)
+    Timeout,           // Take the request but never answer
+    Nak,               // NAK every request frame
+    Garbage,           // Answer with a corrupted frame
+    SlowAck(Duration), // Acknowledge the request late, then approve
+}
+
+impl std::str::FromStr for SimOutcome {
+    type Err = String;
+    
+    /// approved | refused[:CODE] | timeout | nak | garbage | slow-ack[:MS]
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (name, arg) = match s.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        match (name.trim().to_lowercase().as_str(), arg) {
+            ("approved", None) => Ok(SimOutcome::Approved),
+            ("refused", code) => {
+                let code = code.unwrap_or("02");
+                if code.is_empty() || code.len() > 3 || !code.chars().all(|c| c.is_ascii_alphanumeric()) {
+                    return Err(format!("Invalid refusal code '{}'", code));
+                }
+                Ok(SimOutcome::Refused(code.to_string()))
+            }
+            ("timeout", None) => Ok(SimOutcome::Timeout),
+            ("nak", None) => Ok(SimOutcome::Nak),
+            ("garbage", None) => Ok(SimOutcome::Garbage),
+            ("slow-ack", ms) => ms
+                .unwrap_or("3000")
+                .parse::<u64>()
+                .map(|ms| SimOutcome::SlowAck(Duration::from_millis(ms)))
+                .map_err(|e| format!("Invalid slow-ack delay: {}", e)),
+            _ => Err(format!(
+                "Unknown outcome '{}' (approved, refused:CODE, timeout, nak, garbage, slow-ack:MS)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SimState {
+    script: Mutex<VecDeque<SimOutcome>>,
+    requests: Mutex<Vec<String>>, // Request bodies, between STX and ETX
+    text_requests: Mutex<Vec<String>>, // Unframed text lines (ASCII fallback)
+    cancel_received: AtomicBool,
+    stop: AtomicBool,
+    counter: AtomicU32,
+    captured_minor: AtomicU64, // Approved debits since the last reconciliation
+    captured_count: AtomicU32,
+    connections: AtomicU32, // TCP connections accepted (Caisse-AP)
+}
+
+impl SimState {
+    fn next_outcome(&self) -> SimOutcome {
+        self.script
+            .lock()
+            .ok()
+            .and_then(|mut script| script.pop_front())
+            .unwrap_or(SimOutcome::Approved)
+    }
+    
+    /// Add an approved debit to the day totals
+    fn capture(&self, fields: &tlv::TlvMessage) {
+        if fields.get("CD") == Some(TransactionType::Debit.code()) {
+            let amount = fields.get("CB").and_then(|cb| cb.parse::<u64>().ok()).unwrap_or(0);
+            self.captured_minor.fetch_add(amount, Ordering::SeqCst);
+            self.captured_count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    
+    /// Day totals as MT / MR / NT fields, reset like a real télécollecte
+    fn totals(&self) -> Vec<(&'static str, String)> {
+        let captured = self.captured_minor.swap(0, Ordering::SeqCst);
+        let count = self.captured_count.swap(0, Ordering::SeqCst);
+        vec![
+            ("MT", format!("{:012}", captured)),
+            ("MR", format!("{:012}", 0)),
+            ("NT", format!("{:06}", count)),
+            ("TM", format!("TELECOLLECTE\r\nDEBITS {}.{:02} EUR\r\n{} TRANSACTIONS", captured / 100, captured % 100, count)),
+        ]
+    }
+    
+    fn record(&self, body: &[u8]) {
+        let body = String::from_utf8_lossy(body).to_string();
+        println!("[SIM] Request: {}", body);
+        if let Ok(mut requests) = self.requests.lock() {
+            requests.push(body);
+        }
+    }
+    
+    fn record_text(&self, line: &[u8]) {
+        let line = String::from_utf8_lossy(line).to_string();
+        println!("[SIM] Text request: {}", line);
+        if let Ok(mut lines) = self.text_requests.lock() {
+            lines.push(line);
+        }
+    }
+}
+
+/// A running simulated terminal, stopped when dropped
+pub struct TpeSimulator {
+    address: String,
+    state: Arc<SimState>,
+    // The pty slave stays open so the master never sees a hang-up between two POS connections
+    #[cfg(unix)]
+    _slave: Option<serialport::TTYPort>,
+}
+
+impl TpeSimulator {
+    /// Concert V2/V3 terminal on a new pseudo-terminal; connect to `address()`
+    #[cfg(unix)]
+    pub fn concert() -> Result<Self, String> {
+        let (master, slave) = serialport::TTYPort::pair().map_err(|e| format!("pty: {}", e))?;
+        let address = serialport::SerialPort::name(&slave).ok_or("pty: no slave name")?;
+        
+        let state = Arc::new(SimState::default());
+        let thread_state = state.clone();
+        std::thread::spawn(move || {
+            let mut link = Link { stream: master, state: thread_state };
+            while !link.state.stop.load(Ordering::SeqCst) {
+                if let Err(e) = concert_session(&mut link) {
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        println!("[SIM] Concert session error: {}", e);
+                    }
+                }
+            }
+        });
+        
+        println!("[SIM] Concert terminal on {}", address);
+        Ok(TpeSimulator { address, state, _slave: Some(slave) })
+    }
+    
+    /// Caisse-AP IP terminal listening on `bind` ("127.0.0.1:0" picks a free port)
+    pub fn caisse_ap(bind: &str) -> Result<Self, String> {
+        let listener = TcpListener::bind(bind).map_err(|e| format!("Bind {}: {}", bind, e))?;
+        let address = listener.local_addr().map_err(|e| e.to_string())?.to_string();
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        
+        let state = Arc::new(SimState::default());
+        let thread_state = state.clone();
+        std::thread::spawn(move || {
+            while !thread_state.stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        println!("[SIM] POS connected from {}", peer);
+                        thread_state.connections.fetch_add(1, Ordering::SeqCst);
+                        let _ = stream.set_nonblocking(false);
+                        let _ = stream.set_read_timeout(Some(Duration::from_millis(50)));
+                        let mut link = Link { stream, state: thread_state.clone() };
+                        if let Err(e) = caisse_ap_session(&mut link) {
+                            println!("[SIM] Caisse-AP session ended: {}", e);
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(20));
+                    }
+                    Err(e) => println!("[SIM] Accept error: {}", e),
+                }
+            }
+        });
+        
+        println!("[SIM] Caisse-AP terminal on {}", address);
+        Ok(TpeSimulator {
+            address,
+            state,
+            #[cfg(unix)]
+            _slave: None,
+        })
+    }
+    
+    /// Serial port path or host:port to configure as the terminal's port
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+    
+    /// Queue outcomes for the next transactions
+    pub fn script(&self, outcomes: impl IntoIterator<Item = SimOutcome>) {
+        if let Ok(mut script) = self.state.script.lock() {
+            script.extend(outcomes);
+        }
+    }
+    
+    /// Request bodies received so far
+    pub fn requests(&self) -> Vec<String> {
+        self.state.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+    
+    /// Unframed text lines received so far (ASCII fallback: "DEBIT 5.00 EUR")
+    pub fn text_requests(&self) -> Vec<String> {
+        self.state.text_requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+    
+    /// TCP connections the POS opened so far
+    pub fn connections(&self) -> u32 {
+        self.state.connections.load(Ordering::SeqCst)
+    }
+    
+    /// Did the POS send the CAN cancellation sequence?
+    pub fn cancel_received(&self) -> bool {
+        self.state.cancel_received.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for TpeSimulator {
+    fn drop(&mut self) {
+        self.state.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+// ===================================
+// Byte Level Access
+// ===================================
+
+/// The terminal's end of the line
+struct Link<S: Read + Write> {
+    stream: S,
+    state: Arc<SimState>,
+}
+
+impl<S: Read + Write> Link<S> {
+    /// Next byte within the timeout (None on timeout). CAN from the POS is recorded.
+    fn byte(&mut self, timeout: Duration) -> io::Result<Option<u8>> {
+        let start = Instant::now();
+        let mut buf = [0u8; 1];
+        
+        while start.elapsed() < timeout {
+            if self.state.stop.load(Ordering::SeqCst) {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "simulator stopped"));
+            }
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "POS closed the connection")),
+                Ok(_) => {
+                    if buf[0] == CAN {
+                        println!("[SIM] CAN received");
+                        self.state.cancel_received.store(true, Ordering::SeqCst);
+                    }
+                    return Ok(Some(buf[0]));
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+        
+        Ok(None)
+    }
+    
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.stream.write_all(data)?;
+        self.stream.flush()
+    }
+    
+    /// Read one STX..ETX+LRC frame: (body, LRC valid). Bytes before STX are
+    /// ignored, except CR-terminated text lines which are recorded.
+    fn frame(&mut self, timeout: Duration) -> io::Result<Option<(Vec<u8>, bool)>> {
+        let deadline = Instant::now() + timeout;
+        let remaining = || deadline.saturating_duration_since(Instant::now());
+        
+        let mut text = Vec::new();
+        loop {
+            match self.byte(remaining())? {
+                Some(STX) => break,
+                Some(other) => self.text_byte(&mut text, other),
+                None => return Ok(None),
+            }
+        }
+        let mut body = Vec::new();
+        loop {
+            match self.byte(remaining())? {
+                Some(ETX) => break,
+                Some(b) => body.push(b),
+                None => return Ok(None),
+            }
+        }
+        let Some(lrc) = self.byte(remaining())? else {
+            return Ok(None);
+        };
+        
+        let mut checked = body.clone();
+        checked.push(ETX);
+        Ok(Some((body, calculate_lrc(&checked) == lrc)))
+    }
+    
+    /// Collect unframed text, recording each CR-terminated line (ASCII fallback)
+    fn text_byte(&self, text: &mut Vec<u8>, byte: u8) {
+        match byte {
+            b'\r' if !text.is_empty() => self.state.record_text(&std::mem::take(text)),
+            b' ' => text.push(byte),
+            _ if byte.is_ascii_graphic() => text.push(byte),
+            _ => text.clear(),
+        }
+    }
+    
+    /// Consume bytes until the line stays quiet (or a CAN arrives)
+    fn drain_until_cancel(&mut self) -> io::Result<()> {
+        while self.byte(STEP_TIMEOUT)?.is_some() {
+            if self.state.cancel_received.load(Ordering::SeqCst) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+// ===================================
+// Concert Terminal
+// ===================================
+
+/// One Concert exchange: ENQ/ACK, request frame, then our answer frame
+fn concert_session<S: Read + Write>(link: &mut Link<S>) -> io::Result<()> {
+    // Idle: wait for the POS's ENQ
+    let mut text = Vec::new();
+    loop {
+        match link.byte(Duration::from_millis(500))? {
+            Some(ENQ) => break,
+            Some(other) => link.text_byte(&mut text, other),
+            None => return Ok(()),
+        }
+    }
+    link.send(&[ACK])?;
+    
+    let outcome = link.state.next_outcome();
+    println!("[SIM] Transaction outcome: {:?}", outcome);
+    
+    // Request frame, NAKed on a bad LRC (or always, for the NAK outcome) until the POS gives up
+    let mut request = None;
+    for _ in 0..=MAX_RETRANSMISSIONS {
+        let Some((body, lrc_ok)) = link.frame(STEP_TIMEOUT)? else {
+            println!("[SIM] No request frame");
+            return Ok(());
+        };
+        if !lrc_ok || outcome == SimOutcome::Nak {
+            link.send(&[NAK])?;
+            continue;
+        }
+        request = Some(body);
+        break;
+    }
+    let Some(request) = request else {
+        return Ok(());
+    };
+    link.state.record(&request);
+    
+    if let SimOutcome::SlowAck(delay) = outcome {
+        std::thread::sleep(delay);
+    }
+    link.send(&[ACK])?;
+    let _ = link.byte(EOT_WAIT)?; // POS releases the line with EOT
+    
+    if outcome == SimOutcome::Timeout {
+        return link.drain_until_cancel();
+    }
+    
+    // Our emission: ENQ, frame (resent on NAK), EOT. A POS abort (CAN ... EOT)
+    // crossing our ENQ turns the answer into "abandoned".
+    link.send(&[ENQ])?;
+    let mut aborted = false;
+    loop {
+        match link.byte(STEP_TIMEOUT)? {
+            Some(ACK) => break,
+            Some(CAN) => aborted = true,
+            Some(EOT) if aborted => {}
+            _ => {
+                println!("[SIM] POS did not acknowledge our ENQ");
+                return Ok(());
+            }
+        }
+    }
+    let outcome = if aborted { SimOutcome::Refused(ABANDONED.to_string()) } else { outcome };
+    
+    let answer = concert_answer(&request, &outcome, &link.state);
+    let mut frame = frame_message(&answer);
+    if outcome == SimOutcome::Garbage {
+        let lrc = frame.len() - 1;
+        frame[lrc] ^= 0x01;
+    }
+    for _ in 0..=MAX_RETRANSMISSIONS {
+        link.send(&frame)?;
+        match link.byte(STEP_TIMEOUT)? {
+            Some(NAK) => continue,
+            Some(ACK) => break,
+            other => {
+                println!("[SIM] Answer frame not acknowledged ({:?})", other);
+                return Ok(());
+            }
+        }
+    }
+    link.send(&[EOT])
+}
+
+/// Answer body in the request's format
+fn concert_answer(request: &[u8], outcome: &SimOutcome, state: &SimState) -> String {
+    let text = String::from_utf8_lossy(request).to_string();
+    let code = match outcome {
+        SimOutcome::Refused(code) => code.as_str(),
+        _ => "00",
+    };
+    
+    // TLV request (Concert V3 / SmilePay)
+    if let Some(fields) = tlv::decode(request).ok().filter(|m| m.get("CZ").is_some()) {
+        let echo = |tag: &str| fields.get(tag).unwrap_or_default().to_string();
+        if code != "00" {
+            return encode(&[("AE", "01".to_string()), ("AF", code.to_string()), ("CA", echo("CA"))]);
+        }
+        if fields.get("CD") == Some(TransactionType::Reconciliation.code()) {
+            let mut answer = vec![("AE", "10".to_string()), ("CA", echo("CA"))];
+            answer.extend(state.totals());
+            return encode(&answer);
+        }
+        state.capture(&fields);
+        return encode(&[
+            ("AE", "10".to_string()),
+            ("CA", echo("CA")),
+            ("CB", echo("CB")),
+            ("CE", echo("CE")),
+            ("AC", "123456".to_string()),
+            ("PA", "497010******1234".to_string()),
+            ("MA", "CB".to_string()),
+            ("ME", "C".to_string()),
+            ("TA", acquirer_reference(state)),
+        ]);
+    }
+    
+    // Fixed formats echo the type and amount around the result code
+    match text.len() {
+        19 => format!("{}{:0>2}{}", &text[..2], code, &text[4..16]), // V3: TYPE(2) POS(2) AMOUNT(12)
+        _ => format!("{}{:0>2}{}", text.get(..1).unwrap_or("0"), code, text.get(3..11).unwrap_or_default()), // V2
+    }
+}
+
+// ===================================
+// Caisse-AP IP Terminal
+// ===================================
+
+/// Caisse-AP IP exchanges on one TCP connection: TLV frame in, TLV frame out
+fn caisse_ap_session(link: &mut Link<TcpStream>) -> io::Result<()> {
+    loop {
+        let Some((request, _)) = link.frame(Duration::from_secs(60))? else {
+            return Ok(());
+        };
+        link.state.record(&request);
+        
+        let outcome = link.state.next_outcome();
+        println!("[SIM] Transaction outcome: {:?}", outcome);
+        
+        let answer = match &outcome {
+            SimOutcome::Timeout => return link.drain_until_cancel(),
+            SimOutcome::Nak => {
+                link.send(&[NAK])?;
+                return Ok(()); // Hang up
+            }
+            SimOutcome::Garbage => {
+                let mut frame = frame_message("#!?garbage");
+                let lrc = frame.len() - 1;
+                frame[lrc] ^= 0x01;
+                link.send(&frame)?;
+                return Ok(());
+            }
+            SimOutcome::SlowAck(delay) => {
+                std::thread::sleep(*delay);
+                caisse_ap_answer(&request, "00", &link.state)
+            }
+            SimOutcome::Refused(code) => caisse_ap_answer(&request, code, &link.state),
+            SimOutcome::Approved => caisse_ap_answer(&request, "00", &link.state),
+        };
+        link.send(&frame_message(&answer))?;
+        
+        // The POS acknowledges with ACK + EOT
+        let _ = link.byte(STEP_TIMEOUT)?;
+        let _ = link.byte(STEP_TIMEOUT)?;
+    }
+}
+
+fn caisse_ap_answer(request: &[u8], code: &str, state: &SimState) -> String {
+    let fields = tlv::decode(request).unwrap_or_default();
+    let echo = |tag: &str| fields.get(tag).unwrap_or_default().to_string();
+    
+    if code != "00" {
+        return encode(&[("CA", echo("CA")), ("TI", echo("TI")), ("CV", "01".to_string()), ("CO", code.to_string())]);
+    }
+    if fields.get("CD") == Some(TransactionType::Reconciliation.code()) {
+        let mut answer = vec![("CA", echo("CA")), ("TI", echo("TI")), ("CV", "00".to_string())];
+        answer.extend(state.totals());
+        return encode(&answer);
+    }
+    state.capture(&fields);
+    encode(&[
+        ("CA", echo("CA")),
+        ("TI", echo("TI")),
+        ("CB", echo("CB")),
+        ("CE", echo("CE")),
+        ("CV", "00".to_string()),
+        ("AC", "654321".to_string()),
+        ("PA", "513100******9876".to_string()),
+        ("AI", "A0000000041010".to_string()),
+        ("ME", "P".to_string()),
+        ("TA", acquirer_reference(state)),
+        ("TC", "CARTE BANCAIRE\r\nMASTERCARD\r\n\r\nDEBIT\r\nTICKET CLIENT A CONSERVER".to_string()),
+    ])
+}
+
+// ===================================
+// Helpers
+// ===================================
+
+/// TLV body without the POS-side validation, so any value can be simulated
+fn encode(fields: &[(&str, String)]) -> String {
+    fields
+        .iter()
+        .map(|(tag, value)| format!("{}{:03}{}", tag, value.len(), value))
+        .collect()
+}
+
+fn acquirer_reference(state: &SimState) -> String {
+    format!("SIM{:06}", state.counter.fetch_add(1, Ordering::SeqCst) + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+    use crate::pending::list_pending_tpe_payments;
+    use crate::protocols::{CardApplication, TpeProtocol, TpeTransaction};
+    use crate::serial_line::SerialSettings;
+    use crate::tpe::{cancel_tpe_transaction, execute_transaction, TpeConfig, TpePaymentResponse};
+    use crate::tpe_detect::detect_tpe_protocol;
+    use crate::tpe_error::TpeError;
+    use crate::tpe_reconciliation::reconcile;
+    use crate::tpe_timeouts::TpeTimeouts;
+    
+    fn config(port: &str, protocol_version: u8) -> TpeConfig {
+        TpeConfig {
+            name: "Simulateur".to_string(),
+            port: port.to_string(),
+            baud_rate: 9600,
+            pos_number: "01".to_string(),
+            protocol_version,
+            terminal_id: None,
+            api_key: None,
+            currency: Currency::default(),
+            serial: SerialSettings::default(),
+            // The simulator answers at once: short ACK wait keeps the slow cases quick
+            timeouts: TpeTimeouts { ack_ms: 300, ..TpeTimeouts::default() },
+        }
+    }
+    
+    async fn pay(sim: &TpeSimulator, protocol_version: u8, amount_minor: u32) -> Result<TpePaymentResponse, TpeError> {
+        let tx = TpeTransaction::debit(amount_minor, "01");
+        execute_transaction(None, config(sim.address(), protocol_version), tx, None).await
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_v3_tlv_approved() {
+        let sim = TpeSimulator::concert().unwrap();
+        
+        let res = pay(&sim, 3, 1250).await.unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.authorization_number.as_deref(), Some("123456"));
+        assert_eq!(res.card_brand.as_deref(), Some("CB"));
+        assert_eq!(res.entry_mode.as_deref(), Some("CONTACTLESS"));
+        assert!(sim.requests()[0].contains("CB012000000001250"));
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_v2_refused() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::Refused("02".to_string())]);
+        
+        let res = pay(&sim, 2, 500).await.unwrap();
+        
+        assert!(!res.success);
+        assert_eq!(res.transaction_result, "02");
+        assert_eq!(res.error, Some(TpeError::Refused { code: "02".to_string() }));
+        assert_eq!(sim.requests(), vec!["00100000500978".to_string()]);
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_v3_binary_refused_is_not_approved() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::Refused("02".to_string()), SimOutcome::Approved]);
+        
+        let refused = pay(&sim, 4, 500).await.unwrap();
+        let approved = pay(&sim, 4, 500).await.unwrap();
+        
+        assert!(!refused.success);
+        assert_eq!(refused.transaction_result, "02");
+        assert!(approved.success);
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_v2_refund_with_reference_is_not_sent() {
+        let sim = TpeSimulator::concert().unwrap();
+        let tx = TpeTransaction::credit(500, "01", Some("REF123".to_string()));
+        
+        let err = execute_transaction(None, config(sim.address(), 2), tx, None).await.unwrap_err();
+        
+        assert!(matches!(err, TpeError::Unsupported { ref operation, .. } if operation.contains("référence")));
+        assert!(sim.requests().is_empty());
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_nak_falls_back_to_ascii() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::Nak]);
+        
+        let tx = TpeTransaction::debit(500, "01");
+        let res = execute_transaction(None, config(sim.address(), 3), tx, Some("sim-nak".to_string())).await;
+        
+        assert_eq!(res.unwrap_err(), TpeError::Timeout { seconds: 0.6 });
+        assert_eq!(sim.text_requests(), vec!["DEBIT 5.00 EUR".to_string()]);
+        assert!(sim.requests().is_empty());
+        // The ASCII debit left the POS: it stays in the journal until checked
+        let entry = list_pending_tpe_payments().into_iter().find(|p| p.payment_id == "sim-nak").unwrap();
+        assert!(entry.last_error.is_some());
+    }
+    
+    #[tokio::test]
+    async fn invalid_request_is_not_journaled() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        let tx = TpeTransaction::debit(500, "AB");
+        
+        let err = execute_transaction(None, config(sim.address(), 8), tx, Some("sim-invalid".to_string())).await.unwrap_err();
+        
+        assert_eq!(err.code(), "InvalidRequest");
+        assert!(!list_pending_tpe_payments().iter().any(|p| p.payment_id == "sim-invalid"));
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_garbage_answer_is_rejected() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::Garbage]);
+        
+        let res = pay(&sim, 3, 500).await;
+        
+        let err = res.unwrap_err();
+        assert_eq!(err.code(), "Protocol");
+        assert!(err.to_string().contains("LRC invalide"));
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_slow_ack_still_gets_the_answer() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::SlowAck(Duration::from_millis(500))]);
+        
+        let res = pay(&sim, 3, 500).await.unwrap();
+        
+        assert!(res.success);
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn detection_stops_at_the_first_understood_framing() {
+        let sim = TpeSimulator::concert().unwrap();
+        
+        let res = detect_tpe_protocol(sim.address().to_string(), 9600, "01".to_string(), None, None, None).await.unwrap();
+        
+        assert_eq!(res.probes.len(), 1);
+        assert!(res.probes[0].answered);
+        assert_eq!(res.suggested.unwrap().protocol_version, TpeProtocol::ConcertV3Tlv.version());
+        assert_eq!(sim.requests().len(), 1);
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn detection_infers_ascii_without_sending_a_text_debit() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::Nak, SimOutcome::Nak, SimOutcome::Nak]);
+        
+        let res = detect_tpe_protocol(sim.address().to_string(), 9600, "01".to_string(), None, None, None).await.unwrap();
+        
+        assert_eq!(res.probes.len(), 4);
+        assert!(res.probes.last().unwrap().ascii);
+        assert!(res.suggested.unwrap().port.ends_with("+ASCII"));
+        assert!(sim.text_requests().is_empty());
+    }
+    
+    #[tokio::test]
+    async fn invalid_timeouts_are_rejected_before_connecting() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        let mut config = config(sim.address(), 8);
+        config.timeouts.card_wait_secs = Some(5);
+        
+        let err = execute_transaction(None, config, TpeTransaction::debit(500, "01"), None).await.unwrap_err();
+        
+        assert_eq!(err.code(), "InvalidConfig");
+        assert_eq!(sim.connections(), 0);
+    }
+    
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn concert_timeout_is_cancelled() {
+        let sim = TpeSimulator::concert().unwrap();
+        sim.script([SimOutcome::Timeout]);
+        let cancel_once_sent = async {
+            // Cancel while the terminal holds the request
+            while sim.requests().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel_tpe_transaction("sim-timeout".to_string()).unwrap();
+        };
+        
+        let tx = TpeTransaction::debit(500, "01");
+        let payment = execute_transaction(None, config(sim.address(), 3), tx, Some("sim-timeout".to_string()));
+        let (res, _) = tokio::join!(payment, cancel_once_sent);
+        let res = res.unwrap();
+        
+        assert_eq!(res.transaction_result, "CANCELLED");
+        assert_eq!(res.error, Some(TpeError::Cancelled));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(sim.cancel_received());
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_timeout_is_cancelled() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        sim.script([SimOutcome::Timeout]);
+        let cancel_once_sent = async {
+            while sim.requests().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            cancel_tpe_transaction("sim-ap-timeout".to_string()).unwrap();
+        };
+        
+        let tx = TpeTransaction::debit(500, "01");
+        let payment = execute_transaction(None, config(sim.address(), 8), tx, Some("sim-ap-timeout".to_string()));
+        let (res, _) = tokio::join!(payment, cancel_once_sent);
+        let res = res.unwrap();
+        
+        assert_eq!(res.transaction_result, "CANCELLED");
+        assert_eq!(res.error, Some(TpeError::Cancelled));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(sim.cancel_received());
+        // The request had left: the terminal may still approve it after the CAN
+        let entry = list_pending_tpe_payments().into_iter().find(|p| p.payment_id == "sim-ap-timeout").unwrap();
+        assert!(entry.last_error.is_some());
+    }
+    
+    #[tokio::test]
+    async fn late_answer_never_reaches_the_next_payment() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        sim.script([SimOutcome::SlowAck(Duration::from_millis(400))]);
+        let cancel_once_sent = async {
+            while sim.requests().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            cancel_tpe_transaction("sim-late".to_string()).unwrap();
+        };
+        
+        let tx = TpeTransaction::debit(500, "01");
+        let payment = execute_transaction(None, config(sim.address(), 8), tx, Some("sim-late".to_string()));
+        let (cancelled, _) = tokio::join!(payment, cancel_once_sent);
+        let next = pay(&sim, 8, 700).await.unwrap();
+        
+        assert_eq!(cancelled.unwrap().error, Some(TpeError::Cancelled));
+        // The cancelled exchange's connection was dropped, not kept warm
+        assert_eq!(sim.connections(), 2);
+        assert!(next.success);
+        assert_eq!(next.tags.get("TI"), next.tpe_transaction_id.as_ref());
+        assert_eq!(next.tags.get("CB").map(String::as_str), Some("000000000700"));
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_nak_is_rejected_and_reconnects() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        sim.script([SimOutcome::Nak]);
+        
+        let rejected = pay(&sim, 8, 500).await.unwrap_err();
+        let next = pay(&sim, 8, 500).await.unwrap();
+        
+        assert_eq!(rejected.code(), "Protocol");
+        assert!(rejected.to_string().contains("format rejeté"));
+        assert!(next.success);
+        assert_eq!(sim.connections(), 2);
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_approved() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        
+        let res = pay(&sim, 8, 2000).await.unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.authorization_number.as_deref(), Some("654321"));
+        assert_eq!(res.card_brand.as_deref(), Some("MASTERCARD"));
+        assert_eq!(res.acquirer_transaction_id.as_deref(), Some("SIM000001"));
+        assert_eq!(res.cardholder_ticket, ["CARTE BANCAIRE", "MASTERCARD", "", "DEBIT", "TICKET CLIENT A CONSERVER"]);
+        assert!(res.merchant_ticket.is_empty());
+        assert_eq!(res.card_application, Some(CardApplication::BankCard));
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_meal_voucher_is_requested_and_reported() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        
+        let tx = TpeTransaction::debit(1190, "01").with_application(CardApplication::MealVoucher);
+        let res = execute_transaction(None, config(sim.address(), 8), tx, None).await.unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.card_application, Some(CardApplication::MealVoucher));
+        assert!(sim.requests()[0].contains("CC003002"));
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_reuses_the_warm_connection() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        
+        let first = pay(&sim, 8, 500).await.unwrap();
+        let second = pay(&sim, 8, 700).await.unwrap();
+        
+        assert!(first.success && second.success);
+        assert_eq!(sim.requests().len(), 2);
+        assert_eq!(sim.connections(), 1);
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_reconciliation_flags_a_difference() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        pay(&sim, 8, 2000).await.unwrap();
+        pay(&sim, 8, 1250).await.unwrap();
+        
+        let tx = TpeTransaction::reconciliation("01");
+        let res = execute_transaction(None, config(sim.address(), 8), tx, None).await.unwrap();
+        let totals = reconcile(res, "Simulateur", &Currency::default(), 3000);
+        
+        assert!(totals.success);
+        assert_eq!(totals.terminal_total_minor, Some(3250));
+        assert_eq!(totals.terminal_count, Some(2));
+        assert_eq!(totals.difference_minor, Some(250));
+        assert!(!totals.balanced);
+        assert_eq!(totals.error_message.as_deref(), Some("Écart de 2.50 EUR entre le TPE et la caisse"));
+        assert_eq!(totals.ticket[0], "TELECOLLECTE");
+    }
+    
+    #[tokio::test]
+    async fn binary_framing_cannot_pick_an_application() {
+        let tx = TpeTransaction::debit(1190, "01").with_application(CardApplication::HolidayVoucher);
+        
+        let res = execute_transaction(None, config("127.0.0.1:1", 2), tx, None).await;
+        
+        assert!(matches!(res.unwrap_err(), TpeError::Unsupported { operation, .. } if operation.contains("ANCV")));
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_refused_with_code() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        sim.script([SimOutcome::Refused("051".to_string())]);
+        
+        // Concert V3 configured on a TCP port is carried as Caisse-AP IP
+        let tx = TpeTransaction::debit(2000, "01");
+        let res = execute_transaction(None, config(sim.address(), 3), tx, Some("sim-ap-refused".to_string())).await.unwrap();
+        
+        assert!(!res.success);
+        assert_eq!(res.error_message.as_deref(), Some("Paiement refusé (Code: 051)"));
+        // A readable refusal settles the payment
+        assert!(!list_pending_tpe_payments().iter().any(|p| p.payment_id == "sim-ap-refused"));
+    }
+    
+    #[tokio::test]
+    async fn caisse_ap_garbage_is_unreadable() {
+        let sim = TpeSimulator::caisse_ap("127.0.0.1:0").unwrap();
+        sim.script([SimOutcome::Garbage]);
+        
+        let tx = TpeTransaction::debit(2000, "01");
+        let res = execute_transaction(None, config(sim.address(), 8), tx, Some("sim-ap-garbage".to_string())).await.unwrap();
+        
+        assert!(!res.success);
+        assert_eq!(res.error.as_ref().map(TpeError::code), Some("Protocol"));
+        assert!(res.error_message.unwrap().starts_with("Réponse TPE illisible"));
+        // The card may have been debited: the payment stays pending until checked
+        let entry = list_pending_tpe_payments().into_iter().find(|p| p.payment_id == "sim-ap-garbage").unwrap();
+        assert!(entry.last_error.unwrap().contains("incompréhensible"));
+    }
+    
+    #[test]
+    fn parses_outcomes() {
+        assert_eq!("approved".parse::<SimOutcome>(), Ok(SimOutcome::Approved));
+        assert_eq!("refused:51".parse::<SimOutcome>(), Ok(SimOutcome::Refused("51".to_string())));
+        assert_eq!("slow-ack:2500".parse::<SimOutcome>(), Ok(SimOutcome::SlowAck(Duration::from_millis(2500))));
+        assert!("refused:12345".parse::<SimOutcome>().is_err());
+        assert!("crash".parse::<SimOutcome>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+// ===================================
+// TPE Timeouts - per-phase time limits of a terminal
+// ===================================
+//
+// Saved with the terminal settings. The card wait is the one shops tune:
+// shorter when the queue is long, longer for terminals that fall back from
+// contactless to chip and PIN. Missing fields keep today's limits.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::protocols::TpeProtocol;
+
+const DEFAULT_CONNECT_SECS: u64 = 10;
+/// Today's ACK limit: a Concert terminal acknowledges within a few character times
+const DEFAULT_ACK_MS: u64 = 500;
+const DEFAULT_TEST_MS: u64 = 300;
+const DEFAULT_WRITE_SECS: u64 = 10;
+const DEFAULT_EOT_MS: u64 = 1000;
+const DEFAULT_ASCII_ANSWER_MS: u64 = 600;
+const DEFAULT_LRC_GRACE_MS: u64 = 200;
+const DEFAULT_PROBE_SECS: u64 = 5;
+const DEFAULT_ABORT_SECS: u64 = 3;
+
+/// Card wait of the Concert serial links, then of the IP and HTTP terminals
+const SERIAL_CARD_WAIT_SECS: u64 = 120;
+const IP_CARD_WAIT_SECS: u64 = 150;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct TpeTimeouts {
+    pub connect_secs: u64,           // Opening the TCP connection (slow terminals wake up on connect)
+    pub ack_ms: u64,                 // ACK of our ENQ or request frame on Concert links
+    pub card_wait_secs: Option<u64>, // Terminal's answer once the amount is sent: None = 120 s serial, 150 s IP
+    pub test_ms: u64,                // Answer to the connection test ENQ
+    pub write_secs: u64,             // Handing a frame over to the transport
+    pub eot_ms: u64,                 // EOT closing the terminal's emission once its frame is ACKed
+    pub ascii_answer_ms: u64,        // Answer to the plain text debit of the ASCII fallback
+    pub lrc_grace_ms: u64,           // LRC byte still missing after ETX on unframed links
+    pub probe_secs: u64,             // Answer to a detection probe
+    pub abort_secs: u64,             // Answer to the CAN aborting a detection probe
+}
+
+impl Default for TpeTimeouts {
+    fn default() -> Self {
+        TpeTimeouts {
+            connect_secs: DEFAULT_CONNECT_SECS,
+            ack_ms: DEFAULT_ACK_MS,
+            card_wait_secs: None,
+            test_ms: DEFAULT_TEST_MS,
+            write_secs: DEFAULT_WRITE_SECS,
+            eot_ms: DEFAULT_EOT_MS,
+            ascii_answer_ms: DEFAULT_ASCII_ANSWER_MS,
+            lrc_grace_ms: DEFAULT_LRC_GRACE_MS,
+            probe_secs: DEFAULT_PROBE_SECS,
+            abort_secs: DEFAULT_ABORT_SECS,
+        }
+    }
+}
+
+impl TpeTimeouts {
+    pub fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect_secs)
+    }
+    
+    pub fn ack(&self) -> Duration {
+        Duration::from_millis(self.ack_ms)
+    }
+    
+    /// Time the customer has to pay, from the request to the terminal's answer
+    pub fn card_wait(&self, protocol: TpeProtocol) -> Duration {
+        let default = if protocol.uses_enq_handshake() { SERIAL_CARD_WAIT_SECS } else { IP_CARD_WAIT_SECS };
+        Duration::from_secs(self.card_wait_secs.unwrap_or(default))
+    }
+    
+    pub fn test(&self) -> Duration {
+        Duration::from_millis(self.test_ms)
+    }
+    
+    pub fn write(&self) -> Duration {
+        Duration::from_secs(self.write_secs)
+    }
+    
+    pub fn eot(&self) -> Duration {
+        Duration::from_millis(self.eot_ms)
+    }
+    
+    pub fn ascii_answer(&self) -> Duration {
+        Duration::from_millis(self.ascii_answer_ms)
+    }
+    
+    pub fn lrc_grace(&self) -> Duration {
+        Duration::from_millis(self.lrc_grace_ms)
+    }
+    
+    pub fn probe(&self) -> Duration {
+        Duration::from_secs(self.probe_secs)
+    }
+    
+    pub fn abort(&self) -> Duration {
+        Duration::from_secs(self.abort_secs)
+    }
+    
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=120).contains(&self.connect_secs) {
+            return Err(format!("délai de connexion invalide ({} s, de 1 à 120 s)", self.connect_secs));
+        }
+        if !(100..=30_000).contains(&self.ack_ms) {
+            return Err(format!("délai d'accusé de réception invalide ({} ms, de 100 à 30000 ms)", self.ack_ms));
+        }
+        if let Some(secs) = self.card_wait_secs.filter(|secs| !(10..=600).contains(secs)) {
+            return Err(format!("délai d'attente de la carte invalide ({} s, de 10 à 600 s)", secs));
+        }
+        if !(50..=10_000).contains(&self.test_ms) {
+            return Err(format!("délai du test de connexion invalide ({} ms, de 50 à 10000 ms)", self.test_ms));
+        }
+        if !(1..=60).contains(&self.write_secs) {
+            return Err(format!("délai d'envoi invalide ({} s, de 1 à 60 s)", self.write_secs));
+        }
+        if !(100..=10_000).contains(&self.eot_ms) {
+            return Err(format!("délai de fin d'émission invalide ({} ms, de 100 à 10000 ms)", self.eot_ms));
+        }
+        if !(100..=10_000).contains(&self.ascii_answer_ms) {
+            return Err(format!("délai de réponse ASCII invalide ({} ms, de 100 à 10000 ms)", self.ascii_answer_ms));
+        }
+        if !(50..=5_000).contains(&self.lrc_grace_ms) {
+            return Err(format!("délai d'attente du LRC invalide ({} ms, de 50 à 5000 ms)", self.lrc_grace_ms));
+        }
+        if !(1..=60).contains(&self.probe_secs) {
+            return Err(format!("délai de détection invalide ({} s, de 1 à 60 s)", self.probe_secs));
+        }
+        if !(1..=30).contains(&self.abort_secs) {
+            return Err(format!("délai d'annulation de la détection invalide ({} s, de 1 à 30 s)", self.abort_secs));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn defaults_keep_the_previous_limits() {
+        let timeouts = TpeTimeouts::default();
+        
+        assert_eq!(timeouts.connect(), Duration::from_secs(10));
+        assert_eq!(timeouts.ack(), Duration::from_millis(500));
+        assert_eq!(timeouts.card_wait(TpeProtocol::ConcertV3Tlv), Duration::from_secs(120));
+        assert_eq!(timeouts.card_wait(TpeProtocol::CaisseApIp), Duration::from_secs(150));
+        assert_eq!(timeouts.card_wait(TpeProtocol::YavinLocal), Duration::from_secs(150));
+        assert_eq!(timeouts.test(), Duration::from_millis(300));
+        assert_eq!(timeouts.write(), Duration::from_secs(10));
+        assert_eq!(timeouts.eot(), Duration::from_secs(1));
+        assert_eq!(timeouts.ascii_answer(), Duration::from_millis(600));
+        assert_eq!(timeouts.lrc_grace(), Duration::from_millis(200));
+        assert_eq!(timeouts.probe(), Duration::from_secs(5));
+        assert_eq!(timeouts.abort(), Duration::from_secs(3));
+        assert!(timeouts.validate().is_ok());
+    }
+    
+    #[test]
+    fn missing_fields_take_their_default() {
+        let timeouts: TpeTimeouts = serde_json::from_str(r#"{"card_wait_secs": 60}"#).unwrap();
+        
+        assert_eq!(timeouts.card_wait(TpeProtocol::ConcertV2), Duration::from_secs(60));
+        assert_eq!(timeouts.card_wait(TpeProtocol::NeptingRc), Duration::from_secs(60));
+        assert_eq!(timeouts.connect_secs, 10);
+    }
+    
+    #[test]
+    fn rejects_out_of_range_limits() {
+        let card_wait = TpeTimeouts { card_wait_secs: Some(5), ..Default::default() };
+        let ack = TpeTimeouts { ack_ms: 0, ..Default::default() };
+        let probe = TpeTimeouts { probe_secs: 0, ..Default::default() };
+        
+        assert!(card_wait.validate().unwrap_err().contains("carte"));
+        assert!(ack.validate().is_err());
+        assert!(probe.validate().unwrap_err().contains("détection"));
+    }
+}
@@ -0,0 +1,641 @@
+// ===================================
+// Yavin Module - HTTP payment terminals
+// ===================================
+// Local API: the terminal exposes an HTTP server on the shop network.
+// Cloud API: requests go through Yavin's servers, authenticated by an API key
+// and tracked by our merchant reference (POS transaction id).
+// The start call may answer with the final result or with a pending
+// transaction that we poll until it completes.
+
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use crate::pending::{self, PendingPayment};
+use crate::protocols::{build_yavin_cloud_payload, build_yavin_local_payload, TpeTransaction};
+use crate::tpe_error::TpeError;
+use crate::tpe::{cancelled_response, log_to_file, CancelToken, ProgressReporter, TpePaymentResponse, TpePhase};
+
+// Local API endpoints (relative to http://<terminal>:<port>)
+const LOCAL_START_PATH: &str = "/localapi/v4/transaction/start";
+const LOCAL_STATUS_PATH: &str = "/localapi/v4/transaction/status";
+const LOCAL_CANCEL_PATH: &str = "/localapi/v4/transaction/cancel";
+
+// Cloud API endpoints
+const CLOUD_BASE_URL: &str = "https://api.yavin.com";
+const CLOUD_START_PATH: &str = "/api/v5/ecr/transaction/start";
+const CLOUD_STATUS_PATH: &str = "/api/v5/ecr/transaction/status";
+const CLOUD_CANCEL_PATH: &str = "/api/v5/ecr/transaction/cancel";
+const CLOUD_API_KEY_HEADER: &str = "Yavin-Secret";
+
+// Budget of a transaction lookup (payments use the card wait of the terminal)
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(150);
+const POLL_INTERVAL: Duration = Duration::from_millis(1000);
+
+// ===================================
+// Types
+// ===================================
+
+/// Transaction state as reported by the Yavin API
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct YavinTransaction {
+    status: String, // "ok", "ko", "pending", "cancelled"
+    transaction_id: Option<String>,
+    merchant_reference: Option<String>,
+    #[serde(alias = "authorization_code")]
+    auth_code: Option<String>,
+    #[serde(alias = "pan")]
+    masked_pan: Option<String>,
+    #[serde(alias = "issuer")]
+    card_brand: Option<String>,
+    entry_mode: Option<String>,
+    currency: Option<String>,
+    #[serde(alias = "message")]
+    error_message: Option<String>,
+}
+
+impl YavinTransaction {
+    fn is_pending(&self) -> bool {
+        matches!(self.status.to_lowercase().as_str(), "pending" | "in_progress")
+    }
+    
+    /// A missing or undocumented status says nothing about the payment
+    fn status_known(&self) -> bool {
+        self.is_pending() || matches!(self.status.to_lowercase().as_str(), "ok" | "ko" | "cancelled")
+    }
+    
+    fn unknown_status(&self) -> TpeError {
+        TpeError::Protocol { reason: format!("statut Yavin inconnu '{}'", self.status) }
+    }
+    
+    fn into_response(self, amount_minor: u32, raw: String) -> TpePaymentResponse {
+        let status = self.status.to_lowercase();
+        let success = status == "ok";
+        let error = (!self.status_known()).then(|| self.unknown_status());
+        let error_message = if success {
+            None
+        } else if status == "cancelled" {
+            Some("Transaction annulée".to_string())
+        } else if self.is_pending() {
+            Some("Transaction en cours sur le terminal".to_string())
+        } else if let Some(error) = &error {
+            Some(error.to_string())
+        } else {
+            Some(self.error_message.unwrap_or_else(|| "Paiement refusé".to_string()))
+        };
+        
+        let mut tags = std::collections::HashMap::new();
+        if let Some(merchant_ref) = self.merchant_reference {
+            tags.insert("merchant_reference".to_string(), merchant_ref);
+        }
+        
+        TpePaymentResponse {
+            success,
+            transaction_result: if success { "APPROVED".to_string() } else { status.to_uppercase() },
+            amount_minor,
+            authorization_number: self.auth_code,
+            error_message,
+            raw_response: Some(raw),
+            masked_pan: self.masked_pan,
+            card_brand: self.card_brand,
+            entry_mode: self.entry_mode.map(|m| m.to_uppercase()),
+            acquirer_transaction_id: self.transaction_id,
+            currency: self.currency,
+            tags,
+            error,
+            ..Default::default()
+        }
+    }
+}
+
+/// Endpoint set of one Yavin API flavour
+struct YavinApi {
+    base_url: String,
+    start_path: &'static str,
+    status_path: &'static str,
+    cancel_path: &'static str,
+    api_key: Option<String>,
+}
+
+impl YavinApi {
+    fn local(address: &str) -> Self {
+        YavinApi {
+            base_url: base_url(address, "http"),
+            start_path: LOCAL_START_PATH,
+            status_path: LOCAL_STATUS_PATH,
+            cancel_path: LOCAL_CANCEL_PATH,
+            api_key: None,
+        }
+    }
+    
+    /// An empty address targets Yavin's production servers
+    fn cloud(address: &str, api_key: &str) -> Self {
+        let address = if address.trim().is_empty() { CLOUD_BASE_URL } else { address };
+        YavinApi {
+            base_url: base_url(address, "https"),
+            start_path: CLOUD_START_PATH,
+            status_path: CLOUD_STATUS_PATH,
+            cancel_path: CLOUD_CANCEL_PATH,
+            api_key: Some(api_key.to_string()),
+        }
+    }
+    
+    fn post(&self, client: &reqwest::Client, path: &str) -> reqwest::RequestBuilder {
+        let request = client.post(format!("{}{}", self.base_url, path));
+        match &self.api_key {
+            Some(key) => request.header(CLOUD_API_KEY_HEADER, key),
+            None => request,
+        }
+    }
+}
+
+// ===================================
+// Payment Flows
+// ===================================
+
+/// Run a payment on a Yavin terminal through its local HTTP API.
+/// `journal` is recorded right before the start request and gets the terminal's transaction id.
+pub async fn run_local_payment(
+    address: &str,
+    terminal_id: &str,
+    tx: &TpeTransaction,
+    journal: Option<PendingPayment>,
+    card_wait: Duration,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<TpePaymentResponse, TpeError> {
+    let api = YavinApi::local(address);
+    let payload = build_yavin_local_payload(tx, terminal_id);
+    run_payment(&api, payload, None, tx.amount_minor, journal, card_wait, cancel, progress).await
+}
+
+/// Run a payment through the Yavin Cloud API, tracked by our merchant reference
+#[allow(clippy::too_many_arguments)]
+pub async fn run_cloud_payment(
+    address: &str,
+    api_key: &str,
+    terminal_id: &str,
+    tx: &TpeTransaction,
+    journal: Option<PendingPayment>,
+    card_wait: Duration,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<TpePaymentResponse, TpeError> {
+    let merchant_ref = tx.pos_transaction_id.as_deref().unwrap_or_default();
+    if api_key.trim().is_empty() {
+        return Err(TpeError::InvalidConfig { reason: "clé API Yavin manquante".to_string() });
+    }
+    if merchant_ref.trim().is_empty() {
+        return Err(TpeError::InvalidRequest { reason: "référence de transaction caisse requise pour Yavin Cloud".to_string() });
+    }
+    
+    let api = YavinApi::cloud(address, api_key);
+    let payload = build_yavin_cloud_payload(tx, terminal_id);
+    run_payment(&api, payload, Some(merchant_ref), tx.amount_minor, journal, card_wait, cancel, progress).await
+}
+
+/// Look up a cloud transaction by merchant reference (e.g. after a crash).
+/// Returns None if Yavin has no transaction for this reference.
+pub async fn find_cloud_transaction(
+    address: &str,
+    api_key: &str,
+    merchant_ref: &str,
+) -> Result<Option<TpePaymentResponse>, TpeError> {
+    let api = YavinApi::cloud(address, api_key);
+    let client = http_client(&api, LOOKUP_TIMEOUT)?;
+    
+    log_to_file(&format!("Yavin Cloud lookup: merchant_reference={}", merchant_ref));
+    let response = api
+        .post(&client, api.status_path)
+        .json(&serde_json::json!({ "merchant_reference": merchant_ref }))
+        .send()
+        .await
+        .map_err(|e| connect_failed(&api, e))?;
+    
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    
+    let (transaction, raw) = read_transaction(response).await?;
+    log_to_file(&format!("Yavin Cloud lookup result: {}", raw));
+    Ok(Some(transaction.into_response(0, raw)))
+}
+
+/// Look up a local API transaction by the id the terminal gave when it started.
+/// Returns None if the terminal has no transaction under this id.
+pub async fn find_local_transaction(address: &str, transaction_id: &str) -> Result<Option<TpePaymentResponse>, TpeError> {
+    let api = YavinApi::local(address);
+    let client = http_client(&api, LOOKUP_TIMEOUT)?;
+    
+    log_to_file(&format!("Yavin local lookup: transaction_id={}", transaction_id));
+    let response = api
+        .post(&client, api.status_path)
+        .json(&serde_json::json!({ "transaction_id": transaction_id }))
+        .send()
+        .await
+        .map_err(|e| connect_failed(&api, e))?;
+    
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    
+    let (transaction, raw) = read_transaction(response).await?;
+    log_to_file(&format!("Yavin local lookup result: {}", raw));
+    Ok(Some(transaction.into_response(0, raw)))
+}
+
+/// Check that something answers the Yavin local API at this address.
+/// Only the status endpoint is queried: no transaction is started.
+pub async fn probe_local(address: &str, timeout: Duration) -> Result<String, TpeError> {
+    let api = YavinApi::local(address);
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| connect_failed(&api, e))?;
+    
+    let response = api
+        .post(&client, api.status_path)
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .map_err(|e| connect_failed(&api, e))?;
+    Ok(format!("HTTP {}", response.status().as_u16()))
+}
+
+/// Start a transaction, then poll its status until it completes, times out or is cancelled
+#[allow(clippy::too_many_arguments)]
+async fn run_payment(
+    api: &YavinApi,
+    payload: String,
+    merchant_ref: Option<&str>,
+    amount_minor: u32,
+    journal: Option<PendingPayment>,
+    card_wait: Duration,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<TpePaymentResponse, TpeError> {
+    let client = http_client(api, card_wait)?;
+    log_to_file(&format!("Yavin start {}: {}", api.base_url, payload));
+    progress.phase(TpePhase::Sending);
+    
+    // From here the terminal may debit the card: journal the payment right before it starts
+    let journal_id = journal.as_ref().map(|entry| entry.payment_id.clone());
+    journal.map_or(Ok(()), pending::record)?;
+    
+    let start = api
+        .post(&client, api.start_path)
+        .header("Content-Type", "application/json")
+        .body(payload)
+        .send();
+    
+    // The start call can block until the card is presented, race it against "Annuler"
+    let response = tokio::select! {
+        res = start => match res {
+            Ok(response) => response,
+            // The terminal was never reached: nothing to recover
+            Err(e) if e.is_connect() => {
+                if let Some(id) = &journal_id {
+                    pending::resolve(id);
+                }
+                return Err(connect_failed(api, e));
+            }
+            Err(e) => return Err(connect_failed(api, e)),
+        },
+        _ = cancel.cancelled() => {
+            cancel_transaction(&client, api, None, merchant_ref).await;
+            return Ok(cancelled_response(amount_minor));
+        }
+    };
+    
+    let (mut transaction, mut raw) = read_transaction(response).await?;
+    if let (Some(journal_id), Some(transaction_id)) = (&journal_id, transaction.transaction_id.as_deref()) {
+        pending::note_terminal_reference(journal_id, transaction_id);
+    }
+    let started = Instant::now();
+    if transaction.is_pending() {
+        progress.phase(TpePhase::WaitingCard);
+    }
+    
+    // An unreadable status gets one more poll before giving up
+    let mut unreadable = false;
+    while transaction.is_pending() || !transaction.status_known() {
+        if !transaction.status_known() {
+            if unreadable {
+                log_to_file(&format!("Yavin: unknown status in {}", raw));
+                return Err(transaction.unknown_status());
+            }
+            log_to_file(&format!("Yavin: unknown status in {}, polling again", raw));
+        }
+        unreadable = !transaction.status_known();
+        
+        if started.elapsed() > card_wait {
+            log_to_file("Yavin: timeout waiting for result");
+            return Err(TpeError::Timeout { seconds: card_wait.as_secs_f64() });
+        }
+        
+        let cancelled = tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => cancel.is_cancelled(),
+            _ = cancel.cancelled() => true,
+        };
+        if cancelled {
+            cancel_transaction(&client, api, transaction.transaction_id.as_deref(), merchant_ref).await;
+            return Ok(cancelled_response(amount_minor));
+        }
+        
+        let status = api
+            .post(&client, api.status_path)
+            .json(&serde_json::json!({
+                "transaction_id": transaction.transaction_id,
+                "merchant_reference": merchant_ref,
+            }))
+            .send()
+            .await
+            .map_err(|e| connect_failed(api, e))?;
+        (transaction, raw) = read_transaction(status).await?;
+        progress.frame(raw.as_bytes());
+    }
+    
+    log_to_file(&format!("Yavin result: {}", raw));
+    Ok(transaction.into_response(amount_minor, raw))
+}
+
+/// Ask the terminal to abort the current transaction (best effort)
+async fn cancel_transaction(
+    client: &reqwest::Client,
+    api: &YavinApi,
+    transaction_id: Option<&str>,
+    merchant_ref: Option<&str>,
+) {
+    log_to_file("Yavin: sending cancel request");
+    let res = api
+        .post(client, api.cancel_path)
+        .json(&serde_json::json!({
+            "transaction_id": transaction_id,
+            "merchant_reference": merchant_ref,
+        }))
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await;
+    if let Err(e) = res {
+        log_to_file(&format!("Yavin cancel failed: {}", e));
+    }
+}
+
+// ===================================
+// Tauri Commands
+// ===================================
+
+/// Recover the outcome of a Yavin Cloud payment from its merchant reference
+#[tauri::command]
+pub async fn find_yavin_transaction(
+    port_name: String,
+    api_key: String,
+    merchant_reference: String,
+) -> Result<Option<TpePaymentResponse>, TpeError> {
+    find_cloud_transaction(&port_name, &api_key, &merchant_reference).await
+}
+
+// ===================================
+// Helpers
+// ===================================
+
+fn http_client(api: &YavinApi, timeout: Duration) -> Result<reqwest::Client, TpeError> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|e| connect_failed(api, e))
+}
+
+fn connect_failed(api: &YavinApi, e: reqwest::Error) -> TpeError {
+    TpeError::ConnectFailed { target: api.base_url.clone(), reason: e.to_string() }
+}
+
+/// Accept "192.168.1.60:16125" as well as a full "http://..." URL. An address
+/// without scheme gets `default_scheme`: plain HTTP only for the Local API,
+/// so the Cloud API key never leaves unencrypted by accident.
+fn base_url(address: &str, default_scheme: &str) -> String {
+    let address = address.trim().trim_end_matches('/');
+    if address.starts_with("http://") || address.starts_with("https://") {
+        address.to_string()
+    } else {
+        format!("{}://{}", default_scheme, address)
+    }
+}
+
+async fn read_transaction(response: reqwest::Response) -> Result<(YavinTransaction, String), TpeError> {
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| TpeError::ConnectionLost { reason: format!("réponse Yavin incomplète: {}", e) })?;
+    
+    if !status.is_success() {
+        log_to_file(&format!("Yavin HTTP {}: {}", status, body));
+        return Err(TpeError::Protocol { reason: format!("Yavin HTTP {}: {}", status.as_u16(), body) });
+    }
+    
+    let transaction = serde_json::from_str(&body)
+        .map_err(|e| TpeError::Protocol { reason: format!("réponse Yavin invalide: {} ({})", e, body) })?;
+    Ok((transaction, body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    
+    const CARD_WAIT: Duration = Duration::from_secs(150);
+    
+    /// Minimal HTTP server answering each request with the next canned body.
+    /// Returns the base address and the list of request paths received.
+    fn mock_server(bodies: Vec<&'static str>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let seen = paths.clone();
+        
+        std::thread::spawn(move || {
+            let mut bodies = bodies.into_iter();
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).is_err() || line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = v.trim().parse().unwrap_or(0);
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                if reader.read_exact(&mut body).is_err() {
+                    continue;
+                }
+                seen.lock().unwrap().push(request_line.split_whitespace().nth(1).unwrap_or("").to_string());
+                
+                let answer = bodies.next().unwrap_or(r#"{"status":"ko"}"#);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    answer.len(),
+                    answer
+                );
+            }
+        });
+        
+        (address, paths)
+    }
+    
+    #[tokio::test]
+    async fn approved_on_start() {
+        let (address, paths) = mock_server(vec![
+            r#"{"status":"ok","transaction_id":"T1","auth_code":"123456","pan":"4970********1234","issuer":"CB","entry_mode":"contactless"}"#,
+        ]);
+        let cancel = CancelToken::new();
+        
+        let res = run_local_payment(&address, "SN1", &TpeTransaction::debit(1250, "01"), None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.amount_minor, 1250);
+        assert_eq!(res.authorization_number.as_deref(), Some("123456"));
+        assert_eq!(res.masked_pan.as_deref(), Some("4970********1234"));
+        assert_eq!(res.card_brand.as_deref(), Some("CB"));
+        assert_eq!(res.entry_mode.as_deref(), Some("CONTACTLESS"));
+        assert_eq!(res.acquirer_transaction_id.as_deref(), Some("T1"));
+        assert_eq!(*paths.lock().unwrap(), vec![LOCAL_START_PATH.to_string()]);
+    }
+    
+    #[tokio::test]
+    async fn polls_pending_until_refused() {
+        let (address, paths) = mock_server(vec![
+            r#"{"status":"pending","transaction_id":"T2"}"#,
+            r#"{"status":"pending","transaction_id":"T2"}"#,
+            r#"{"status":"ko","transaction_id":"T2","message":"Carte refusée"}"#,
+        ]);
+        let cancel = CancelToken::new();
+        
+        let res = run_local_payment(&address, "SN1", &TpeTransaction::debit(500, "01"), None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.unwrap();
+        
+        assert!(!res.success);
+        assert_eq!(res.transaction_result, "KO");
+        assert_eq!(res.error_message.as_deref(), Some("Carte refusée"));
+        assert_eq!(paths.lock().unwrap().len(), 3);
+    }
+    
+    #[tokio::test]
+    async fn missing_status_is_polled_once_then_rejected() {
+        let (address, paths) = mock_server(vec![
+            r#"{"transaction_id":"T5"}"#,
+            r#"{"status":"","transaction_id":"T5"}"#,
+        ]);
+        let cancel = CancelToken::new();
+        
+        let err = run_local_payment(&address, "SN1", &TpeTransaction::debit(500, "01"), None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.unwrap_err();
+        
+        assert_eq!(err.code(), "Protocol");
+        assert!(err.to_string().contains("statut Yavin inconnu"));
+        assert_eq!(paths.lock().unwrap().len(), 2);
+    }
+    
+    #[tokio::test]
+    async fn unknown_status_recovers_on_the_next_poll() {
+        let (address, _) = mock_server(vec![
+            r#"{"status":"processing","transaction_id":"T6"}"#,
+            r#"{"status":"ok","transaction_id":"T6","auth_code":"654321"}"#,
+        ]);
+        let cancel = CancelToken::new();
+        
+        let res = run_local_payment(&address, "SN1", &TpeTransaction::debit(500, "01"), None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.authorization_number.as_deref(), Some("654321"));
+    }
+    
+    #[tokio::test]
+    async fn cancel_while_pending() {
+        let (address, paths) = mock_server(vec![
+            r#"{"status":"pending","transaction_id":"T3"}"#,
+            r#"{"status":"pending","transaction_id":"T3"}"#,
+            r#"{"status":"cancelled"}"#,
+        ]);
+        let cancel = CancelToken::new();
+        let token = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            token.cancel();
+        });
+        
+        let res = run_local_payment(&address, "SN1", &TpeTransaction::debit(500, "01"), None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.unwrap();
+        
+        assert!(!res.success);
+        assert_eq!(res.transaction_result, "CANCELLED");
+        assert!(paths.lock().unwrap().contains(&LOCAL_CANCEL_PATH.to_string()));
+    }
+    
+    #[tokio::test]
+    async fn cloud_payment_tracks_merchant_reference() {
+        let (address, paths) = mock_server(vec![
+            r#"{"status":"pending","transaction_id":"C1","merchant_reference":"42"}"#,
+            r#"{"status":"ok","transaction_id":"C1","merchant_reference":"42","auth_code":"A1"}"#,
+        ]);
+        let cancel = CancelToken::new();
+        
+        let tx = TpeTransaction::debit(900, "01").with_pos_transaction_id(Some("42".to_string()));
+        let res = run_cloud_payment(&format!("http://{}", address), "secret", "SN1", &tx, None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.authorization_number.as_deref(), Some("A1"));
+        assert_eq!(res.tags.get("merchant_reference").map(String::as_str), Some("42"));
+        assert_eq!(
+            *paths.lock().unwrap(),
+            vec![CLOUD_START_PATH.to_string(), CLOUD_STATUS_PATH.to_string()]
+        );
+    }
+    
+    #[test]
+    fn cloud_addresses_default_to_https() {
+        assert_eq!(YavinApi::cloud("", "secret").base_url, CLOUD_BASE_URL);
+        assert_eq!(YavinApi::cloud("api.example.com/", "secret").base_url, "https://api.example.com");
+        assert_eq!(YavinApi::cloud("http://127.0.0.1:8080", "secret").base_url, "http://127.0.0.1:8080");
+        assert_eq!(YavinApi::local("192.168.1.60:16125").base_url, "http://192.168.1.60:16125");
+    }
+    
+    #[tokio::test]
+    async fn cloud_payment_requires_api_key() {
+        let cancel = CancelToken::new();
+        let tx = TpeTransaction::debit(900, "01").with_pos_transaction_id(Some("42".to_string()));
+        assert!(run_cloud_payment("", "", "SN1", &tx, None, CARD_WAIT, &cancel, &ProgressReporter::silent()).await.is_err());
+    }
+    
+    #[tokio::test]
+    async fn cloud_lookup_by_merchant_reference() {
+        let (address, _) = mock_server(vec![
+            r#"{"status":"ok","transaction_id":"C2","merchant_reference":"43","auth_code":"A2"}"#,
+        ]);
+        
+        let res = find_cloud_transaction(&format!("http://{}", address), "secret", "43").await.unwrap().unwrap();
+        
+        assert!(res.success);
+        assert_eq!(res.acquirer_transaction_id.as_deref(), Some("C2"));
+    }
+    
+    #[tokio::test]
+    async fn local_lookup_by_transaction_id() {
+        let (address, paths) = mock_server(vec![r#"{"status":"ko","transaction_id":"T4","message":"Carte refusée"}"#]);
+        
+        let res = find_local_transaction(&address, "T4").await.unwrap().unwrap();
+        
+        assert!(!res.success);
+        assert_eq!(res.acquirer_transaction_id.as_deref(), Some("T4"));
+        assert_eq!(*paths.lock().unwrap(), vec![LOCAL_STATUS_PATH.to_string()]);
+    }
+}
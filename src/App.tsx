@@ -44,6 +44,9 @@ import { useImageCacheStore } from './stores/imageCacheStore';
 import { logger, initGlobalErrorHandling } from './services/logger';
 import { useEffect, useState } from 'react';
 import { SplashScreen, UpdateChecker } from './components/ui';
+import { PendingPaymentsChecker } from './components/pos';
+import { syncTpeTerminals } from './services/tpeTerminals';
+import { invoke } from '@tauri-apps/api/core';
 
 function App() {
   const [showSplash, setShowSplash] = useState(true);
@@ -57,6 +60,19 @@ function App() {
         const { initCache } = useImageCacheStore.getState();
         await initCache();
 
+        // Register the TPE settings as named terminals (payments are sent by terminal name)
+        const savedTpeConfig = localStorage.getItem('ma-caisse-tpe-config');
+        if (savedTpeConfig) {
+          await syncTpeTerminals(JSON.parse(savedTpeConfig)).catch(err => {
+            console.warn('[App] TPE terminal registry sync failed:', err);
+          });
+        }
+
+        // Keep network terminals connected and watch their online state
+        invoke('start_tpe_monitor').catch(err => {
+          console.warn('[App] TPE session monitor not started:', err);
+        });
+
         // Start auto-sync
         startAutoSync();
 
@@ -161,6 +177,9 @@ function App() {
 
       {/* Auto-Update Checker */}
       <UpdateChecker />
+
+      {/* TPE payments interrupted by a crash or power cut */}
+      <PendingPaymentsChecker />
     </BrowserRouter>
   );
 }
@@ -2,11 +2,15 @@
 // Payment Modal Component
 // ===================================
 
-import React, { useState, useCallback, useMemo, useEffect } from 'react';
+import React, { useState, useCallback, useMemo, useEffect, useRef } from 'react';
 import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
 import { Button, CashIcon, CardIcon, ArrowLeftIcon, XIcon, CheckIcon, DrawerIcon, RefreshIcon, AlertIcon, UserIcon } from '../ui';
 import { TicketsModal } from './TicketsModal';
-import type { PaymentMethod, CartItem } from '../../types';
+import type { PaymentMethod, CartItem, CardApplication } from '../../types';
+import { tpeErrorMessage } from '../../services/tpeErrors';
+import type { TpeError } from '../../services/tpeErrors';
+import { toMinorUnits } from '../../services/tpeTerminals';
 import './PaymentModal.css';
 
 interface PaymentModalProps {
@@ -24,6 +28,10 @@ export interface PaymentResult {
     cashReceived: number;
     cardAmount: number;
     changeGiven: number;
+    cardTicket?: string[]; // Card ticket lines returned by the terminal (customer copy)
+    merchantTicket?: string[];
+    tpeTransactionId?: string; // TI sent to the terminal, links the sale to the terminal logs
+    cardApplication?: CardApplication; // Bank card, meal voucher or ANCV, as reported by the terminal
 }
 
 type PaymentStep = 'method' | 'cash' | 'card' | 'mixed' | 'complete';
@@ -34,7 +42,17 @@ interface TpeDeviceConfig {
     port: string;
     baudRate: number;
     posNumber: string;
-    protocolVersion: 2 | 3 | 4 | 5 | 6 | 7;
+    protocolVersion: 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9;
+    terminalId?: string;
+    apiKey?: string;
+    currency?: { numeric: string; alpha: string; symbol: string; minor_units: number };
+}
+
+interface TpeProgressEvent {
+    payment_id: string;
+    phase: string;
+    message: string;
+    frame?: string;
 }
 
 interface TpeConfig {
@@ -42,6 +60,16 @@ interface TpeConfig {
     activeDeviceIndex: 0 | 1;
 }
 
+// Card application asked from the terminal ('any' lets the terminal choose from the card)
+type RequestedApplication = 'any' | CardApplication;
+
+const CARD_APPLICATIONS: { value: RequestedApplication; label: string }[] = [
+    { value: 'any', label: 'Automatique' },
+    { value: 'bank_card', label: 'Carte bancaire' },
+    { value: 'meal_voucher', label: 'Titre-restaurant' },
+    { value: 'holiday_voucher', label: 'Chèque-Vacances (ANCV)' },
+];
+
 const QUICK_AMOUNTS = [5, 10, 20, 50];
 
 const DENOMINATIONS = [
@@ -76,6 +104,8 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
     // TPE State
     const [tpeStatus, setTpeStatus] = useState<TpeStatus>('idle');
     const [tpeMessage, setTpeMessage] = useState<string>('');
+    const [cardApplication, setCardApplication] = useState<RequestedApplication>('any');
+    const tpePaymentIdRef = useRef<string | null>(null);
 
     // Open Cash Drawer
     const handleOpenDrawer = async () => {
@@ -123,9 +153,24 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
             setShowTickets(false);
             setTpeStatus('idle');
             setTpeMessage('');
+            setCardApplication('any');
         }
     }, [isOpen]);
 
+    // Live TPE progress for the payment in flight
+    useEffect(() => {
+        const unlisten = listen<TpeProgressEvent>('tpe-progress', (event) => {
+            const { payment_id, phase, message } = event.payload;
+            if (payment_id !== tpePaymentIdRef.current) return;
+            // Raw frames are for the debug logs, keep the last readable message
+            if (phase === 'frame_received') return;
+            setTpeMessage(message);
+        });
+        return () => {
+            unlisten.then((fn) => fn());
+        };
+    }, []);
+
 
     // Calculate cash received from input
     const cashReceived = useMemo(() => {
@@ -175,7 +220,7 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
     }, [changeGiven, isPaymentValid]);
 
     // Send payment to TPE
-    const sendToTpe = useCallback(async (amountCents: number) => {
+    const sendToTpe = useCallback(async (amount: number) => {
         setTpeStatus('connecting');
         setTpeMessage('Connexion au TPE...');
 
@@ -191,7 +236,8 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
             const config: TpeConfig = JSON.parse(savedConfig);
             const activeTpe = config.devices[config.activeDeviceIndex];
 
-            if (!activeTpe.port) {
+            // Yavin Cloud goes through Yavin's servers, no local port needed
+            if (!activeTpe.port && activeTpe.protocolVersion !== 7) {
                 setTpeStatus('error');
                 setTpeMessage(`TPE "${activeTpe.name}" non configuré (port manquant).`);
                 return false;
@@ -200,14 +246,33 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
             setTpeStatus('waiting');
             setTpeMessage(`Attente du paiement sur ${activeTpe.name}...`);
 
+            // The terminal takes the amount in minor units of its currency
+            const amountMinor = toMinorUnits(amount, activeTpe.currency);
+
+            // Id of this payment attempt: cancels it and is the merchant reference
+            // (the sale id is only assigned once the payment is confirmed)
+            const paymentId = `pay-${Date.now()}-${Math.random().toString(36).slice(2, 8)}`;
+            tpePaymentIdRef.current = paymentId;
+
             // Send payment to TPE
-            const result = await invoke<{ success: boolean; transaction_result: string; error_message?: string }>('send_tpe_payment', {
-                portName: activeTpe.port,
-                baudRate: activeTpe.baudRate,
-                posNumber: activeTpe.posNumber,
-                protocolVersion: activeTpe.protocolVersion,
-                amountCents,
+            const result = await invoke<{
+                success: boolean;
+                transaction_result: string;
+                error_message?: string;
+                cardholder_ticket?: string[];
+                merchant_ticket?: string[];
+                tpe_transaction_id?: string;
+                card_application?: CardApplication;
+                error?: TpeError;
+            }>('send_tpe_payment_by_name', {
+                // Settings are synced into the terminal registry by name
+                terminalName: activeTpe.name,
+                amountMinor,
+                posTransactionId: paymentId,
+                paymentId,
+                cardApplication,
             });
+            tpePaymentIdRef.current = null;
 
             if (result.success) {
                 setTpeStatus('success');
@@ -217,10 +282,14 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
                 setTimeout(() => {
                     const paymentResult: PaymentResult = {
                         method: 'card',
-                        totalAmount: amountCents / 100,
+                        totalAmount: amount,
                         cashReceived: 0,
-                        cardAmount: amountCents / 100,
+                        cardAmount: amount,
                         changeGiven: 0,
+                        cardTicket: result.cardholder_ticket,
+                        merchantTicket: result.merchant_ticket,
+                        tpeTransactionId: result.tpe_transaction_id,
+                        cardApplication: result.card_application,
                     };
                     setStep('complete');
                     // Call onConfirm after showing complete step briefly
@@ -233,15 +302,16 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
                 return true;
             } else {
                 setTpeStatus('error');
-                setTpeMessage(result.error_message || 'Paiement refusé');
+                setTpeMessage(result.error_message || result.error?.message || 'Paiement refusé');
                 return false;
             }
         } catch (err) {
+            tpePaymentIdRef.current = null;
             setTpeStatus('error');
-            setTpeMessage(String(err));
+            setTpeMessage(tpeErrorMessage(err));
             return false;
         }
-    }, [onConfirm, totalAmount]);
+    }, [onConfirm, totalAmount, cardApplication]);
 
     const handleMethodSelect = useCallback((selectedMethod: PaymentMethod) => {
         setMethod(selectedMethod);
@@ -568,13 +638,29 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
                                 </p>
                             </div>
 
+                            {/* Meal vouchers and ANCV run as their own application on the terminal */}
+                            {(tpeStatus === 'idle' || tpeStatus === 'error') && (
+                                <div className="payment-modal__card-apps">
+                                    {CARD_APPLICATIONS.map(app => (
+                                        <button
+                                            key={app.value}
+                                            type="button"
+                                            className={`payment-modal__card-app ${cardApplication === app.value ? 'payment-modal__card-app--active' : ''}`}
+                                            onClick={() => setCardApplication(app.value)}
+                                        >
+                                            {app.label}
+                                        </button>
+                                    ))}
+                                </div>
+                            )}
+
                             {/* Actions based on TPE status */}
                             {tpeStatus === 'idle' && (
                                 <Button
                                     variant="primary"
                                     size="xl"
                                     isFullWidth
-                                    onClick={() => sendToTpe(Math.round(totalAmount * 100))}
+                                    onClick={() => sendToTpe(totalAmount)}
                                 >
                                     <CardIcon size={18} /> Envoyer au TPE
                                 </Button>
@@ -586,9 +672,13 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
                                     size="xl"
                                     isFullWidth
                                     onClick={async () => {
+                                        // Only this payment: another till's terminal may be busy too
+                                        const paymentId = tpePaymentIdRef.current;
                                         try {
-                                            await invoke('cancel_tpe_transaction');
-                                            setTpeMessage('Annulation envoyée...');
+                                            if (paymentId) {
+                                                await invoke('cancel_tpe_transaction', { paymentId });
+                                                setTpeMessage('Annulation envoyée...');
+                                            }
                                         } catch (e) {
                                             console.error('Cancel failed', e);
                                         }
@@ -627,7 +717,7 @@ export const PaymentModal: React.FC<PaymentModalProps> = ({
                                     <Button
                                         variant="primary"
                                         size="xl"
-                                        onClick={() => sendToTpe(Math.round(totalAmount * 100))}
+                                        onClick={() => sendToTpe(totalAmount)}
                                         style={{ flex: 1 }}
                                     >
                                         <RefreshIcon size={18} /> Réessayer
@@ -0,0 +1,119 @@
+// ===================================
+// PendingPaymentsChecker - TPE payments left unresolved by a crash
+// ===================================
+
+import { useEffect } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { ask, message } from '@tauri-apps/plugin-dialog';
+import { tpeErrorMessage } from '../../services/tpeErrors';
+import { formatMinorUnits, type TpeCurrency } from '../../services/tpeTerminals';
+
+interface PendingPayment {
+    payment_id: string;
+    started_at: string;
+    terminal: { name: string; port: string; protocol_version: number };
+    transaction_type: string;
+    amount_minor: number;
+    currency?: TpeCurrency;
+    pos_transaction_id?: string;
+    last_error?: string;
+}
+
+interface TpePaymentResponse {
+    success: boolean;
+    transaction_result: string;
+    error_message?: string;
+    authorization_number?: string;
+}
+
+const TRANSACTION_LABELS: Record<string, string> = {
+    Debit: 'Paiement',
+    Credit: 'Remboursement',
+    PreAuthorization: 'Pré-autorisation',
+    Completion: 'Encaissement de pré-autorisation',
+    PreAuthCancellation: 'Annulation de pré-autorisation',
+};
+
+const describe = (payment: PendingPayment): string => {
+    const amount = formatMinorUnits(payment.amount_minor, payment.currency);
+    const date = new Date(payment.started_at).toLocaleString('fr-FR');
+    const kind = TRANSACTION_LABELS[payment.transaction_type] || 'Paiement';
+    const terminal = payment.terminal.name || payment.terminal.port;
+    const sale = payment.pos_transaction_id ? ` (vente n°${payment.pos_transaction_id})` : '';
+    return `${kind} de ${amount} du ${date} sur ${terminal}${sale}`;
+};
+
+export const PendingPaymentsChecker: React.FC = () => {
+    useEffect(() => {
+        checkPendingPayments();
+    }, []);
+
+    const checkPendingPayments = async () => {
+        // Only run in Tauri environment
+        if (typeof window === 'undefined' || !(window as any).__TAURI_INTERNALS__) {
+            return;
+        }
+
+        try {
+            const pending = await invoke<PendingPayment[]>('list_pending_tpe_payments');
+            for (const payment of pending) {
+                await resolvePayment(payment);
+            }
+        } catch (error) {
+            console.error('[PendingPayments] Failed to load pending payments:', error);
+        }
+    };
+
+    const resolvePayment = async (payment: PendingPayment) => {
+        const shouldCheck = await ask(
+            `${describe(payment)} : le résultat n'a jamais été reçu.\n\nLe client a peut-être été débité. Interroger le terminal ?`,
+            {
+                title: 'Paiement TPE non confirmé',
+                kind: 'warning',
+                okLabel: 'Interroger le TPE',
+                cancelLabel: 'Plus tard'
+            }
+        );
+        if (!shouldCheck) return;
+
+        try {
+            const result = await invoke<TpePaymentResponse | null>('check_pending_tpe_payment', {
+                paymentId: payment.payment_id,
+            });
+
+            if (!result) {
+                await message('Le terminal n\'a aucune trace de cette transaction : le client n\'a pas été débité.', {
+                    title: 'Paiement TPE',
+                    kind: 'info'
+                });
+                await invoke('dismiss_pending_tpe_payment', { paymentId: payment.payment_id });
+            } else if (result.success) {
+                await message(`Paiement accepté par le terminal (autorisation ${result.authorization_number || '-'}).\n\nPensez à enregistrer la vente.`, {
+                    title: 'Paiement TPE',
+                    kind: 'info'
+                });
+            } else {
+                await message(`Résultat du terminal : ${result.error_message || result.transaction_result}`, {
+                    title: 'Paiement TPE',
+                    kind: 'info'
+                });
+            }
+        } catch (error) {
+            // Protocol cannot be queried: the cashier checks the terminal journal by hand
+            const checked = await ask(
+                `${tpeErrorMessage(error)}\n\nMarquer ce paiement comme vérifié ?`,
+                {
+                    title: 'Paiement TPE non confirmé',
+                    kind: 'warning',
+                    okLabel: 'Vérifié',
+                    cancelLabel: 'Plus tard'
+                }
+            );
+            if (checked) {
+                await invoke('dismiss_pending_tpe_payment', { paymentId: payment.payment_id });
+            }
+        }
+    };
+
+    return null;
+};
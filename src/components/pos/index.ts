@@ -9,3 +9,4 @@ export { TicketsModal } from './TicketsModal';
 export { CashCounter } from './CashCounter';
 export { CashMovementModal } from './CashMovementModal';
 export { OpenClosureModal } from './OpenClosureModal';
+export { PendingPaymentsChecker } from './PendingPaymentsChecker';
@@ -4,6 +4,7 @@
 
 import React, { useState, useMemo, useCallback, useEffect } from 'react';
 import { useNavigate } from 'react-router-dom';
+import { invoke } from '@tauri-apps/api/core';
 import {
     Button,
     ChartIcon,
@@ -17,6 +18,8 @@ import { CashCounter } from '../components/pos/CashCounter';
 import { useAuthStore, useTransactionStore, useClosureStore, useProductStore } from '../stores';
 import { getCurrentSession, type CurrentSessionData } from '../services/api';
 import { generateClosurePDF } from '../services/pdfService';
+import { tpeErrorMessage } from '../services/tpeErrors';
+import { activeTpeCurrency, formatMinorUnits, toMinorUnits, type TpeCurrency } from '../services/tpeTerminals';
 import type { CashClosureWithDetails } from '../types';
 import './ClosurePage.css';
 
@@ -25,6 +28,20 @@ const formatPrice = (amount: number) => {
     return new Intl.NumberFormat('fr-FR', { style: 'currency', currency: 'EUR' }).format(amount);
 };
 
+// Terminal totals compared with the till's card sales (run_tpe_reconciliation)
+interface TpeReconciliation {
+    success: boolean;
+    terminal_name: string;
+    currency: TpeCurrency; // Amounts are in its minor units
+    terminal_total_minor: number | null;
+    terminal_count: number | null;
+    local_card_total_minor: number;
+    difference_minor: number | null;
+    balanced: boolean;
+    ticket: string[];
+    error_message: string | null;
+}
+
 const formatDate = (date: string | Date) => {
     return new Date(date).toLocaleString('fr-FR', {
         day: '2-digit',
@@ -54,6 +71,9 @@ export const ClosurePage: React.FC = () => {
     const [backendSession, setBackendSession] = useState<CurrentSessionData | null>(null);
     const [_isLoading, setIsLoading] = useState(true);
     const [_error, setError] = useState<string | null>(null);
+    const [reconciliation, setReconciliation] = useState<TpeReconciliation | null>(null);
+    const [reconciliationError, setReconciliationError] = useState<string | null>(null);
+    const [isReconciling, setIsReconciling] = useState(false);
 
     // Fetch session data from backend
     useEffect(() => {
@@ -160,6 +180,24 @@ export const ClosurePage: React.FC = () => {
         navigate('/pos');
     }, [navigate]);
 
+    // Ask the default terminal for its day totals (télécollecte)
+    const handleReconcile = async () => {
+        setIsReconciling(true);
+        setReconciliationError(null);
+        try {
+            const result = await invoke<TpeReconciliation>('run_tpe_reconciliation', {
+                terminalName: null,
+                localCardTotalMinor: toMinorUnits(sessionStats.totalCardSales, activeTpeCurrency()),
+            });
+            setReconciliation(result);
+        } catch (err) {
+            setReconciliation(null);
+            setReconciliationError(tpeErrorMessage(err));
+        } finally {
+            setIsReconciling(false);
+        }
+    };
+
     const handleCloseSession = async () => {
         if (!currentClosure) return;
 
@@ -309,6 +347,37 @@ export const ClosurePage: React.FC = () => {
                             </div>
                         </div>
 
+                        {/* Card totals: terminal vs till */}
+                        <div className="mt-4">
+                            <div className="flex justify-between items-center mb-2">
+                                <h3 className="text-[10px] font-bold text-gray-400 uppercase tracking-wider">Carte bancaire (TPE)</h3>
+                                <Button variant="secondary" size="sm" onClick={handleReconcile} disabled={isReconciling}>
+                                    {isReconciling ? 'Télécollecte...' : 'Télécollecte TPE'}
+                                </Button>
+                            </div>
+                            <div className="space-y-1">
+                                <div className="flex justify-between text-xs text-gray-500">
+                                    <span>Ventes carte (caisse)</span>
+                                    <span>{formatPrice(sessionStats.totalCardSales)}</span>
+                                </div>
+                                {reconciliation?.terminal_total_minor != null && (
+                                    <div className="flex justify-between text-xs text-gray-500">
+                                        <span>Total TPE {reconciliation.terminal_name} ({reconciliation.terminal_count ?? '?'} transactions)</span>
+                                        <span>{formatMinorUnits(reconciliation.terminal_total_minor, reconciliation.currency)}</span>
+                                    </div>
+                                )}
+                                {reconciliation?.difference_minor != null && (
+                                    <div className={`flex justify-between text-xs font-bold ${reconciliation.balanced ? 'text-green-600' : 'text-red-600'}`}>
+                                        <span>{reconciliation.balanced ? 'Totaux concordants' : 'Écart TPE / caisse'}</span>
+                                        <span>{reconciliation.difference_minor > 0 ? '+' : ''}{formatMinorUnits(reconciliation.difference_minor, reconciliation.currency)}</span>
+                                    </div>
+                                )}
+                                {(reconciliationError || (reconciliation && !reconciliation.balanced && reconciliation.error_message)) && (
+                                    <p className="text-xs text-red-600">{reconciliationError || reconciliation?.error_message}</p>
+                                )}
+                            </div>
+                        </div>
+
                         <div className="closure-notes-container mt-auto">
                             <label className="text-[10px] font-bold text-gray-400 uppercase tracking-wider mb-1 block">
                                 Notes
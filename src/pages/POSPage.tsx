@@ -10,6 +10,8 @@ import type { PaymentResult } from '../components/pos';
 import { CachedImage } from '../components/ui/CachedImage';
 import { useAuthStore, useCartStore, useTransactionStore, useProductStore, useMenuStore, useSyncStore, useClosureStore } from '../stores';
 import type { Menu, Product, Transaction } from '../types';
+import { watchTpeSessions } from '../services/tpeTerminals';
+import type { TpeSessionStatus } from '../services/tpeTerminals';
 import './POSPage.css';
 import logoImg from '../assets/logo-asmsp.png';
 
@@ -116,6 +118,12 @@ export const POSPage: React.FC = () => {
     const lowStockCount = getLowStockProducts().length;
     const { addToQueue, isOnline } = useSyncStore();
 
+    // Network terminals watched by the TPE session monitor
+    const [tpeSessions, setTpeSessions] = useState<TpeSessionStatus[]>([]);
+    useEffect(() => watchTpeSessions(setTpeSessions), []);
+    const checkedTpe = tpeSessions.filter(s => s.online !== null);
+    const offlineTpe = checkedTpe.filter(s => !s.online);
+
     // Ref for category scroll navigation
     const categoriesRef = useRef<HTMLDivElement>(null);
 
@@ -251,7 +259,9 @@ export const POSPage: React.FC = () => {
             paymentResult.totalAmount,
             paymentResult.method,
             paymentResult.cashReceived,
-            paymentResult.changeGiven
+            paymentResult.changeGiven,
+            paymentResult.tpeTransactionId,
+            paymentResult.cardApplication
         );
 
         // Decrement stock locally for immediate UI feedback
@@ -283,7 +293,44 @@ export const POSPage: React.FC = () => {
                     }
                 }
 
-                // 2. Print individual tickets for products with printTicket = true
+                // 2. Print the card ticket returned by the terminal (no more terminal paper roll)
+                // on the serial printer in serial mode, via the system driver otherwise
+                const serialPrinterPort = hardwareConfig.connectionMode === 'serial' ? hardwareConfig.printerPort : '';
+                if (serialPrinterPort || printerName) {
+                    const cardCopies: [string[] | undefined, 'customer' | 'merchant'][] = [
+                        [paymentResult.cardTicket, 'customer'],
+                        [paymentResult.merchantTicket, 'merchant'],
+                    ];
+                    for (const [lines, copy] of cardCopies) {
+                        if (!lines || lines.length === 0) continue;
+                        try {
+                            const { invoke } = await import('@tauri-apps/api/core');
+                            if (serialPrinterPort) {
+                                await invoke('print_card_ticket', {
+                                    config: {
+                                        port: serialPrinterPort,
+                                        baud_rate: hardwareConfig.printerBaudRate || 9600,
+                                        paper_width: hardwareConfig.paperWidth ?? 80,
+                                    },
+                                    lines,
+                                    copy,
+                                });
+                            } else {
+                                await invoke('print_card_ticket_via_driver', {
+                                    printerName,
+                                    lines,
+                                    copy,
+                                    paperWidth: hardwareConfig.paperWidth ?? 80,
+                                });
+                            }
+                            console.log(`[POS] Card ticket printed (${copy})`);
+                        } catch (printError) {
+                            console.warn('[POS] Failed to print card ticket:', printError);
+                        }
+                    }
+                }
+
+                // 3. Print individual tickets for products with printTicket = true
                 // (one ticket per item, no summary receipt)
                 // For menus: print separate tickets for each component
                 const ticketItems = items.filter(item => item.product.printTicket === true);
@@ -318,6 +365,7 @@ export const POSPage: React.FC = () => {
                                             printerName,
                                             receipt: componentReceipt,
                                             paperWidth: hardwareConfig.paperWidth ?? 80,
+                                            currency: hardwareConfig.currency,
                                         });
                                         console.log(`[POS] Menu component ticket printed: ${componentName}`);
                                     } catch (printError) {
@@ -347,6 +395,7 @@ export const POSPage: React.FC = () => {
                                         printerName,
                                         receipt: singleItemReceipt,
                                         paperWidth: hardwareConfig.paperWidth ?? 80,
+                                        currency: hardwareConfig.currency,
                                     });
                                     console.log(`[POS] Ticket printed for ${item.product.name} (${i + 1}/${item.quantity})`);
                                 } catch (printError) {
@@ -441,6 +490,21 @@ export const POSPage: React.FC = () => {
                         <span className="status-dot"></span>
                         {isOnline ? 'En ligne' : 'Hors ligne'}
                     </div>
+                    {checkedTpe.length > 0 && (
+                        <div
+                            className={`pos-header__status-badge ${offlineTpe.length === 0 ? 'status-online' : 'status-offline'}`}
+                            title={checkedTpe
+                                .map(s => `${s.terminal_name || s.address} : ${s.online ? 'en ligne' : `hors ligne${s.last_error ? ` (${s.last_error.message})` : ''}`}`)
+                                .join('\n')}
+                        >
+                            <span className="status-dot"></span>
+                            {offlineTpe.length === 0
+                                ? 'TPE en ligne'
+                                : checkedTpe.length === 1
+                                    ? 'TPE hors ligne'
+                                    : `${offlineTpe.length} TPE hors ligne`}
+                        </div>
+                    )}
                 </div>
 
                 <div className="pos-header__center">
@@ -2,7 +2,7 @@
 // Settings Page - Hardware Configuration
 // ===================================
 
-import React, { useState, useEffect, useCallback } from 'react';
+import React, { useState, useEffect, useCallback, useRef } from 'react';
 import { useNavigate } from 'react-router-dom';
 import { invoke } from '@tauri-apps/api/core';
 import {
@@ -32,6 +32,8 @@ import { useTransactionStore } from '../stores/transactionStore';
 import { useClosureStore } from '../stores/closureStore';
 import { useSyncStore } from '../stores/syncStore';
 import { useProductStore } from '../stores/productStore';
+import { syncTpeTerminals } from '../services/tpeTerminals';
+import { tpeErrorMessage } from '../services/tpeErrors';
 
 interface SerialPortInfo {
     name: string;
@@ -55,18 +57,66 @@ interface SystemPrinterInfo {
 
 type ConnectionMode = 'serial' | 'driver';
 
+interface CurrencyConfig {
+    numeric: string;     // ISO 4217 numeric (978)
+    alpha: string;       // ISO 4217 alpha (EUR)
+    symbol: string;      // Receipt symbol (€)
+    minor_units: number; // Decimals (2)
+    ascii_symbol?: string;
+}
+
+interface SerialSettingsConfig {
+    data_bits: number;   // 7 or 8
+    parity: 'none' | 'even' | 'odd';
+    stop_bits: number;   // 1 or 2
+    flow_control: 'none' | 'software' | 'hardware';
+    dtr: boolean | null; // null = left as the driver opens the port
+    rts: boolean | null;
+}
+
+// Concert standard: 7E1 without flow control
+const DEFAULT_SERIAL_SETTINGS: SerialSettingsConfig = {
+    data_bits: 7,
+    parity: 'even',
+    stop_bits: 1,
+    flow_control: 'none',
+    dtr: null,
+    rts: null,
+};
+
+// Per-phase time limits, a missing field keeps the default of the terminal
+interface TpeTimeoutsConfig {
+    connect_secs?: number;   // TCP connection (10 s)
+    ack_ms?: number;         // ACK of the request on Concert links (500 ms)
+    card_wait_secs?: number; // Card payment (auto: 120 s serial, 150 s IP)
+    test_ms?: number;        // Answer to the connection test (300 ms)
+    write_secs?: number;     // Sending a frame (10 s)
+    eot_ms?: number;         // EOT closing the terminal's answer (1000 ms)
+    ascii_answer_ms?: number; // Answer to the ASCII fallback (600 ms)
+    lrc_grace_ms?: number;   // LRC after ETX on IP links (200 ms)
+    probe_secs?: number;     // Answer to a detection probe (5 s)
+    abort_secs?: number;     // Answer to an aborted detection probe (3 s)
+}
+
 interface TpeDeviceConfig {
     name: string;        // User-friendly name
     port: string;        // COM port or IP:port
     baudRate: number;    // Baud rate for serial
     posNumber: string;   // POS number (01-99)
-    protocolVersion: 2 | 3 | 4 | 5 | 6 | 7; // Protocol type
+    protocolVersion: 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9; // Protocol type
     // 2 = Concert V2 (Binaire)
     // 3 = Concert V3 (TLV/Caisse-AP) 
     // 4 = Concert V3 (Binaire 19 chars)
     // 5 = SmilePay
     // 6 = Yavin Local API
     // 7 = Yavin Cloud API
+    // 8 = Caisse-AP IP (Nepting)
+    // 9 = Nepting (code RC)
+    terminalId?: string; // Yavin terminal serial number
+    apiKey?: string;     // Yavin Cloud API key
+    currency?: CurrencyConfig; // Defaults to EUR
+    serial?: SerialSettingsConfig; // Defaults to 7E1
+    timeouts?: TpeTimeoutsConfig;
 }
 
 interface TpeConfig {
@@ -90,6 +140,7 @@ interface HardwareConfig {
     drawerPort: string;
     drawerPin: number;
     systemPrinterName: string;
+    currency?: CurrencyConfig; // Defaults to EUR
 }
 
 const DEFAULT_CONFIG: HardwareConfig = {
@@ -128,6 +179,9 @@ export const SettingsPage: React.FC = () => {
     });
     const [tpeTestResult, setTpeTestResult] = useState<{ deviceIndex: number; type: 'success' | 'error'; message: string } | null>(null);
     const [isTpeTesting, setIsTpeTesting] = useState<number | null>(null);
+    const [currencies, setCurrencies] = useState<CurrencyConfig[]>([]);
+    // Slot names last synced to the terminal registry (renames update the same entry)
+    const tpeNamesRef = useRef<string[]>(tpeConfig.devices.map(d => d.name));
 
     // clear Data State
     const [showClearDataModal, setShowClearDataModal] = useState(false);
@@ -150,6 +204,9 @@ export const SettingsPage: React.FC = () => {
         scanPorts();
         scanSystemPrinters();
         checkStatus();
+        invoke<CurrencyConfig[]>('list_currencies')
+            .then(setCurrencies)
+            .catch((err) => console.error('Failed to load currencies:', err));
     }, []);
 
     // Save configuration when it changes
@@ -160,6 +217,10 @@ export const SettingsPage: React.FC = () => {
     // Save TPE configuration when it changes
     useEffect(() => {
         localStorage.setItem('ma-caisse-tpe-config', JSON.stringify(tpeConfig));
+        syncTpeTerminals(tpeConfig, tpeNamesRef.current).catch(err => {
+            console.warn('[Settings] TPE terminal registry sync failed:', err);
+        });
+        tpeNamesRef.current = tpeConfig.devices.map(d => d.name);
     }, [tpeConfig]);
 
     const scanPorts = useCallback(async () => {
@@ -283,6 +344,8 @@ export const SettingsPage: React.FC = () => {
             const result = await invoke<{ connected: boolean; message: string }>('test_tpe_connection', {
                 portName: device.port,
                 baudRate: device.baudRate,
+                serial: device.serial,
+                timeouts: device.timeouts,
             });
             setTpeTestResult({
                 deviceIndex,
@@ -293,7 +356,7 @@ export const SettingsPage: React.FC = () => {
             setTpeTestResult({
                 deviceIndex,
                 type: 'error',
-                message: String(err),
+                message: tpeErrorMessage(err),
             });
         } finally {
             setIsTpeTesting(null);
@@ -308,6 +371,147 @@ export const SettingsPage: React.FC = () => {
         });
     };
 
+    const updateSerialSettings = (deviceIndex: number, updates: Partial<SerialSettingsConfig>) => {
+        const current = { ...DEFAULT_SERIAL_SETTINGS, ...tpeConfig.devices[deviceIndex].serial };
+        updateTpeDevice(deviceIndex, { serial: { ...current, ...updates } });
+    };
+
+    // Serial line settings, for terminals on a COM port only
+    const renderSerialSettings = (deviceIndex: number) => {
+        const serial = { ...DEFAULT_SERIAL_SETTINGS, ...tpeConfig.devices[deviceIndex].serial };
+        const lineValue = (state: boolean | null) => (state === null ? 'default' : state ? 'on' : 'off');
+        const lineState = (value: string) => (value === 'default' ? null : value === 'on');
+        return (
+            <div className="settings-form__row">
+                <div className="settings-form__group">
+                    <label className="settings-form__label">Format</label>
+                    <div style={{ display: 'flex', gap: '6px' }}>
+                        <select
+                            className="settings-form__select"
+                            value={serial.data_bits}
+                            onChange={(e) => updateSerialSettings(deviceIndex, { data_bits: Number(e.target.value) })}
+                        >
+                            <option value={7}>7 bits</option>
+                            <option value={8}>8 bits</option>
+                        </select>
+                        <select
+                            className="settings-form__select"
+                            value={serial.parity}
+                            onChange={(e) => updateSerialSettings(deviceIndex, { parity: e.target.value as SerialSettingsConfig['parity'] })}
+                        >
+                            <option value="even">Paire</option>
+                            <option value="odd">Impaire</option>
+                            <option value="none">Aucune</option>
+                        </select>
+                        <select
+                            className="settings-form__select"
+                            value={serial.stop_bits}
+                            onChange={(e) => updateSerialSettings(deviceIndex, { stop_bits: Number(e.target.value) })}
+                        >
+                            <option value={1}>1 stop</option>
+                            <option value={2}>2 stop</option>
+                        </select>
+                    </div>
+                    <p className="settings-form__help">
+                        Concert = 7 bits, paire, 1 stop | PAX et anciens Ingenico = souvent 8 bits, aucune
+                    </p>
+                </div>
+                <div className="settings-form__group">
+                    <label className="settings-form__label">Contrôle de flux</label>
+                    <select
+                        className="settings-form__select"
+                        value={serial.flow_control}
+                        onChange={(e) => updateSerialSettings(deviceIndex, { flow_control: e.target.value as SerialSettingsConfig['flow_control'] })}
+                    >
+                        <option value="none">Aucun</option>
+                        <option value="hardware">Matériel (RTS/CTS)</option>
+                        <option value="software">Logiciel (XON/XOFF)</option>
+                    </select>
+                </div>
+                <div className="settings-form__group">
+                    <label className="settings-form__label">DTR / RTS</label>
+                    <div style={{ display: 'flex', gap: '6px' }}>
+                        <select
+                            className="settings-form__select"
+                            value={lineValue(serial.dtr)}
+                            onChange={(e) => updateSerialSettings(deviceIndex, { dtr: lineState(e.target.value) })}
+                        >
+                            <option value="default">DTR auto</option>
+                            <option value="on">DTR actif</option>
+                            <option value="off">DTR inactif</option>
+                        </select>
+                        <select
+                            className="settings-form__select"
+                            value={lineValue(serial.rts)}
+                            onChange={(e) => updateSerialSettings(deviceIndex, { rts: lineState(e.target.value) })}
+                        >
+                            <option value="default">RTS auto</option>
+                            <option value="on">RTS actif</option>
+                            <option value="off">RTS inactif</option>
+                        </select>
+                    </div>
+                </div>
+            </div>
+        );
+    };
+
+    // An emptied field goes back to the default of the terminal
+    const updateTimeout = (deviceIndex: number, key: keyof TpeTimeoutsConfig, value: string) => {
+        const timeouts = { ...tpeConfig.devices[deviceIndex].timeouts };
+        if (value === '') {
+            delete timeouts[key];
+        } else {
+            timeouts[key] = Number(value);
+        }
+        updateTpeDevice(deviceIndex, { timeouts: Object.keys(timeouts).length > 0 ? timeouts : undefined });
+    };
+
+    // Time limits of each phase of a transaction
+    const renderTimeoutSettings = (deviceIndex: number) => {
+        const timeouts = tpeConfig.devices[deviceIndex].timeouts || {};
+        const field = (key: keyof TpeTimeoutsConfig, label: string, placeholder: string, min: number, max: number) => (
+            <div className="settings-form__group">
+                <label className="settings-form__label">{label}</label>
+                <input
+                    type="number"
+                    className="settings-form__input"
+                    min={min}
+                    max={max}
+                    value={timeouts[key] ?? ''}
+                    onChange={(e) => updateTimeout(deviceIndex, key, e.target.value)}
+                    placeholder={placeholder}
+                />
+            </div>
+        );
+        return (
+            <>
+                <div className="settings-form__row">
+                    {field('card_wait_secs', 'Attente carte (s)', 'Auto (120 série, 150 IP)', 10, 600)}
+                    {field('connect_secs', 'Connexion (s)', '10', 1, 120)}
+                </div>
+                <div className="settings-form__row">
+                    {field('ack_ms', 'Accusé de réception (ms)', '500', 100, 30000)}
+                    {field('test_ms', 'Test de connexion (ms)', '300', 50, 10000)}
+                </div>
+                <div className="settings-form__row">
+                    {field('write_secs', 'Envoi (s)', '10', 1, 60)}
+                    {field('eot_ms', 'Fin de réponse EOT (ms)', '1000', 100, 10000)}
+                </div>
+                <div className="settings-form__row">
+                    {field('ascii_answer_ms', 'Réponse mode ASCII (ms)', '600', 100, 10000)}
+                    {field('lrc_grace_ms', 'Attente du LRC (ms)', '200', 50, 5000)}
+                </div>
+                <div className="settings-form__row">
+                    {field('probe_secs', 'Détection (s)', '5', 1, 60)}
+                    {field('abort_secs', 'Annulation détection (s)', '3', 1, 30)}
+                </div>
+                <p className="settings-form__help">
+                    Laisser vide pour garder la valeur par défaut. Allonger l'attente carte si le client doit souvent saisir son code.
+                </p>
+            </>
+        );
+    };
+
     // Test payment (1 centime) to verify TPE communication
     const handleTestPayment = useCallback(async (deviceIndex: number) => {
         setIsTpeTesting(deviceIndex);
@@ -319,7 +523,13 @@ export const SettingsPage: React.FC = () => {
                 baudRate: device.baudRate,
                 posNumber: device.posNumber,
                 protocolVersion: device.protocolVersion,
-                amountCents: 1, // 1 centime test
+                amountMinor: 1, // 1 centime test (smallest unit of the currency)
+                terminalId: device.terminalId,
+                apiKey: device.apiKey,
+                posTransactionId: `TEST-${Date.now()}`,
+                currency: device.currency,
+                serial: device.serial,
+                timeouts: device.timeouts,
             });
             setTpeTestResult({
                 deviceIndex,
@@ -330,7 +540,46 @@ export const SettingsPage: React.FC = () => {
             setTpeTestResult({
                 deviceIndex,
                 type: 'error',
-                message: String(err),
+                message: tpeErrorMessage(err),
+            });
+        } finally {
+            setIsTpeTesting(null);
+        }
+    }, [tpeConfig.devices]);
+
+    // Probe every protocol on the configured port and apply the one that answered (saved automatically)
+    const handleDetectProtocol = useCallback(async (deviceIndex: number) => {
+        setIsTpeTesting(deviceIndex);
+        setTpeTestResult(null);
+        const device = tpeConfig.devices[deviceIndex];
+        try {
+            const result = await invoke<{
+                message: string;
+                suggested: { port: string; protocol_version: number } | null;
+            }>('detect_tpe_protocol', {
+                portName: device.port,
+                baudRate: device.baudRate,
+                posNumber: device.posNumber,
+                protocolVersion: device.protocolVersion,
+                serial: device.serial,
+                timeouts: device.timeouts,
+            });
+            if (result.suggested) {
+                updateTpeDevice(deviceIndex, {
+                    port: result.suggested.port,
+                    protocolVersion: result.suggested.protocol_version as 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9,
+                });
+            }
+            setTpeTestResult({
+                deviceIndex,
+                type: result.suggested ? 'success' : 'error',
+                message: result.message,
+            });
+        } catch (err) {
+            setTpeTestResult({
+                deviceIndex,
+                type: 'error',
+                message: tpeErrorMessage(err),
             });
         } finally {
             setIsTpeTesting(null);
@@ -604,6 +853,20 @@ export const SettingsPage: React.FC = () => {
                                     </>
                                 )}
 
+                                {/* Receipt currency - common to both modes */}
+                                <div className="settings-form__group">
+                                    <label>Devise des tickets</label>
+                                    <select
+                                        className="settings-form__select"
+                                        value={config.currency?.alpha || 'EUR'}
+                                        onChange={(e) => setConfig({ ...config, currency: currencies.find(c => c.alpha === e.target.value) })}
+                                    >
+                                        {currencies.map(c => (
+                                            <option key={c.alpha} value={c.alpha}>{c.alpha} ({c.symbol})</option>
+                                        ))}
+                                    </select>
+                                </div>
+
                                 {/* Paper Width - common to both modes */}
                                 <div className="settings-form__group">
                                     <label>Largeur papier</label>
@@ -985,6 +1248,18 @@ export const SettingsPage: React.FC = () => {
                                                 maxLength={2}
                                             />
                                         </div>
+                                        <div className="settings-form__group">
+                                            <label className="settings-form__label">Devise</label>
+                                            <select
+                                                className="settings-form__select"
+                                                value={tpeConfig.devices[0].currency?.alpha || 'EUR'}
+                                                onChange={(e) => updateTpeDevice(0, { currency: currencies.find(c => c.alpha === e.target.value) })}
+                                            >
+                                                {currencies.map(c => (
+                                                    <option key={c.alpha} value={c.alpha}>{c.alpha} ({c.symbol})</option>
+                                                ))}
+                                            </select>
+                                        </div>
                                     </div>
                                     <div className="settings-form__row">
                                         <div className="settings-form__group">
@@ -992,7 +1267,7 @@ export const SettingsPage: React.FC = () => {
                                             <select
                                                 className="settings-form__select"
                                                 value={tpeConfig.devices[0].protocolVersion}
-                                                onChange={(e) => updateTpeDevice(0, { protocolVersion: Number(e.target.value) as 2 | 3 | 4 | 5 | 6 | 7 })}
+                                                onChange={(e) => updateTpeDevice(0, { protocolVersion: Number(e.target.value) as 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9 })}
                                             >
                                                 <option value={2}>Concert V2 (Binaire - Ancien)</option>
                                                 <option value={3}>Concert V3 TLV (Caisse-AP)</option>
@@ -1000,12 +1275,40 @@ export const SettingsPage: React.FC = () => {
                                                 <option value={5}>SmilePay</option>
                                                 <option value={6}>Yavin (Local API)</option>
                                                 <option value={7}>Yavin (Cloud API)</option>
+                                                <option value={8}>Caisse-AP IP (Nepting)</option>
+                                                <option value={9}>Nepting (code RC)</option>
                                             </select>
                                             <p className="settings-form__help">
                                                 Indigo/SmilePay = V3 TLV | Yavin = API HTTP
                                             </p>
                                         </div>
+                                        {(tpeConfig.devices[0].protocolVersion === 6 || tpeConfig.devices[0].protocolVersion === 7) && (
+                                            <div className="settings-form__group">
+                                                <label className="settings-form__label">N° de série Yavin</label>
+                                                <input
+                                                    type="text"
+                                                    className="settings-form__input"
+                                                    value={tpeConfig.devices[0].terminalId || ''}
+                                                    onChange={(e) => updateTpeDevice(0, { terminalId: e.target.value })}
+                                                    placeholder="Numéro de série du terminal"
+                                                />
+                                            </div>
+                                        )}
+                                        {tpeConfig.devices[0].protocolVersion === 7 && (
+                                            <div className="settings-form__group">
+                                                <label className="settings-form__label">Clé API Yavin</label>
+                                                <input
+                                                    type="password"
+                                                    className="settings-form__input"
+                                                    value={tpeConfig.devices[0].apiKey || ''}
+                                                    onChange={(e) => updateTpeDevice(0, { apiKey: e.target.value })}
+                                                    placeholder="Clé API du compte Yavin"
+                                                />
+                                            </div>
+                                        )}
                                     </div>
+                                    {!tpeConfig.devices[0].port.includes(':') && tpeConfig.devices[0].protocolVersion !== 7 && renderSerialSettings(0)}
+                                    {renderTimeoutSettings(0)}
                                     <div style={{ display: 'flex', gap: '10px', marginTop: '10px' }}>
                                         <Button onClick={() => handleTestTpe(0)} disabled={isTpeTesting === 0}>
                                             {isTpeTesting === 0 ? (
@@ -1017,6 +1320,9 @@ export const SettingsPage: React.FC = () => {
                                         <Button onClick={() => handleTestPayment(0)} disabled={isTpeTesting === 0} variant="secondary">
                                             <CardIcon size={16} className="mr-2" /> Test paiement (1c)
                                         </Button>
+                                        <Button onClick={() => handleDetectProtocol(0)} disabled={isTpeTesting === 0} variant="secondary">
+                                            <SearchIcon size={16} className="mr-2" /> Détecter le protocole
+                                        </Button>
                                     </div>
                                     {tpeTestResult && tpeTestResult.deviceIndex === 0 && (
                                         <div className={`settings-alert settings-alert--${tpeTestResult.type}`} style={{ marginTop: '10px' }}>
@@ -1103,6 +1409,18 @@ export const SettingsPage: React.FC = () => {
                                                 maxLength={2}
                                             />
                                         </div>
+                                        <div className="settings-form__group">
+                                            <label className="settings-form__label">Devise</label>
+                                            <select
+                                                className="settings-form__select"
+                                                value={tpeConfig.devices[1].currency?.alpha || 'EUR'}
+                                                onChange={(e) => updateTpeDevice(1, { currency: currencies.find(c => c.alpha === e.target.value) })}
+                                            >
+                                                {currencies.map(c => (
+                                                    <option key={c.alpha} value={c.alpha}>{c.alpha} ({c.symbol})</option>
+                                                ))}
+                                            </select>
+                                        </div>
                                     </div>
                                     <div className="settings-form__row">
                                         <div className="settings-form__group">
@@ -1110,7 +1428,7 @@ export const SettingsPage: React.FC = () => {
                                             <select
                                                 className="settings-form__select"
                                                 value={tpeConfig.devices[1].protocolVersion}
-                                                onChange={(e) => updateTpeDevice(1, { protocolVersion: Number(e.target.value) as 2 | 3 | 4 | 5 | 6 | 7 })}
+                                                onChange={(e) => updateTpeDevice(1, { protocolVersion: Number(e.target.value) as 2 | 3 | 4 | 5 | 6 | 7 | 8 | 9 })}
                                             >
                                                 <option value={2}>Concert V2 (Binaire - Ancien)</option>
                                                 <option value={3}>Concert V3 TLV (Caisse-AP)</option>
@@ -1118,12 +1436,40 @@ export const SettingsPage: React.FC = () => {
                                                 <option value={5}>SmilePay</option>
                                                 <option value={6}>Yavin (Local API)</option>
                                                 <option value={7}>Yavin (Cloud API)</option>
+                                                <option value={8}>Caisse-AP IP (Nepting)</option>
+                                                <option value={9}>Nepting (code RC)</option>
                                             </select>
                                             <p className="settings-form__help">
                                                 Indigo/SmilePay = V3 TLV | Yavin = API HTTP
                                             </p>
                                         </div>
+                                        {(tpeConfig.devices[1].protocolVersion === 6 || tpeConfig.devices[1].protocolVersion === 7) && (
+                                            <div className="settings-form__group">
+                                                <label className="settings-form__label">N° de série Yavin</label>
+                                                <input
+                                                    type="text"
+                                                    className="settings-form__input"
+                                                    value={tpeConfig.devices[1].terminalId || ''}
+                                                    onChange={(e) => updateTpeDevice(1, { terminalId: e.target.value })}
+                                                    placeholder="Numéro de série du terminal"
+                                                />
+                                            </div>
+                                        )}
+                                        {tpeConfig.devices[1].protocolVersion === 7 && (
+                                            <div className="settings-form__group">
+                                                <label className="settings-form__label">Clé API Yavin</label>
+                                                <input
+                                                    type="password"
+                                                    className="settings-form__input"
+                                                    value={tpeConfig.devices[1].apiKey || ''}
+                                                    onChange={(e) => updateTpeDevice(1, { apiKey: e.target.value })}
+                                                    placeholder="Clé API du compte Yavin"
+                                                />
+                                            </div>
+                                        )}
                                     </div>
+                                    {!tpeConfig.devices[1].port.includes(':') && tpeConfig.devices[1].protocolVersion !== 7 && renderSerialSettings(1)}
+                                    {renderTimeoutSettings(1)}
                                     <div style={{ display: 'flex', gap: '10px', marginTop: '10px' }}>
                                         <Button onClick={() => handleTestTpe(1)} disabled={isTpeTesting === 1}>
                                             {isTpeTesting === 1 ? (
@@ -1135,6 +1481,9 @@ export const SettingsPage: React.FC = () => {
                                         <Button onClick={() => handleTestPayment(1)} disabled={isTpeTesting === 1} variant="secondary">
                                             <CardIcon size={16} className="mr-2" /> Test paiement (1c)
                                         </Button>
+                                        <Button onClick={() => handleDetectProtocol(1)} disabled={isTpeTesting === 1} variant="secondary">
+                                            <SearchIcon size={16} className="mr-2" /> Détecter le protocole
+                                        </Button>
                                     </div>
                                     {tpeTestResult && tpeTestResult.deviceIndex === 1 && (
                                         <div className={`settings-alert settings-alert--${tpeTestResult.type}`} style={{ marginTop: '10px' }}>
@@ -1168,7 +1517,7 @@ export const SettingsPage: React.FC = () => {
                                                     URL.revokeObjectURL(url);
                                                     setTpeTestResult({ deviceIndex: -1, type: 'success', message: 'Logs téléchargés !' });
                                                 } catch (err) {
-                                                    setTpeTestResult({ deviceIndex: -1, type: 'error', message: String(err) });
+                                                    setTpeTestResult({ deviceIndex: -1, type: 'error', message: tpeErrorMessage(err) });
                                                 }
                                             }}
                                         >
@@ -1181,7 +1530,7 @@ export const SettingsPage: React.FC = () => {
                                                     await invoke('clear_tpe_logs');
                                                     setTpeTestResult({ deviceIndex: -1, type: 'success', message: 'Logs effacés !' });
                                                 } catch (err) {
-                                                    setTpeTestResult({ deviceIndex: -1, type: 'error', message: String(err) });
+                                                    setTpeTestResult({ deviceIndex: -1, type: 'error', message: tpeErrorMessage(err) });
                                                 }
                                             }}
                                         >
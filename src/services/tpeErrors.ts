@@ -0,0 +1,36 @@
+// ===================================
+// TPE Errors - Structured errors returned by the TPE commands
+// ===================================
+
+// Stable codes of the Rust TpeError (tpe_error.rs)
+export type TpeErrorCode =
+    | 'ConnectFailed'
+    | 'Timeout'
+    | 'Refused'
+    | 'Cancelled'
+    | 'ConnectionLost'
+    | 'Protocol'
+    | 'Unsupported'
+    | 'InvalidConfig'
+    | 'InvalidRequest'
+    | 'UnknownTerminal'
+    | 'NoDefaultTerminal'
+    | 'DuplicateTerminal'
+    | 'UnknownPayment'
+    | 'Storage';
+
+// Serialized as { code, message, ...fields of the variant } (reason, target, seconds, code...)
+export interface TpeError {
+    code: TpeErrorCode;
+    message: string;
+    [field: string]: unknown;
+}
+
+export function isTpeError(err: unknown): err is TpeError {
+    return typeof err === 'object' && err !== null && 'code' in err && 'message' in err;
+}
+
+/** Message to show for an error thrown by a TPE command (or any other invoke) */
+export function tpeErrorMessage(err: unknown): string {
+    return isTpeError(err) ? err.message : String(err);
+}
@@ -0,0 +1,157 @@
+// ===================================
+// TPE Terminals - Sync the settings slots into the Rust terminal registry
+// ===================================
+
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { TpeError } from './tpeErrors';
+
+// Device slot as saved by the settings page (localStorage 'ma-caisse-tpe-config')
+export interface TpeDeviceSlot {
+    name: string;
+    port: string;
+    baudRate: number;
+    posNumber: string;
+    protocolVersion: number;
+    terminalId?: string;
+    apiKey?: string;
+    currency?: TpeCurrency;
+    serial?: object;
+    timeouts?: object;
+}
+
+// ISO 4217 currency of a terminal (Rust Currency)
+export interface TpeCurrency {
+    numeric: string;
+    alpha: string;
+    symbol: string;
+    minor_units: number;
+    ascii_symbol?: string;
+}
+
+export interface TpeSlotsConfig {
+    devices: TpeDeviceSlot[];
+    activeDeviceIndex: number;
+}
+
+interface TerminalRegistry {
+    terminals: { name: string }[];
+    default_terminal: string | null;
+}
+
+const toTerminal = (device: TpeDeviceSlot) => ({
+    name: device.name.trim(),
+    port: device.port,
+    baud_rate: device.baudRate,
+    pos_number: device.posNumber,
+    protocol_version: device.protocolVersion,
+    terminal_id: device.terminalId || null,
+    api_key: device.apiKey || null,
+    ...(device.currency ? { currency: device.currency } : {}),
+    ...(device.serial ? { serial: device.serial } : {}),
+    ...(device.timeouts ? { timeouts: device.timeouts } : {}),
+});
+
+// Terminals count amounts in minor units of their currency: cents for EUR,
+// francs for XPF (no decimals). A slot without a currency is in EUR.
+const minorFactor = (currency?: { minor_units: number }) => 10 ** (currency?.minor_units ?? 2);
+
+export const toMinorUnits = (amount: number, currency?: { minor_units: number }): number =>
+    Math.round(amount * minorFactor(currency));
+
+export const fromMinorUnits = (amountMinor: number, currency?: { minor_units: number }): number =>
+    amountMinor / minorFactor(currency);
+
+export const formatMinorUnits = (amountMinor: number, currency?: TpeCurrency): string =>
+    new Intl.NumberFormat('fr-FR', { style: 'currency', currency: currency?.alpha ?? 'EUR' })
+        .format(fromMinorUnits(amountMinor, currency));
+
+/** Currency of the active slot, which is synced as the default terminal */
+export function activeTpeCurrency(): TpeCurrency | undefined {
+    try {
+        const saved = localStorage.getItem('ma-caisse-tpe-config');
+        if (!saved) return undefined;
+        const config: TpeSlotsConfig = JSON.parse(saved);
+        return config.devices[config.activeDeviceIndex]?.currency;
+    } catch {
+        return undefined;
+    }
+}
+
+// Syncs run one after the other: each one reads the registry left by the previous
+let syncQueue: Promise<void> = Promise.resolve();
+
+/**
+ * Register or update every named slot and make the active one the default terminal.
+ * `previousNames` are the slot names before an edit, so a renamed slot updates its entry.
+ */
+export function syncTpeTerminals(config: TpeSlotsConfig, previousNames: string[] = []): Promise<void> {
+    syncQueue = syncQueue.catch(() => undefined).then(() => syncNow(config, previousNames));
+    return syncQueue;
+}
+
+async function syncNow(config: TpeSlotsConfig, previousNames: string[]): Promise<void> {
+    const registry = await invoke<TerminalRegistry>('list_tpe_terminals');
+    const registered = new Set(registry.terminals.map(t => t.name));
+
+    for (const [index, device] of config.devices.entries()) {
+        const name = device.name.trim();
+        if (!name) continue;
+
+        const previous = previousNames[index]?.trim();
+        const existing = previous && registered.has(previous) ? previous : registered.has(name) ? name : null;
+        try {
+            if (existing) {
+                await invoke('update_tpe_terminal', { name: existing, terminal: toTerminal(device) });
+                registered.delete(existing);
+            } else {
+                await invoke('add_tpe_terminal', { terminal: toTerminal(device) });
+            }
+            registered.add(name);
+        } catch (err) {
+            // Incomplete slot (no port yet, duplicate name...): keep the last valid entry
+            console.warn(`[TPE] Terminal "${name}" not saved:`, err);
+        }
+    }
+
+    const active = config.devices[config.activeDeviceIndex]?.name.trim();
+    if (active && registered.has(active) && registry.default_terminal !== active) {
+        await invoke('set_default_tpe_terminal', { name: active });
+    }
+}
+
+// Network terminal state reported by the Rust session monitor
+export interface TpeSessionStatus {
+    terminal_name: string;
+    address: string;
+    online: boolean | null;
+    connected: boolean;
+    last_seen: string | null;
+    last_error: TpeError | null;
+}
+
+/**
+ * Call `onChange` with the state of every network terminal, now and on each
+ * online/offline change. Returns the function that stops watching.
+ */
+export function watchTpeSessions(onChange: (sessions: TpeSessionStatus[]) => void): () => void {
+    const sessions = new Map<string, TpeSessionStatus>();
+    const publish = () => onChange([...sessions.values()]);
+
+    invoke<TpeSessionStatus[]>('get_tpe_sessions')
+        .then(initial => {
+            for (const session of initial) {
+                if (!sessions.has(session.address)) sessions.set(session.address, session);
+            }
+            publish();
+        })
+        .catch(err => console.warn('[TPE] Session states unavailable:', err));
+
+    const unlisten = listen<TpeSessionStatus>('tpe-status', event => {
+        sessions.set(event.payload.address, event.payload);
+        publish();
+    });
+    return () => {
+        unlisten.then(stop => stop()).catch(() => undefined);
+    };
+}
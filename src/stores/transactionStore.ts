@@ -4,7 +4,7 @@
 
 import { create } from 'zustand';
 import { persist } from 'zustand/middleware';
-import type { Transaction, CartItem, PaymentMethod } from '../types';
+import type { Transaction, CartItem, PaymentMethod, CardApplication } from '../types';
 
 interface TransactionState {
     // State
@@ -19,7 +19,9 @@ interface TransactionState {
         totalAmount: number,
         paymentMethod: PaymentMethod,
         cashReceived?: number,
-        changeGiven?: number
+        changeGiven?: number,
+        tpeTransactionId?: string,
+        cardApplication?: CardApplication
     ) => Transaction;
 
     getTransactionById: (id: number) => Transaction | undefined;
@@ -39,7 +41,7 @@ export const useTransactionStore = create<TransactionState>()(
             lastTransactionId: 0,
 
             // Actions
-            addTransaction: (userId, items, totalAmount, paymentMethod, cashReceived, changeGiven) => {
+            addTransaction: (userId, items, totalAmount, paymentMethod, cashReceived, changeGiven, tpeTransactionId, cardApplication) => {
                 const { lastTransactionId, transactions, currentSessionTransactions } = get();
 
                 const newTransactionId = lastTransactionId + 1;
@@ -61,6 +63,8 @@ export const useTransactionStore = create<TransactionState>()(
                     paymentMethod,
                     cashReceived,
                     changeGiven,
+                    tpeTransactionId,
+                    cardApplication,
                     items: transactionItems,
                     isSynced: false,
                 };
@@ -8,6 +8,9 @@ export type UserRole = 'admin' | 'manager' | 'cashier';
 // Payment methods available
 export type PaymentMethod = 'cash' | 'card' | 'mixed' | 'benevole';
 
+// Card application used by the payment terminal (meal and holiday vouchers run apart from bank cards)
+export type CardApplication = 'bank_card' | 'meal_voucher' | 'holiday_voucher';
+
 // Stock movement types
 export type StockMovementType = 'in' | 'out' | 'adjustment' | 'sale';
 
@@ -98,6 +101,8 @@ export interface Transaction {
   paymentMethod: PaymentMethod;
   cashReceived?: number;
   changeGiven?: number;
+  tpeTransactionId?: string; // TI sent to the payment terminal (card payments)
+  cardApplication?: CardApplication; // Application reported by the terminal (card payments)
   items: TransactionItem[];
   isSynced: boolean;
 }
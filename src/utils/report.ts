@@ -248,6 +248,23 @@ function openPrintWindow(html: string, _title: string): void {
 // Pre-built Report Templates
 // ===================================
 
+// Card sales split by terminal application (sales recorded before the split count as bank cards)
+function cardSalesByApplication(transactions: Transaction[]) {
+    const cardTransactions = transactions.filter(t => t.paymentMethod === 'card');
+    const total = (application: string) => cardTransactions
+        .filter(t => (t.cardApplication ?? 'bank_card') === application)
+        .reduce((sum, t) => sum + t.totalAmount, 0);
+
+    return {
+        bankCard: total('bank_card'),
+        mealVoucher: total('meal_voucher'),
+        holidayVoucher: total('holiday_voucher'),
+    };
+}
+
+const cardPaymentLabel = (t: Transaction) =>
+    t.cardApplication === 'meal_voucher' ? 'Titre-restaurant' : t.cardApplication === 'holiday_voucher' ? 'ANCV' : 'Carte';
+
 export function generateDailyReport(
     transactions: Transaction[],
     date: Date = new Date()
@@ -265,6 +282,7 @@ export function generateDailyReport(
     const totalSales = dayTransactions.reduce((sum, t) => sum + t.totalAmount, 0);
     const cashSales = dayTransactions.filter(t => t.paymentMethod === 'cash').reduce((sum, t) => sum + t.totalAmount, 0);
     const cardSales = dayTransactions.filter(t => t.paymentMethod === 'card').reduce((sum, t) => sum + t.totalAmount, 0);
+    const cardSplit = cardSalesByApplication(dayTransactions);
     const avgTicket = dayTransactions.length > 0 ? totalSales / dayTransactions.length : 0;
 
     const formatPrice = (p: number) => p.toFixed(2).replace('.', ',') + ' €';
@@ -283,7 +301,9 @@ export function generateDailyReport(
                     { label: 'Nombre de transactions', value: String(dayTransactions.length) },
                     { label: 'Ticket moyen', value: formatPrice(avgTicket) },
                     { label: 'Paiements espèces', value: formatPrice(cashSales) },
-                    { label: 'Paiements carte', value: formatPrice(cardSales) },
+                    { label: 'Paiements carte bancaire', value: formatPrice(cardSplit.bankCard) },
+                    { label: 'Titres-restaurant', value: formatPrice(cardSplit.mealVoucher) },
+                    { label: 'Chèques-Vacances (ANCV)', value: formatPrice(cardSplit.holidayVoucher) },
                     { label: 'Mixte/Autre', value: formatPrice(totalSales - cashSales - cardSales) },
                 ],
             },
@@ -296,7 +316,7 @@ export function generateDailyReport(
                         String(t.id),
                         formatTime(t.createdAt),
                         formatPrice(t.totalAmount),
-                        t.paymentMethod === 'cash' ? 'Espèces' : t.paymentMethod === 'card' ? 'Carte' : 'Mixte',
+                        t.paymentMethod === 'cash' ? 'Espèces' : t.paymentMethod === 'card' ? cardPaymentLabel(t) : 'Mixte',
                     ]),
                 },
             },
@@ -316,7 +336,7 @@ export function generateClosureReport(closure: CashClosure, transactions: Transa
 
     const totalSales = closureTransactions.reduce((sum, t) => sum + t.totalAmount, 0);
     const cashSales = closureTransactions.filter(t => t.paymentMethod === 'cash').reduce((sum, t) => sum + t.totalAmount, 0);
-    const cardSales = closureTransactions.filter(t => t.paymentMethod === 'card').reduce((sum, t) => sum + t.totalAmount, 0);
+    const cardSplit = cardSalesByApplication(closureTransactions);
 
     const formatPrice = (p: number) => p.toFixed(2).replace('.', ',') + ' €';
     const formatDateTime = (d: Date) => new Date(d).toLocaleString('fr-FR');
@@ -344,7 +364,9 @@ export function generateClosureReport(closure: CashClosure, transactions: Transa
                     { label: 'Chiffre d\'affaires', value: formatPrice(totalSales) },
                     { label: 'Transactions', value: String(closureTransactions.length) },
                     { label: 'Espèces', value: formatPrice(cashSales) },
-                    { label: 'Carte', value: formatPrice(cardSales) },
+                    { label: 'Carte bancaire', value: formatPrice(cardSplit.bankCard) },
+                    { label: 'Titres-restaurant', value: formatPrice(cardSplit.mealVoucher) },
+                    { label: 'Chèques-Vacances (ANCV)', value: formatPrice(cardSplit.holidayVoucher) },
                 ],
             },
             {